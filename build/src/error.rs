@@ -0,0 +1,140 @@
+//! This module contains types and functions related to public-facing errors.
+
+use std::{error::Error as StdError, fmt, io, path::PathBuf};
+
+/// Represents an error that can occur while compiling a pattern manifest.
+///
+/// The message of this error is intended to be shown directly to the user when
+/// a build script fails, so it always names the file or regular expression
+/// that caused it. When a regular expression fails to compile, the
+/// [`wahgex::BuildError`] is available as the [source][StdError::source] of
+/// this error.
+#[derive(Debug)]
+pub struct BuildError {
+    kind: Box<BuildErrorKind>,
+}
+
+impl BuildError {
+    pub(crate) fn io(path: impl Into<PathBuf>, err: io::Error) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::Io {
+                path: path.into(),
+                err,
+            }),
+        }
+    }
+
+    pub(crate) fn manifest(path: impl Into<PathBuf>, err: toml::de::Error) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::Manifest {
+                path: path.into(),
+                err,
+            }),
+        }
+    }
+
+    pub(crate) fn build(name: impl Into<String>, err: wahgex::BuildError) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::Build {
+                name: name.into(),
+                err,
+            }),
+        }
+    }
+
+    pub(crate) fn invalid_name(name: impl Into<String>) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::InvalidName { name: name.into() }),
+        }
+    }
+
+    pub(crate) fn duplicate_name(name: impl Into<String>) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::DuplicateName { name: name.into() }),
+        }
+    }
+
+    pub(crate) fn missing_out_dir() -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::MissingOutDir),
+        }
+    }
+
+    /// Returns the name of the regular expression that failed to compile, if
+    /// the error was caused by one.
+    pub fn regex_name(&self) -> Option<&str> {
+        match &*self.kind {
+            BuildErrorKind::Build { name, .. }
+            | BuildErrorKind::InvalidName { name }
+            | BuildErrorKind::DuplicateName { name } => Some(name),
+            BuildErrorKind::Io { .. }
+            | BuildErrorKind::Manifest { .. }
+            | BuildErrorKind::MissingOutDir => None,
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.kind {
+            BuildErrorKind::Io { path, err } => {
+                write!(f, "failed to access `{}`: {err}", path.display())
+            },
+            BuildErrorKind::Manifest { path, err } => {
+                write!(f, "invalid pattern manifest `{}`: {err}", path.display())
+            },
+            BuildErrorKind::Build { name, err } => {
+                write!(f, "failed to compile regex `{name}`: {err}")
+            },
+            BuildErrorKind::InvalidName { name } => write!(
+                f,
+                "regex name `{name}` must be a valid Rust identifier made of ASCII letters, \
+                 digits, and underscores"
+            ),
+            BuildErrorKind::DuplicateName { name } => {
+                write!(f, "regex name `{name}` is used more than once")
+            },
+            BuildErrorKind::MissingOutDir => f.write_str(
+                "no output directory was configured and the `OUT_DIR` environment variable is not \
+                 set",
+            ),
+        }
+    }
+}
+
+impl StdError for BuildError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &*self.kind {
+            BuildErrorKind::Io { err, .. } => Some(err),
+            BuildErrorKind::Manifest { err, .. } => Some(err),
+            BuildErrorKind::Build { err, .. } => Some(err),
+            BuildErrorKind::InvalidName { .. }
+            | BuildErrorKind::DuplicateName { .. }
+            | BuildErrorKind::MissingOutDir => None,
+        }
+    }
+}
+
+/// Represents the specific kind of an [`BuildError`].
+#[derive(Debug)]
+enum BuildErrorKind {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Manifest {
+        path: PathBuf,
+        err: toml::de::Error,
+    },
+    Build {
+        name: String,
+        err: wahgex::BuildError,
+    },
+    InvalidName {
+        name: String,
+    },
+    DuplicateName {
+        name: String,
+    },
+    MissingOutDir,
+}
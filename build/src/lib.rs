@@ -0,0 +1,301 @@
+//! `wahgex-build` compiles a manifest of regular expressions into WebAssembly
+//! modules from a build script.
+//!
+//! The manifest is a TOML file with one `[[regex]]` table per module:
+//!
+//! ```toml
+//! [[regex]]
+//! name = "date"
+//! patterns = ['\d{4}-\d{2}-\d{2}']
+//!
+//! [[regex]]
+//! name = "keyword"
+//! patterns = ["fn", "let"]
+//! syntax = { case_insensitive = true }
+//! config = { include_names = true }
+//! ```
+//!
+//! The `syntax` and `config` tables accept the same options as
+//! [`RegexSyntaxConfig`] and [`Config`] respectively.
+//!
+//! Each entry is written to `<name>.wasm` in the output directory, and a Rust
+//! module is generated that exposes them as `static` [`RegexBytecode`]
+//! values. From a `build.rs`:
+//!
+//! ```no_run
+//! wahgex_build::Build::new().compile("regexes.toml").unwrap();
+//! ```
+//!
+//! And then in the crate:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/wahgex_regexes.rs"));
+//!
+//! let regex = wahgex::engines::wasmi::Regex::new(&DATE)?;
+//! ```
+//!
+//! [`RegexBytecode`]: wahgex::RegexBytecode
+
+#![deny(missing_docs, missing_debug_implementations)]
+
+use std::{
+    collections::HashSet,
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use wahgex::{Builder, Config, RegexSyntaxConfig};
+
+pub use crate::error::BuildError;
+use crate::manifest::Manifest;
+
+mod error;
+mod manifest;
+
+/// The default file name of the generated Rust module.
+pub const DEFAULT_MODULE_FILE_NAME: &str = "wahgex_regexes.rs";
+
+/// A builder for compiling a pattern manifest into WebAssembly modules.
+#[derive(Debug, Clone, Default)]
+pub struct Build {
+    out_dir: Option<PathBuf>,
+    module_file_name: Option<String>,
+    config: Config,
+    syntax: RegexSyntaxConfig,
+}
+
+impl Build {
+    /// Creates a new builder with its default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures the directory that the modules are written to.
+    ///
+    /// This defaults to the `OUT_DIR` environment variable, which Cargo sets
+    /// for build scripts.
+    pub fn out_dir(mut self, out_dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// Configures the file name of the generated Rust module.
+    ///
+    /// This defaults to [`DEFAULT_MODULE_FILE_NAME`].
+    pub fn module_file_name(mut self, module_file_name: impl Into<String>) -> Self {
+        self.module_file_name = Some(module_file_name.into());
+        self
+    }
+
+    /// Configures the [`Config`] used for every entry of the manifest.
+    ///
+    /// Options set in the `config` table of an entry take precedence.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Configures the [`RegexSyntaxConfig`] used for every entry of the
+    /// manifest.
+    ///
+    /// Options set in the `syntax` table of an entry take precedence.
+    pub fn syntax(mut self, syntax: RegexSyntaxConfig) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Compiles every entry of the manifest at `manifest_path`, and returns
+    /// the path of the generated Rust module.
+    ///
+    /// This also instructs Cargo to re-run the build script when the manifest
+    /// changes.
+    pub fn compile(&self, manifest_path: impl AsRef<Path>) -> Result<PathBuf, BuildError> {
+        let manifest_path = manifest_path.as_ref();
+        println!("cargo::rerun-if-changed={}", manifest_path.display());
+
+        let contents =
+            fs::read_to_string(manifest_path).map_err(|err| BuildError::io(manifest_path, err))?;
+        let manifest: Manifest =
+            toml::from_str(&contents).map_err(|err| BuildError::manifest(manifest_path, err))?;
+
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or_else(BuildError::missing_out_dir)?,
+        };
+        fs::create_dir_all(&out_dir).map_err(|err| BuildError::io(&out_dir, err))?;
+
+        let mut module = format!(
+            "// @generated by wahgex-build from `{}`\n",
+            manifest_path.display()
+        );
+        let mut static_names = HashSet::new();
+        for entry in &manifest.regexes {
+            let static_name = static_name(&entry.name)?;
+            if !static_names.insert(static_name.clone()) {
+                return Err(BuildError::duplicate_name(&entry.name));
+            }
+
+            let mut builder = Builder::new();
+            builder
+                .configure(entry.config.apply(self.config))
+                .syntax(entry.syntax.apply(self.syntax));
+            let (bytecode, _) = builder
+                .build_many(&entry.patterns)
+                .map_err(|err| BuildError::build(&entry.name, err))?;
+
+            let wasm_path = out_dir.join(format!("{}.wasm", entry.name));
+            fs::write(&wasm_path, bytecode.as_ref())
+                .map_err(|err| BuildError::io(&wasm_path, err))?;
+
+            writeln!(
+                module,
+                "\n/// Compiled from the `{}` entry of the pattern manifest.",
+                entry.name
+            )
+            .and_then(|()| {
+                writeln!(
+                    module,
+                    "pub static {static_name}: ::wahgex::RegexBytecode =\n    \
+                     ::wahgex::RegexBytecode::from_static_bytes_unchecked(include_bytes!({:?}));",
+                    wasm_path.display().to_string()
+                )
+            })
+            .expect("writing to a string cannot fail");
+        }
+
+        let module_path = out_dir.join(
+            self.module_file_name
+                .as_deref()
+                .unwrap_or(DEFAULT_MODULE_FILE_NAME),
+        );
+        fs::write(&module_path, module).map_err(|err| BuildError::io(&module_path, err))?;
+
+        Ok(module_path)
+    }
+}
+
+/// Returns the name of the `static` generated for the regex with the given
+/// name, or an error if the name would not be a valid identifier.
+fn static_name(name: &str) -> Result<String, BuildError> {
+    let mut chars = name.chars();
+    let is_valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_";
+    if !is_valid {
+        return Err(BuildError::invalid_name(name));
+    }
+
+    Ok(name.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use wahgex::{RegexContext, engines::wasmi::Regex};
+
+    use super::*;
+
+    /// Writes the manifest to a fresh directory, and returns the path of the
+    /// manifest and the output directory.
+    fn setup(test_name: &str, manifest: &str) -> (PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("wahgex-build-{test_name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let manifest_path = dir.join("regexes.toml");
+        fs::write(&manifest_path, manifest).unwrap();
+        (manifest_path, dir.join("out"))
+    }
+
+    #[test]
+    fn compile_manifest() {
+        let (manifest_path, out_dir) = setup(
+            "compile_manifest",
+            r#"
+            [[regex]]
+            name = "date"
+            patterns = ['\d{4}-\d{2}-\d{2}']
+
+            [[regex]]
+            name = "keyword"
+            patterns = ["fn", "let"]
+            syntax = { case_insensitive = true }
+            config = { include_names = true }
+            "#,
+        );
+
+        let module_path = Build::new()
+            .out_dir(&out_dir)
+            .compile(&manifest_path)
+            .unwrap();
+
+        let module = fs::read_to_string(module_path).unwrap();
+        assert!(module.contains("pub static DATE: ::wahgex::RegexBytecode"));
+        assert!(module.contains("pub static KEYWORD: ::wahgex::RegexBytecode"));
+
+        let bytecode =
+            wahgex::RegexBytecode::from_bytes(fs::read(out_dir.join("keyword.wasm")).unwrap())
+                .unwrap();
+        let context = RegexContext::from_bytecode(&bytecode).unwrap();
+        assert_eq!(context.pattern_len(), 2);
+        assert!(context.config.get_include_names());
+
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.is_match("LET x = 1;".into()));
+        assert!(!regex.is_match("const x = 1;".into()));
+    }
+
+    #[test]
+    fn build_error_names_regex() {
+        let (manifest_path, out_dir) = setup(
+            "build_error_names_regex",
+            r#"
+            [[regex]]
+            name = "unclosed"
+            patterns = ["(abc"]
+            "#,
+        );
+
+        let err = Build::new()
+            .out_dir(out_dir)
+            .compile(manifest_path)
+            .unwrap_err();
+        assert_eq!(err.regex_name(), Some("unclosed"));
+        assert!(
+            err.to_string()
+                .starts_with("failed to compile regex `unclosed`:"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn invalid_and_duplicate_names() {
+        assert!(static_name("date_2").is_ok());
+        assert!(static_name("2date").is_err());
+        assert!(static_name("da-te").is_err());
+        assert!(static_name("_").is_err());
+        assert!(static_name("").is_err());
+
+        let (manifest_path, out_dir) = setup(
+            "invalid_and_duplicate_names",
+            r#"
+            [[regex]]
+            name = "date"
+            patterns = ["a"]
+
+            [[regex]]
+            name = "DATE"
+            patterns = ["b"]
+            "#,
+        );
+        let err = Build::new()
+            .out_dir(out_dir)
+            .compile(manifest_path)
+            .unwrap_err();
+        assert_eq!(err.regex_name(), Some("DATE"));
+    }
+}
@@ -0,0 +1,137 @@
+//! This module contains the types that a pattern manifest is deserialized
+//! into.
+
+use serde::Deserialize;
+use wahgex::{Config, RegexSyntaxConfig};
+
+/// The top-level structure of a pattern manifest file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The regular expressions to compile, each into their own module.
+    #[serde(default, rename = "regex")]
+    pub regexes: Vec<RegexEntry>,
+}
+
+/// A single named regular expression in the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegexEntry {
+    /// The name of the regular expression, used for the output file and the
+    /// generated `static`.
+    pub name: String,
+    /// The patterns to compile into a single module.
+    pub patterns: Vec<String>,
+    /// Options for parsing the patterns.
+    #[serde(default)]
+    pub syntax: SyntaxOptions,
+    /// Options for compiling the module.
+    #[serde(default)]
+    pub config: ConfigOptions,
+}
+
+/// Mirrors the options of [`RegexSyntaxConfig`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyntaxOptions {
+    case_insensitive: Option<bool>,
+    multi_line: Option<bool>,
+    dot_matches_new_line: Option<bool>,
+    crlf: Option<bool>,
+    swap_greed: Option<bool>,
+    ignore_whitespace: Option<bool>,
+    unicode: Option<bool>,
+    utf8: Option<bool>,
+    octal: Option<bool>,
+    nest_limit: Option<u32>,
+}
+
+impl SyntaxOptions {
+    /// Applies the options that were set on top of the given config.
+    pub fn apply(&self, mut syntax: RegexSyntaxConfig) -> RegexSyntaxConfig {
+        if let Some(yes) = self.case_insensitive {
+            syntax = syntax.case_insensitive(yes);
+        }
+        if let Some(yes) = self.multi_line {
+            syntax = syntax.multi_line(yes);
+        }
+        if let Some(yes) = self.dot_matches_new_line {
+            syntax = syntax.dot_matches_new_line(yes);
+        }
+        if let Some(yes) = self.crlf {
+            syntax = syntax.crlf(yes);
+        }
+        if let Some(yes) = self.swap_greed {
+            syntax = syntax.swap_greed(yes);
+        }
+        if let Some(yes) = self.ignore_whitespace {
+            syntax = syntax.ignore_whitespace(yes);
+        }
+        if let Some(yes) = self.unicode {
+            syntax = syntax.unicode(yes);
+        }
+        if let Some(yes) = self.utf8 {
+            syntax = syntax.utf8(yes);
+        }
+        if let Some(yes) = self.octal {
+            syntax = syntax.octal(yes);
+        }
+        if let Some(limit) = self.nest_limit {
+            syntax = syntax.nest_limit(limit);
+        }
+        syntax
+    }
+}
+
+/// Mirrors the options of [`Config`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOptions {
+    include_names: Option<bool>,
+    compact_data_section: Option<bool>,
+    step_counter: Option<bool>,
+    include_metadata: Option<bool>,
+    include_patterns: Option<bool>,
+    max_haystack_len: Option<usize>,
+    wasi_grep: Option<bool>,
+    trace: Option<bool>,
+    inline_epsilon_closure_threshold: Option<usize>,
+    branch_hints: Option<bool>,
+}
+
+impl ConfigOptions {
+    /// Applies the options that were set on top of the given config.
+    pub fn apply(&self, mut config: Config) -> Config {
+        if let Some(yes) = self.include_names {
+            config = config.include_names(yes);
+        }
+        if let Some(yes) = self.compact_data_section {
+            config = config.compact_data_section(yes);
+        }
+        if let Some(yes) = self.step_counter {
+            config = config.step_counter(yes);
+        }
+        if let Some(yes) = self.include_metadata {
+            config = config.include_metadata(yes);
+        }
+        if let Some(yes) = self.include_patterns {
+            config = config.include_patterns(yes);
+        }
+        if let Some(len) = self.max_haystack_len {
+            config = config.max_haystack_len(len);
+        }
+        if let Some(yes) = self.wasi_grep {
+            config = config.wasi_grep(yes);
+        }
+        if let Some(yes) = self.trace {
+            config = config.trace(yes);
+        }
+        if let Some(threshold) = self.inline_epsilon_closure_threshold {
+            config = config.inline_epsilon_closure_threshold(threshold);
+        }
+        if let Some(yes) = self.branch_hints {
+            config = config.branch_hints(yes);
+        }
+        config
+    }
+}
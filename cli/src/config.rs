@@ -0,0 +1,284 @@
+//! The arguments that are mapped onto the syntax, NFA, and module
+//! configuration of a [`Builder`].
+
+use std::error::Error;
+
+use clap::Args;
+use wahgex::{
+    Builder, Config, LookMatcher, RegexBytecode, RegexContext, RegexNFAConfig, RegexSyntaxConfig,
+};
+
+/// Options that are mapped onto the configuration of the [`Builder`].
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    #[command(flatten)]
+    syntax: SyntaxArgs,
+    #[command(flatten)]
+    nfa: NfaArgs,
+    #[command(flatten)]
+    module: ModuleArgs,
+}
+
+/// Options that are mapped onto the [`RegexSyntaxConfig`].
+#[derive(Debug, Args)]
+#[command(next_help_heading = "Syntax options")]
+struct SyntaxArgs {
+    /// Match letters case insensitively.
+    #[arg(long)]
+    case_insensitive: bool,
+    /// Make `^` and `$` match at the start and end of lines.
+    #[arg(long)]
+    multi_line: bool,
+    /// Make `.` match the line terminator as well.
+    #[arg(long)]
+    dot_matches_new_line: bool,
+    /// Treat `\r` as a line terminator as well, in multi-line mode.
+    #[arg(long)]
+    crlf: bool,
+    /// The line terminator used by `^`, `$` and `.`, as a character or a
+    /// `0x` prefixed byte.
+    #[arg(long, value_name = "BYTE", value_parser = parse_byte)]
+    line_terminator: Option<u8>,
+    /// Make repetitions lazy by default, and greedy when followed by `?`.
+    #[arg(long)]
+    swap_greed: bool,
+    /// Ignore whitespace and allow `#` comments in the pattern.
+    #[arg(long)]
+    ignore_whitespace: bool,
+    /// Disable Unicode mode, as if the pattern started with `(?-u)`.
+    #[arg(long)]
+    no_unicode: bool,
+    /// Allow the pattern to match invalid UTF-8, such as with `(?-u:\xFF)`.
+    #[arg(long)]
+    no_utf8_syntax: bool,
+    /// The maximum nesting depth of the pattern.
+    #[arg(long, value_name = "DEPTH")]
+    nest_limit: Option<u32>,
+    /// Allow octal escapes like `\0`.
+    #[arg(long)]
+    octal: bool,
+}
+
+/// Options that are mapped onto the [`RegexNFAConfig`].
+#[derive(Debug, Args)]
+#[command(next_help_heading = "NFA options")]
+struct NfaArgs {
+    /// Allow empty matches to split UTF-8 encoded codepoints.
+    #[arg(long)]
+    no_utf8_nfa: bool,
+    /// The line terminator used by the `(?m:^)` and `(?m:$)` assertions, as a
+    /// character or a `0x` prefixed byte.
+    #[arg(long, value_name = "BYTE", value_parser = parse_byte)]
+    look_line_terminator: Option<u8>,
+    /// Shrink the NFA, which is slow for large Unicode classes.
+    #[arg(long)]
+    shrink: bool,
+}
+
+/// Options that are mapped onto the [`Config`] of the module.
+#[derive(Debug, Args)]
+#[command(next_help_heading = "Module options")]
+struct ModuleArgs {
+    /// Include a name section in the module.
+    #[arg(long)]
+    names: bool,
+    /// Don't merge adjacent data segments.
+    #[arg(long)]
+    no_compact: bool,
+    /// Count the steps of each search against a budget set by the host.
+    #[arg(long)]
+    step_counter: bool,
+    /// The maximum length of haystack that the module will accept.
+    #[arg(long, value_name = "BYTES")]
+    max_haystack_len: Option<usize>,
+    /// Don't include the `wahgex` metadata section.
+    #[arg(long)]
+    no_metadata: bool,
+    /// Include the pattern strings in the `wahgex` metadata section.
+    #[arg(long)]
+    include_patterns: bool,
+    /// Compile a standalone WASI command that filters lines of stdin.
+    #[arg(long)]
+    wasi_grep: bool,
+    /// Report the active NFA states at every offset to a host callback.
+    #[arg(long)]
+    trace: bool,
+    /// Inline epsilon closures of up to this many states into transitions.
+    #[arg(long, value_name = "STATES")]
+    inline_epsilon_closure_threshold: Option<usize>,
+    /// Hint to the engine which branches of the search loop are rarely taken.
+    #[arg(long)]
+    branch_hints: bool,
+}
+
+impl ConfigArgs {
+    pub fn syntax_config(&self) -> RegexSyntaxConfig {
+        let syntax = &self.syntax;
+        let mut config = RegexSyntaxConfig::new()
+            .case_insensitive(syntax.case_insensitive)
+            .multi_line(syntax.multi_line)
+            .dot_matches_new_line(syntax.dot_matches_new_line)
+            .crlf(syntax.crlf)
+            .swap_greed(syntax.swap_greed)
+            .ignore_whitespace(syntax.ignore_whitespace)
+            .unicode(!syntax.no_unicode)
+            .utf8(!syntax.no_utf8_syntax)
+            .octal(syntax.octal);
+        if let Some(line_terminator) = syntax.line_terminator {
+            config = config.line_terminator(line_terminator);
+        }
+        if let Some(nest_limit) = syntax.nest_limit {
+            config = config.nest_limit(nest_limit);
+        }
+        config
+    }
+
+    pub fn nfa_config(&self) -> RegexNFAConfig {
+        let mut look_matcher = LookMatcher::new();
+        if let Some(line_terminator) = self.nfa.look_line_terminator {
+            look_matcher.set_line_terminator(line_terminator);
+        }
+        RegexNFAConfig::new()
+            .utf8(!self.nfa.no_utf8_nfa)
+            .look_matcher(look_matcher)
+            .shrink(self.nfa.shrink)
+    }
+
+    pub fn to_config(&self) -> Config {
+        let module = &self.module;
+        let mut config = Config::new()
+            .include_names(module.names)
+            .compact_data_section(!module.no_compact)
+            .step_counter(module.step_counter)
+            .include_metadata(!module.no_metadata)
+            .include_patterns(module.include_patterns)
+            .wasi_grep(module.wasi_grep)
+            .trace(module.trace)
+            .branch_hints(module.branch_hints);
+        if let Some(max_haystack_len) = module.max_haystack_len {
+            config = config.max_haystack_len(max_haystack_len);
+        }
+        if let Some(threshold) = module.inline_epsilon_closure_threshold {
+            config = config.inline_epsilon_closure_threshold(threshold);
+        }
+        config
+    }
+
+    pub fn builder(&self) -> Builder {
+        let mut builder = Builder::new();
+        builder
+            .configure(self.to_config())
+            .syntax(self.syntax_config())
+            .thompson(self.nfa_config());
+        builder
+    }
+
+    pub fn build<P: AsRef<str>>(
+        &self,
+        patterns: &[P],
+    ) -> Result<(RegexBytecode, RegexContext), Box<dyn Error>> {
+        Ok(self.builder().build_many(patterns)?)
+    }
+
+    /// Returns the name and value of every option, after applying the
+    /// defaults.
+    pub fn effective_config(&self) -> Vec<(&'static str, String)> {
+        let syntax = self.syntax_config();
+        let nfa = self.nfa_config();
+        let config = self.to_config();
+        vec![
+            (
+                "syntax.case_insensitive",
+                syntax.get_case_insensitive().to_string(),
+            ),
+            ("syntax.multi_line", syntax.get_multi_line().to_string()),
+            (
+                "syntax.dot_matches_new_line",
+                syntax.get_dot_matches_new_line().to_string(),
+            ),
+            ("syntax.crlf", syntax.get_crlf().to_string()),
+            (
+                "syntax.line_terminator",
+                format_byte(syntax.get_line_terminator()),
+            ),
+            ("syntax.swap_greed", syntax.get_swap_greed().to_string()),
+            (
+                "syntax.ignore_whitespace",
+                syntax.get_ignore_whitespace().to_string(),
+            ),
+            ("syntax.unicode", syntax.get_unicode().to_string()),
+            ("syntax.utf8", syntax.get_utf8().to_string()),
+            ("syntax.nest_limit", syntax.get_nest_limit().to_string()),
+            ("syntax.octal", syntax.get_octal().to_string()),
+            ("nfa.utf8", nfa.get_utf8().to_string()),
+            (
+                "nfa.look_line_terminator",
+                format_byte(nfa.get_look_matcher().get_line_terminator()),
+            ),
+            ("nfa.shrink", nfa.get_shrink().to_string()),
+            (
+                "config.include_names",
+                config.get_include_names().to_string(),
+            ),
+            (
+                "config.compact_data_section",
+                config.get_compact_data_section().to_string(),
+            ),
+            ("config.step_counter", config.get_step_counter().to_string()),
+            (
+                "config.max_haystack_len",
+                config
+                    .get_max_haystack_len()
+                    .map_or_else(|| "none".into(), |len| len.to_string()),
+            ),
+            (
+                "config.include_metadata",
+                config.get_include_metadata().to_string(),
+            ),
+            (
+                "config.include_patterns",
+                config.get_include_patterns().to_string(),
+            ),
+            ("config.wasi_grep", config.get_wasi_grep().to_string()),
+            ("config.trace", config.get_trace().to_string()),
+            (
+                "config.inline_epsilon_closure_threshold",
+                config.get_inline_epsilon_closure_threshold().to_string(),
+            ),
+            ("config.branch_hints", config.get_branch_hints().to_string()),
+        ]
+    }
+}
+
+/// Parses a byte given as a single ASCII character, or as a `0x` prefixed
+/// hexadecimal number.
+fn parse_byte(value: &str) -> Result<u8, String> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u8::from_str_radix(hex, 16).map_err(|err| format!("invalid byte `{value}`: {err}"));
+    }
+    match value.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!(
+            "invalid byte `{value}`: expected a single ASCII character or a `0x` prefixed byte"
+        )),
+    }
+}
+
+fn format_byte(byte: u8) -> String {
+    format!("{:?}", char::from(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_byte_values() {
+        assert_eq!(parse_byte("a"), Ok(b'a'));
+        assert_eq!(parse_byte("0x00"), Ok(0));
+        assert_eq!(parse_byte("0xff"), Ok(0xff));
+        assert!(parse_byte("0x100").is_err());
+        assert!(parse_byte("ab").is_err());
+        assert!(parse_byte("é").is_err());
+    }
+}
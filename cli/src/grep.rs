@@ -0,0 +1,278 @@
+//! The `grep` subcommand, which searches files line by line with a compiled
+//! module.
+//!
+//! The module only reports whether a haystack matches, not where, so
+//! selected lines are printed without highlighting the matches.
+
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Args;
+use regex_automata::nfa::thompson::WhichCaptures;
+use wahgex::{Builder, Input, RegexNFAConfig, RegexSyntaxConfig, engines::wasmi::Regex};
+
+use crate::{ConfigArgs, PatternArgs, read_input};
+
+/// Options that control how the pattern is compiled and how each file is
+/// searched.
+#[derive(Debug, Args)]
+pub struct GrepArgs {
+    #[command(flatten)]
+    pattern: PatternArgs,
+    /// The files to search, or stdin if none are given or the file is `-`.
+    files: Vec<PathBuf>,
+    /// Match case insensitively.
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Only select lines where the pattern matches whole words.
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+    /// Only select lines where the pattern matches the whole line.
+    #[arg(short = 'x', long)]
+    line_regexp: bool,
+    /// Select the lines that don't match.
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+    /// Print the number of selected lines in each file, instead of the lines.
+    #[arg(short = 'c', long)]
+    count: bool,
+    /// Prefix each selected line with its line number.
+    #[arg(short = 'n', long, conflicts_with = "multiline")]
+    line_number: bool,
+    /// Search each file as a single haystack, where `^` and `$` match at line
+    /// boundaries, and print the names of the selected files.
+    #[arg(long)]
+    multiline: bool,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+impl GrepArgs {
+    /// Returns the patterns with the `-w` and `-x` restrictions applied, and
+    /// the files to search.
+    fn patterns_and_files(&self) -> Result<(Vec<String>, Vec<PathBuf>), Box<dyn Error>> {
+        let (patterns, files) = self.pattern.patterns_and_files(&self.files)?;
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| {
+                if self.line_regexp {
+                    if self.multiline {
+                        format!("(?m:^)(?:{pattern})(?m:$)")
+                    } else {
+                        format!(r"\A(?:{pattern})\z")
+                    }
+                } else if self.word_regexp {
+                    format!(r"\b{{start-half}}(?:{pattern})\b{{end-half}}")
+                } else {
+                    pattern
+                }
+            })
+            .collect();
+        Ok((patterns, files))
+    }
+
+    fn syntax_config(&self) -> RegexSyntaxConfig {
+        let config = self.config.syntax_config();
+        config
+            .case_insensitive(self.ignore_case || config.get_case_insensitive())
+            .multi_line(self.multiline || config.get_multi_line())
+    }
+
+    fn nfa_config(&self) -> RegexNFAConfig {
+        // Only `is_match` is available, so capture groups are never reported
+        self.config.nfa_config().which_captures(WhichCaptures::None)
+    }
+}
+
+/// Compiles the patterns once and searches each of the files with them.
+///
+/// The exit code is 0 if any line was selected, and 1 if none were.
+pub fn run(args: GrepArgs) -> Result<ExitCode, Box<dyn Error>> {
+    let (patterns, files) = args.patterns_and_files()?;
+    let (bytecode, _) = Builder::new()
+        .configure(args.config.to_config())
+        .syntax(args.syntax_config())
+        .thompson(args.nfa_config())
+        .build_many(&patterns)?;
+    let mut regex = Regex::new(&bytecode)?;
+
+    let files = if files.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        files
+    };
+    let with_file_name = files.len() > 1;
+
+    let mut out = io::stdout().lock();
+    let mut any_selected = false;
+    for file in &files {
+        let haystack = read_input(file)?;
+        let prefix = with_file_name.then_some(file.as_path());
+        let selected = if args.multiline {
+            grep_file(&mut regex, &args, &haystack, file, &mut out)?
+        } else {
+            grep_lines(&mut regex, &args, &haystack, prefix, &mut out)?
+        };
+        any_selected |= selected > 0;
+    }
+    out.flush()?;
+
+    Ok(if any_selected {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Searches each line of the haystack separately, writing the selected lines
+/// or their count to `out`. Returns the number of selected lines.
+fn grep_lines(
+    regex: &mut Regex,
+    args: &GrepArgs,
+    haystack: &[u8],
+    prefix: Option<&Path>,
+    out: &mut impl Write,
+) -> Result<u64, Box<dyn Error>> {
+    // An empty haystack has no lines, and a trailing line terminator doesn't
+    // start another line
+    let lines = (!haystack.is_empty())
+        .then(|| haystack.strip_suffix(b"\n").unwrap_or(haystack))
+        .into_iter()
+        .flat_map(|haystack| haystack.split(|&b| b == b'\n'));
+
+    let mut selected = 0;
+    for (line_idx, line) in lines.enumerate() {
+        if regex.try_is_match(Input::new(line))? == args.invert_match {
+            continue;
+        }
+
+        selected += 1;
+        if args.count {
+            continue;
+        }
+        if let Some(prefix) = prefix {
+            write!(out, "{}:", prefix.display())?;
+        }
+        if args.line_number {
+            write!(out, "{}:", line_idx + 1)?;
+        }
+        out.write_all(line)?;
+        out.write_all(b"\n")?;
+    }
+
+    if args.count {
+        if let Some(prefix) = prefix {
+            write!(out, "{}:", prefix.display())?;
+        }
+        writeln!(out, "{selected}")?;
+    }
+    Ok(selected)
+}
+
+/// Searches the whole haystack at once, writing the path or the count to
+/// `out` if it was selected. Returns 1 if the file was selected, and 0
+/// otherwise.
+fn grep_file(
+    regex: &mut Regex,
+    args: &GrepArgs,
+    haystack: &[u8],
+    path: &Path,
+    out: &mut impl Write,
+) -> Result<u64, Box<dyn Error>> {
+    let selected = u64::from(regex.try_is_match(Input::new(haystack))? != args.invert_match);
+
+    if args.count {
+        writeln!(out, "{}:{selected}", path.display())?;
+    } else if selected > 0 {
+        writeln!(out, "{}", path.display())?;
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        args: GrepArgs,
+    }
+
+    fn grep(args: &[&str], haystack: &str) -> (u64, String) {
+        let args = TestCli::try_parse_from(["grep"].iter().chain(args))
+            .unwrap()
+            .args;
+        let (bytecode, _) = Builder::new()
+            .configure(args.config.to_config())
+            .syntax(args.syntax_config())
+            .thompson(args.nfa_config())
+            .build_many(&args.patterns_and_files().unwrap().0)
+            .unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+
+        let mut out = Vec::new();
+        let selected = if args.multiline {
+            grep_file(
+                &mut regex,
+                &args,
+                haystack.as_bytes(),
+                Path::new("file"),
+                &mut out,
+            )
+        } else {
+            grep_lines(&mut regex, &args, haystack.as_bytes(), None, &mut out)
+        }
+        .unwrap();
+        (selected, String::from_utf8(out).unwrap())
+    }
+
+    const HAYSTACK: &str = "foo bar\nFoo\nfoobar\n\nbar foo";
+
+    #[test]
+    fn select_lines() {
+        assert_eq!(
+            grep(&["foo"], HAYSTACK),
+            (3, "foo bar\nfoobar\nbar foo\n".into())
+        );
+        assert_eq!(
+            grep(&["-i", "^foo"], HAYSTACK),
+            (3, "foo bar\nFoo\nfoobar\n".into())
+        );
+        assert_eq!(
+            grep(&["-w", "foo"], HAYSTACK),
+            (2, "foo bar\nbar foo\n".into())
+        );
+        assert_eq!(grep(&["-x", "-i", "foo"], HAYSTACK), (1, "Foo\n".into()));
+        assert_eq!(grep(&["-vn", "foo"], HAYSTACK), (2, "2:Foo\n4:\n".into()));
+        assert_eq!(grep(&["-c", "bar"], HAYSTACK), (3, "3\n".into()));
+        assert_eq!(grep(&["-c", "foo"], ""), (0, "0\n".into()));
+        assert_eq!(
+            grep(&["-x", "-e", "Foo", "-e", "foo.*"], HAYSTACK),
+            (3, "foo bar\nFoo\nfoobar\n".into())
+        );
+    }
+
+    #[test]
+    fn select_file() {
+        assert_eq!(
+            grep(&["--multiline", "^Foo$"], HAYSTACK),
+            (1, "file\n".into())
+        );
+        assert_eq!(
+            grep(&["--multiline", "-x", "Foo"], HAYSTACK),
+            (1, "file\n".into())
+        );
+        assert_eq!(grep(&["--multiline", "^Foo$"], "Foo bar"), (0, "".into()));
+        assert_eq!(
+            grep(&["--multiline", "-vc", "baz"], HAYSTACK),
+            (1, "file:1\n".into())
+        );
+    }
+}
@@ -1,60 +1,376 @@
-use std::{env, iter};
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fs,
+    io::{self, Read},
+    iter,
+    path::PathBuf,
+    process::ExitCode,
+};
 
-use wahgex::{Builder, Config, RegexBytecode, RegexContext};
+use clap::{Parser, Subcommand};
+use regex_automata::{nfa::thompson::pikevm::PikeVM, util::primitives::StateID};
+use wahgex::{Config, Input, RegexBytecode, RegexContext, engines::wasmi::Regex, inspect};
 
-fn main() {
-    let input = env::args().nth(1).unwrap();
+use crate::{config::ConfigArgs, patterns::PatternArgs, trace::ReferenceSets};
 
-    let (bytecode, context) = Builder::new()
-        .configure(
-            Config::new()
-                .include_names(true)
-                .compact_data_section(false),
-        )
-        .build(&input)
-        .unwrap();
+mod config;
+mod grep;
+mod patterns;
+mod trace;
 
-    eprint_input_info(&input, &bytecode, &context);
+/// Compile regular expressions into WebAssembly modules, and inspect or run
+/// them.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compile the patterns and write the WASM module to a file.
+    Compile {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        /// The file to write the module to.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Compile the patterns and print the module in the WebAssembly text
+    /// format.
+    Wat {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Compile the patterns and print information about their NFA and module,
+    /// along with the effective configuration and the size of each part of
+    /// the module.
+    Stats {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Print the NFA of the patterns as a Graphviz DOT graph, annotated with
+    /// the functions and lookup tables generated for each state.
+    Graph {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Compile the patterns and check which of them match each of the inputs.
+    ///
+    /// Each file is searched as a whole. The exit code is 0 if any input
+    /// matched, and 1 if none did.
+    Match {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        /// The files to search, or stdin if none are given or the file is `-`.
+        files: Vec<PathBuf>,
+    },
+    /// Compile the patterns once and print the lines of the inputs that match.
+    ///
+    /// The exit code is 0 if any line was selected, and 1 if none were.
+    Grep(grep::GrepArgs),
+    /// Search the input and print the NFA states that are active at every
+    /// offset, as reported by a module compiled with `--trace`.
+    ///
+    /// Next to them are reference states from a simulation of the NFA, which
+    /// follows the same rules as a PikeVM but is separate from it, since the
+    /// PikeVM of `regex-automata` doesn't expose its sets. A difference may
+    /// come from either side. Only the final result is checked against the
+    /// PikeVM itself. Use the `graph` command to see what each state is. The
+    /// exit code is 0 if the input matched, and 1 if it didn't.
+    Trace {
+        #[command(flatten)]
+        pattern: PatternArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        /// The file to search, or stdin if none is given or the file is `-`.
+        #[arg(value_name = "FILE")]
+        input: Option<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli.command) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            let mut previous = err.to_string();
+            let mut source = err.source();
+            while let Some(err) = source {
+                // Wrapped errors often repeat the message of their source
+                let message = err.to_string();
+                if message != previous {
+                    eprintln!("  caused by: {message}");
+                }
+                previous = message;
+                source = err.source();
+            }
+            ExitCode::from(2)
+        },
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, Box<dyn Error>> {
+    match command {
+        Command::Compile {
+            pattern,
+            config,
+            output,
+        } => {
+            let (bytecode, _) = config.build(&pattern.patterns()?)?;
+            fs::write(&output, bytecode.as_ref())
+                .map_err(|err| format!("failed to write `{}`: {err}", output.display()))?;
+        },
+        Command::Wat { pattern, config } => {
+            let (bytecode, _) = config.build(&pattern.patterns()?)?;
+            println!("{}", wasm_print_module(bytecode)?);
+        },
+        Command::Stats { pattern, config } => {
+            let patterns = pattern.patterns()?;
+            let (bytecode, context) = config.build(&patterns)?;
+            print_input_info(&patterns, &bytecode, &context);
+            println!();
+            print_fields(&config.effective_config());
+            println!();
+            print!("{}", inspect::size_report(&bytecode)?);
+        },
+        Command::Graph { pattern, config } => {
+            let (_, context) = config.build(&pattern.patterns()?)?;
+            print!(
+                "{}",
+                inspect::inspect(
+                    context
+                        .nfa
+                        .as_ref()
+                        .expect("contexts built from patterns have an NFA"),
+                    context.config
+                )?
+                .to_dot()
+            );
+        },
+        Command::Match {
+            pattern,
+            config,
+            files,
+        } => {
+            let (patterns, files) = pattern.patterns_and_files(&files)?;
+            let (bytecode, _) = config.build(&patterns)?;
+            let mut regex = Regex::new(&bytecode)?;
+            // The module only reports whether any pattern matched, so each
+            // pattern is also compiled on its own to find which ones did
+            let mut pattern_regexes = if patterns.len() > 1 {
+                patterns
+                    .iter()
+                    .map(|pattern| Ok(Regex::new(&config.build(&[pattern])?.0)?))
+                    .collect::<Result<Vec<_>, Box<dyn Error>>>()?
+            } else {
+                Vec::new()
+            };
+
+            let files = if files.is_empty() {
+                vec![PathBuf::from("-")]
+            } else {
+                files
+            };
+            let mut any_match = false;
+            for file in files {
+                let haystack = read_input(&file)?;
+                let is_match = regex.try_is_match(Input::new(&haystack))?;
+                any_match |= is_match;
+
+                let pattern_ids = if pattern_regexes.is_empty() {
+                    if is_match { vec![0] } else { vec![] }
+                } else {
+                    let mut pattern_ids = Vec::new();
+                    for (pattern_id, regex) in pattern_regexes.iter_mut().enumerate() {
+                        if regex.try_is_match(Input::new(&haystack))? {
+                            pattern_ids.push(pattern_id);
+                        }
+                    }
+                    pattern_ids
+                };
+                if pattern_ids.is_empty() == is_match {
+                    return Err(format!(
+                        "the module reported {}, but the patterns {pattern_ids:?} matched `{}` on \
+                         their own",
+                        if is_match { "a match" } else { "no match" },
+                        file.display()
+                    )
+                    .into());
+                }
+
+                if is_match {
+                    let pattern_ids = pattern_ids
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    println!("{}: match (patterns: {pattern_ids})", file.display());
+                } else {
+                    println!("{}: no match", file.display());
+                }
+            }
 
-    let pretty_wasm = wasm_print_module(bytecode);
+            return Ok(if any_match {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        },
+        Command::Grep(args) => return grep::run(args),
+        Command::Trace {
+            pattern,
+            config,
+            input,
+        } => {
+            let (patterns, files) = pattern.patterns_and_files(input.as_slice())?;
+            let file = match &files[..] {
+                [] => PathBuf::from("-"),
+                [file] => file.clone(),
+                _ => return Err("expected a single file to trace".into()),
+            };
+            let (bytecode, context) = config
+                .builder()
+                .configure(Config::new().trace(true))
+                .build_many(&patterns)?;
+            let nfa = context
+                .nfa
+                .expect("contexts built from patterns have an NFA");
 
-    println!("{pretty_wasm}");
+            let haystack = read_input(&file)?;
+            let mut regex = Regex::new(&bytecode)?;
+            let is_match = regex.try_is_match(Input::new(&haystack))?;
+            let mut reference = ReferenceSets::new(&nfa, &haystack);
+            let mut differing_offsets = Vec::new();
+            for step in regex.trace() {
+                while reference.at_offset() < step.at_offset {
+                    reference.advance();
+                }
+                let byte = match haystack.get(step.at_offset) {
+                    Some(byte) => format!("{:?}", char::from(*byte)),
+                    None => "end".into(),
+                };
+                let states = format_states(&step.states);
+                let expected = format_states(reference.states());
+                let differs = step.states.iter().collect::<BTreeSet<_>>()
+                    != reference.states().iter().collect();
+                if differs {
+                    differing_offsets.push(step.at_offset);
+                }
+                println!(
+                    "{} ({byte}): {states} | reference: {expected}{}",
+                    step.at_offset,
+                    if differs { " (differs)" } else { "" }
+                );
+            }
+            if !differing_offsets.is_empty() {
+                return Err(format!(
+                    "the module's states differ from the reference states at offsets \
+                     {differing_offsets:?}"
+                )
+                .into());
+            }
+
+            let pikevm = PikeVM::new_from_nfa(nfa)?;
+            let expected = pikevm.is_match(&mut pikevm.create_cache(), Input::new(&haystack));
+            if is_match != expected {
+                return Err(format!(
+                    "the module reported {}, but the PikeVM reported {}",
+                    if is_match { "a match" } else { "no match" },
+                    if expected { "a match" } else { "no match" },
+                )
+                .into());
+            }
+            println!("{}", if is_match { "match" } else { "no match" });
+
+            return Ok(if is_match {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        },
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Formats state IDs the way the `graph` command labels them.
+fn format_states<'a>(states: impl IntoIterator<Item = &'a StateID>) -> String {
+    states
+        .into_iter()
+        .map(|sid| format!("s{}", sid.as_usize()))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-fn eprint_input_info(input: &str, bytecode: &RegexBytecode, context: &RegexContext) {
-    eprint_fields(&[
-        ("input", input.into()),
+/// Reads the whole file, or stdin if the path is `-`.
+fn read_input(path: &PathBuf) -> Result<Vec<u8>, Box<dyn Error>> {
+    let contents = if path.as_os_str() == "-" {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents).map(|_| contents)
+    } else {
+        fs::read(path)
+    };
+    Ok(contents.map_err(|err| format!("failed to read `{}`: {err}", path.display()))?)
+}
+
+fn print_input_info(patterns: &[String], bytecode: &RegexBytecode, context: &RegexContext) {
+    let nfa = context
+        .nfa
+        .as_ref()
+        .expect("contexts built from patterns have an NFA");
+    let pattern_names = (0..patterns.len())
+        .map(|pattern_id| format!("pattern {pattern_id}"))
+        .collect::<Vec<_>>();
+    let mut fields = pattern_names
+        .iter()
+        .zip(patterns)
+        .map(|(name, pattern)| (name.as_str(), pattern.clone()))
+        .collect::<Vec<_>>();
+    fields.extend([
         ("module size", bytecode.as_ref().len().to_string()),
-        ("states", context.nfa.states().len().to_string()),
-        ("pattern len", context.nfa.pattern_len().to_string()),
-        ("has capture?", context.nfa.has_capture().to_string()),
-        ("has empty?", context.nfa.has_empty().to_string()),
-        ("is utf8?", context.nfa.is_utf8().to_string()),
-        ("is reverse?", context.nfa.is_reverse().to_string()),
-        ("lookset any", format!("{:?}", context.nfa.look_set_any())),
-        (
-            "lookset prefix any",
-            format!("{:?}", context.nfa.look_set_prefix_any()),
-        ),
+        ("states", nfa.states().len().to_string()),
+        ("pattern len", nfa.pattern_len().to_string()),
+        ("has capture?", nfa.has_capture().to_string()),
+        ("has empty?", nfa.has_empty().to_string()),
+        ("is utf8?", nfa.is_utf8().to_string()),
+        ("is reverse?", nfa.is_reverse().to_string()),
+        ("lookset any", format!("{:?}", nfa.look_set_any())),
         (
             "lookset prefix any",
-            format!("{:?}", context.nfa.look_set_prefix_any()),
+            format!("{:?}", nfa.look_set_prefix_any()),
         ),
     ]);
+    print_fields(&fields);
 }
 
-fn eprint_fields(fields: &[(&str, String)]) {
+fn print_fields(fields: &[(&str, String)]) {
     let max_name_len = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
 
     for (name, value) in fields {
         let pad: String = iter::repeat_n(' ', max_name_len - name.len() + 1).collect();
-        eprintln!("{pad}{name}:  {value}");
+        println!("{pad}{name}:  {value}");
     }
 }
 
-pub fn wasm_print_module(module_bytes: impl AsRef<[u8]>) -> String {
+/// Prints the module in the WebAssembly text format, or returns the
+/// validation error along with the text annotated with byte offsets.
+fn wasm_print_module(module_bytes: impl AsRef<[u8]>) -> Result<String, Box<dyn Error>> {
     let module_bytes = module_bytes.as_ref();
-    let wasm_text = wasmprinter::print_bytes(module_bytes);
     if let Err(err) = wasmparser::validate(module_bytes) {
         let mut wasm_text_with_offsets = String::new();
         let print = wasmprinter::Config::new().print_offsets(true).print(
@@ -62,12 +378,10 @@ pub fn wasm_print_module(module_bytes: impl AsRef<[u8]>) -> String {
             &mut wasmprinter::PrintFmtWrite(&mut wasm_text_with_offsets),
         );
 
-        match print {
-            Ok(()) => {
-                panic!("{err}:\n{wasm_text_with_offsets}")
-            },
-            Err(print_err) => panic!("{err}:\nUnable to print WAT: {print_err}"),
-        }
+        return Err(match print {
+            Ok(()) => format!("{err}:\n{wasm_text_with_offsets}").into(),
+            Err(print_err) => format!("{err}:\nUnable to print WAT: {print_err}").into(),
+        });
     }
-    wasm_text.expect("should be able to print WASM module in WAT format")
+    Ok(wasmprinter::print_bytes(module_bytes)?)
 }
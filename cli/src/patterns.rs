@@ -0,0 +1,132 @@
+//! The arguments used to pass one or more patterns to the subcommands.
+
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::Args;
+
+/// The patterns to compile into a single module.
+///
+/// Patterns given with `-e` come first, followed by the patterns read from
+/// each `-f` file, and are numbered in that order. The positional pattern is
+/// only used if neither is given.
+#[derive(Debug, Args)]
+pub struct PatternArgs {
+    /// The regular expression to compile, unless `-e` or `-f` is given.
+    #[arg(required_unless_present_any = ["regexp", "file"])]
+    pattern: Option<String>,
+    /// A regular expression to compile, which may be repeated to compile
+    /// several patterns into one module.
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERN")]
+    regexp: Vec<String>,
+    /// A file to read the patterns from, one per line.
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    file: Vec<PathBuf>,
+}
+
+impl PatternArgs {
+    /// Returns the patterns, in the order of their pattern IDs.
+    ///
+    /// Returns an error if a positional pattern was given along with `-e` or
+    /// `-f`.
+    pub fn patterns(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        match (&self.pattern, self.has_flags()) {
+            (Some(pattern), true) => {
+                Err(format!("unexpected pattern `{pattern}` given along with `-e` or `-f`").into())
+            },
+            (Some(pattern), false) => Ok(vec![pattern.clone()]),
+            (None, _) => self.flag_patterns(),
+        }
+    }
+
+    /// Returns the patterns, in the order of their pattern IDs, and the files
+    /// to search.
+    ///
+    /// If the patterns were given with `-e` or `-f`, then the positional
+    /// pattern is really the first file, like in `grep`.
+    pub fn patterns_and_files(
+        &self,
+        files: &[PathBuf],
+    ) -> Result<(Vec<String>, Vec<PathBuf>), Box<dyn Error>> {
+        if !self.has_flags() {
+            return Ok((self.patterns()?, files.to_vec()));
+        }
+
+        let files = self
+            .pattern
+            .iter()
+            .map(PathBuf::from)
+            .chain(files.iter().cloned())
+            .collect();
+        Ok((self.flag_patterns()?, files))
+    }
+
+    fn has_flags(&self) -> bool {
+        !self.regexp.is_empty() || !self.file.is_empty()
+    }
+
+    fn flag_patterns(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut patterns = self.regexp.clone();
+        for file in &self.file {
+            let contents = fs::read_to_string(file)
+                .map_err(|err| format!("failed to read `{}`: {err}", file.display()))?;
+            patterns.extend(contents.lines().map(String::from));
+        }
+        Ok(patterns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        patterns: PatternArgs,
+        files: Vec<PathBuf>,
+    }
+
+    fn parse(args: &[&str]) -> Result<(Vec<String>, Vec<PathBuf>), Box<dyn Error>> {
+        let cli = TestCli::try_parse_from(["test"].iter().chain(args))?;
+        cli.patterns.patterns_and_files(&cli.files)
+    }
+
+    #[test]
+    fn positional_pattern() {
+        let (patterns, files) = parse(&["a+", "x.txt"]).unwrap();
+        assert_eq!(patterns, ["a+"]);
+        assert_eq!(files, [PathBuf::from("x.txt")]);
+    }
+
+    #[test]
+    fn flag_patterns() {
+        let path = std::env::temp_dir().join("wahgex-cli-flag-patterns.txt");
+        fs::write(&path, "c\nd\n").unwrap();
+
+        let (patterns, files) = parse(&[
+            "-e",
+            "a",
+            "-f",
+            path.to_str().unwrap(),
+            "x.txt",
+            "-e",
+            "b",
+            "y.txt",
+        ])
+        .unwrap();
+        assert_eq!(patterns, ["a", "b", "c", "d"]);
+        assert_eq!(files, [PathBuf::from("x.txt"), PathBuf::from("y.txt")]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pattern_required() {
+        assert!(parse(&[]).is_err());
+
+        let cli = TestCli::try_parse_from(["test", "-e", "a", "b"]).unwrap();
+        assert!(cli.patterns.patterns().is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! The reference state sets for the `trace` command.
+//!
+//! The PikeVM of `regex-automata` keeps its active state sets private, so
+//! these are computed by simulating the same NFA with the rules the PikeVM
+//! uses for its epsilon closures: every visited state is added to the set, and
+//! a look-around assertion only continues to its next state if it holds at the
+//! current offset.
+
+use std::collections::BTreeSet;
+
+use regex_automata::{
+    nfa::thompson::{NFA, State},
+    util::primitives::StateID,
+};
+
+/// The NFA states that are active at successive offsets of a search from the
+/// start of the haystack, mirroring the loop of the compiled module.
+pub struct ReferenceSets<'a> {
+    nfa: &'a NFA,
+    haystack: &'a [u8],
+    at_offset: usize,
+    states: BTreeSet<StateID>,
+}
+
+impl<'a> ReferenceSets<'a> {
+    /// Returns the state set at the start of the haystack.
+    pub fn new(nfa: &'a NFA, haystack: &'a [u8]) -> Self {
+        let mut sets = Self {
+            nfa,
+            haystack,
+            at_offset: 0,
+            states: BTreeSet::new(),
+        };
+        let mut states = BTreeSet::new();
+        sets.add_epsilon_closure(nfa.start_anchored(), &mut states);
+        sets.states = states;
+        sets
+    }
+
+    /// Returns the offset of the current state set.
+    pub fn at_offset(&self) -> usize {
+        self.at_offset
+    }
+
+    /// Returns the current state set.
+    pub fn states(&self) -> &BTreeSet<StateID> {
+        &self.states
+    }
+
+    /// Follows the transitions of the current state set over the byte at the
+    /// current offset. Unless the search is anchored, the start state is added
+    /// again at the next offset.
+    pub fn advance(&mut self) {
+        let Some(&byte) = self.haystack.get(self.at_offset) else {
+            self.at_offset += 1;
+            self.states.clear();
+            return;
+        };
+
+        let nexts = self
+            .states
+            .iter()
+            .filter_map(|&sid| match self.nfa.state(sid) {
+                State::ByteRange { trans } => trans.matches_byte(byte).then_some(trans.next),
+                State::Sparse(sparse) => sparse.matches_byte(byte),
+                State::Dense(dense) => dense.matches_byte(byte),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        self.at_offset += 1;
+        let mut states = BTreeSet::new();
+        for next in nexts {
+            self.add_epsilon_closure(next, &mut states);
+        }
+        if !self.nfa.is_always_start_anchored() {
+            self.add_epsilon_closure(self.nfa.start_anchored(), &mut states);
+        }
+        self.states = states;
+    }
+
+    fn add_epsilon_closure(&self, sid: StateID, states: &mut BTreeSet<StateID>) {
+        let mut stack = vec![sid];
+        while let Some(sid) = stack.pop() {
+            if !states.insert(sid) {
+                continue;
+            }
+            match self.nfa.state(sid) {
+                State::Union { alternates } => stack.extend(alternates.iter().rev()),
+                State::BinaryUnion { alt1, alt2 } => stack.extend([*alt2, *alt1]),
+                State::Capture { next, .. } => stack.push(*next),
+                State::Look { look, next } => {
+                    if self
+                        .nfa
+                        .look_matcher()
+                        .matches(*look, self.haystack, self.at_offset)
+                    {
+                        stack.push(*next);
+                    }
+                },
+                State::ByteRange { .. }
+                | State::Sparse(_)
+                | State::Dense(_)
+                | State::Fail
+                | State::Match { .. } => {},
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex_automata::nfa::thompson::WhichCaptures;
+
+    use super::*;
+
+    /// Returns the reference state set at every offset of the haystack,
+    /// including the end.
+    fn sets(pattern: &str, haystack: &str) -> Vec<Vec<usize>> {
+        let nfa = NFA::compiler()
+            .configure(NFA::config().which_captures(WhichCaptures::None))
+            .build(pattern)
+            .unwrap();
+        let mut sets = ReferenceSets::new(&nfa, haystack.as_bytes());
+        (0..=haystack.len())
+            .map(|offset| {
+                if offset > 0 {
+                    sets.advance();
+                }
+                assert_eq!(sets.at_offset(), offset);
+                sets.states().iter().map(|sid| sid.as_usize()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unanchored_restarts() {
+        // 0: binary-union(2, 1), 1: any => 0, 2: a => 3, 3: b => 4, 4: MATCH
+        assert_eq!(sets("ab", "aab"), [&[2][..], &[2, 3], &[2, 3], &[2, 4]]);
+    }
+
+    #[test]
+    fn anchored_has_no_restarts() {
+        // 0: Start => 1, 1: a => 2, 2: b => 3, 3: MATCH
+        assert_eq!(sets("^ab", "aab"), [&[0, 1][..], &[2], &[], &[]]);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        // 2: WordUnicode => 3, 3: a => 4, 4: b => 5, 5: WordUnicode => 6,
+        // 6: MATCH
+        assert_eq!(
+            sets(r"\bab\b", "ab ab"),
+            [
+                &[2, 3][..],
+                &[2, 4],
+                &[2, 3, 5, 6],
+                &[2, 3],
+                &[2, 4],
+                &[2, 3, 5, 6]
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_anchors() {
+        // 2: StartLF => 3, 3: a => 4, 4: EndLF => 5, 5: MATCH
+        assert_eq!(
+            sets("(?m)^a$", "a\nb\na"),
+            [&[2, 3][..], &[2, 4, 5], &[2, 3], &[2], &[2, 3], &[2, 4, 5]]
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use std::{fs::OpenOptions, io::Read, sync::LazyLock};
 
 use gungraun::{library_benchmark, library_benchmark_group, main};
-use wahgex::{Builder, Input, engines::wasmi::Regex};
+use wahgex::{Builder, Config, Input, engines::wasmi::Regex};
 
 fn read_haystack_from_path(path: &str) -> String {
     let mut haystack_file = OpenOptions::new().read(true).open(path).unwrap();
@@ -31,19 +31,51 @@ fn compile_passthrough_haystack(
     (regex, Input::new(haystack))
 }
 
+fn compile_inlined_passthrough_haystack(
+    pattern: &'static str,
+    haystack_name: &'static str,
+) -> (Regex, Input<'static>) {
+    let haystack = cache_read_haystack(haystack_name);
+    let bytecode = Builder::new()
+        .configure(Config::new().inline_epsilon_closure_threshold(8))
+        .build(pattern)
+        .unwrap()
+        .0;
+    let regex = Regex::new(&bytecode).unwrap();
+    (regex, Input::new(haystack))
+}
+
 #[library_benchmark(setup = compile_passthrough_haystack)]
 #[bench::literal(r"Sherlock Holmes", "sherlock")]
 #[bench::literal_prefix(r"Sherlock\s+\w+", "sherlock")]
 #[bench::literal_suffix(r"\w+\s+Holmes", "sherlock")]
 #[bench::unicode_boundary(r"\bSherlock\b", "sherlock")]
 #[bench::ascii_boundary(r"(?-u)\bSherlock\b", "sherlock")]
+#[bench::large_union(
+    r"(?:Sherlock|Holmes|Watson|Lestrade|Hudson|Adler|Moriarty|Mycroft)+ Baskerville",
+    "sherlock"
+)]
 fn bench_is_match((mut regex, haystack): (Regex, Input<'static>)) -> bool {
     regex.is_match(haystack)
 }
 
+#[library_benchmark(setup = compile_inlined_passthrough_haystack)]
+#[bench::literal(r"Sherlock Holmes", "sherlock")]
+#[bench::literal_prefix(r"Sherlock\s+\w+", "sherlock")]
+#[bench::literal_suffix(r"\w+\s+Holmes", "sherlock")]
+#[bench::unicode_boundary(r"\bSherlock\b", "sherlock")]
+#[bench::ascii_boundary(r"(?-u)\bSherlock\b", "sherlock")]
+#[bench::large_union(
+    r"(?:Sherlock|Holmes|Watson|Lestrade|Hudson|Adler|Moriarty|Mycroft)+ Baskerville",
+    "sherlock"
+)]
+fn bench_is_match_inlined((mut regex, haystack): (Regex, Input<'static>)) -> bool {
+    regex.is_match(haystack)
+}
+
 library_benchmark_group!(
     name = bench_wasmi_group;
-    benchmarks = bench_is_match
+    benchmarks = bench_is_match, bench_is_match_inlined
 );
 
 main!(library_benchmark_groups = bench_wasmi_group);
@@ -0,0 +1,121 @@
+//! This module defines the interface between a compiled WASM module and the
+//! host that runs it.
+//!
+//! Every module exports the following items:
+//!
+//! | Name | Kind | Signature |
+//! |------|------|-----------|
+//! | [`PREPARE_INPUT`] | function | `(haystack_len: i64) -> i32` |
+//! | [`IS_MATCH`] | function | `(anchored: i32, anchored_pattern: i32, span_start: i64, span_end: i64, haystack_len: i64) -> i32` |
+//! | [`HAYSTACK_MEMORY`] | 64-bit memory | |
+//! | [`ABI_VERSION_GLOBAL`] | immutable `i32` global | |
+//! | [`STEP_BUDGET_GLOBAL`] | mutable `i64` global | only with [`Config::step_counter`][crate::Config::step_counter] |
+//! | [`STATE_MEMORY`] | 64-bit memory | only with [`Config::trace`][crate::Config::trace] |
+//! | [`STATE_ID_SIZE_GLOBAL`] | immutable `i32` global | only with [`Config::trace`][crate::Config::trace] |
+//!
+//! Modules compiled with [`Config::trace`][crate::Config::trace] also import
+//! the [`TRACE_FUNCTION`] from the [`TRACE_MODULE`], with the signature
+//! `(at_offset: i64, set_ptr: i64, set_len: i32) -> ()`. It is called once per
+//! haystack offset that [`IS_MATCH`] visits, with the set of NFA states that
+//! are active at that offset. The set is `set_len` state IDs stored in the
+//! [`STATE_MEMORY`] starting at `set_ptr`, each of them a little-endian
+//! integer that is [`STATE_ID_SIZE_GLOBAL`] bytes wide.
+//!
+//! To run a search, the host must:
+//!
+//!  1. Call [`PREPARE_INPUT`] with the length of the haystack in bytes. This
+//!     grows the haystack memory if needed, and returns a
+//!     [`PrepareInputResult`].
+//!  2. Copy the haystack into the [`HAYSTACK_MEMORY`], starting at offset `0`.
+//!     The memory must be re-read after step 1, since it may have grown.
+//!  3. Call [`IS_MATCH`], where `anchored` is an [`AnchoredMode`],
+//!     `anchored_pattern` is the pattern ID when `anchored` is
+//!     [`AnchoredMode::Pattern`] (and `0` otherwise), and the span is the range
+//!     of the haystack to search. The function returns an [`IsMatchResult`].
+//!
+//! All integers are passed as their two's complement bit pattern, so the
+//! `i64` lengths and offsets are really unsigned.
+//!
+//! Hosts should compare the value of the [`ABI_VERSION_GLOBAL`] against
+//! [`VERSION`] before using a module.
+
+/// The version of the interface between the host and a compiled WASM module.
+///
+/// This is incremented whenever the exports, their signatures, or their
+/// behaviour change in a way that would cause an older host to misinterpret
+/// a module (or vice-versa).
+///
+/// Version 2 added the [`TRACE_FUNCTION`] import, the [`STATE_MEMORY`] and
+/// [`STATE_ID_SIZE_GLOBAL`] exports, and the imports and extra memory of
+/// [`Config::wasi_grep`][crate::Config::wasi_grep] modules.
+pub const VERSION: u32 = 2;
+
+/// The name of the exported function which prepares the haystack memory for a
+/// haystack of a given length.
+pub const PREPARE_INPUT: &str = "prepare_input";
+
+/// The name of the exported function which runs a search over the haystack.
+pub const IS_MATCH: &str = "is_match";
+
+/// The name of the exported memory which the host copies the haystack into.
+pub const HAYSTACK_MEMORY: &str = "haystack";
+
+/// The name of the exported global which holds the [`VERSION`] of the ABI
+/// that the module implements.
+pub const ABI_VERSION_GLOBAL: &str = "abi_version";
+
+/// The name of the exported global which holds the remaining step budget of
+/// a search, see [`IsMatchResult::BudgetExceeded`].
+pub const STEP_BUDGET_GLOBAL: &str = "step_budget";
+
+/// The name of the exported memory which holds the state of a search.
+pub const STATE_MEMORY: &str = "state";
+
+/// The name of the exported global which holds the size in bytes of each
+/// state ID in the [`STATE_MEMORY`].
+pub const STATE_ID_SIZE_GLOBAL: &str = "state_id_size";
+
+/// The name of the module that the [`TRACE_FUNCTION`] is imported from.
+pub const TRACE_MODULE: &str = "wahgex";
+
+/// The name of the imported function which receives the active NFA states at
+/// every haystack offset.
+pub const TRACE_FUNCTION: &str = "trace";
+
+/// The anchor mode of a search, passed as the `anchored` parameter of
+/// [`IS_MATCH`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchoredMode {
+    /// Mirrors [`regex_automata::Anchored::No`].
+    No = 0,
+    /// Mirrors [`regex_automata::Anchored::Yes`].
+    Yes = 1,
+    /// Mirrors [`regex_automata::Anchored::Pattern`].
+    Pattern = 2,
+}
+
+/// This enum represents the results of the [`PREPARE_INPUT`] function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrepareInputResult {
+    /// Indicates that the input preparation was successful and no memory
+    /// growth was needed.
+    SuccessNoGrowth = 0,
+    /// Indicates that the input preparation was successful and memory was
+    /// grown to accommodate the haystack.
+    SuccessGrowth = 1,
+    /// Indicates that the haystack is longer than the configured maximum
+    /// haystack length, and the memory was left untouched.
+    HaystackTooLarge = 2,
+}
+
+/// This enum represents the results of the [`IS_MATCH`] function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsMatchResult {
+    /// Indicates that the search completed and found no match.
+    NoMatch = 0,
+    /// Indicates that the search completed and found a match.
+    Match = 1,
+    /// Indicates that the search was stopped early because the step budget
+    /// was exhausted.
+    BudgetExceeded = 2,
+}
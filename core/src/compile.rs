@@ -4,11 +4,18 @@
 use input::{InputFunctions, InputLayout};
 use matching::MatchingFunctions;
 use state::{StateFunctions, StateLayout};
+use trace::TraceImports;
+use wasi::{WasiFunctions, WasiImports};
 
-use crate::RegexBytecode;
 pub use crate::error::BuildError;
+use wasm_encoder::{ConstExpr, GlobalType, ValType};
 
-use self::context::CompileContext;
+use crate::{
+    RegexBytecode, abi, inspect,
+    metadata::{METADATA_SECTION_NAME, Metadata},
+};
+
+use self::context::{CompileContext, Global};
 
 mod context;
 mod epsilon_closure;
@@ -19,33 +26,88 @@ mod matching;
 mod pattern;
 mod sparse_set;
 mod state;
+mod trace;
 mod transition;
 mod util;
+mod wasi;
 
 /// Compiles a given Thompson NFA into a [`RegexBytecode`] WebAssembly module,
 /// using the provided configuration.
+///
+/// The `patterns` are the strings the NFA was built from, if any, and are only
+/// used for the metadata section.
 pub fn compile_from_nfa(
     nfa: regex_automata::nfa::thompson::NFA,
+    patterns: Option<Vec<String>>,
     config: super::Config,
 ) -> Result<RegexBytecode, BuildError> {
-    let mut ctx = CompileContext::new(nfa, config);
+    compile_and_inspect(nfa, patterns, config).map(|(bytecode, _)| bytecode)
+}
+
+/// Compiles the NFA like [`compile_from_nfa`], and also describes the
+/// functions and lookup tables that were generated for each of its states.
+pub(crate) fn compile_and_inspect(
+    nfa: regex_automata::nfa::thompson::NFA,
+    patterns: Option<Vec<String>>,
+    config: super::Config,
+) -> Result<(RegexBytecode, Vec<inspect::StateInfo>), BuildError> {
+    let metadata = config.get_include_metadata().then(|| {
+        let patterns = patterns.filter(|_| config.get_include_patterns());
+        Metadata::new(&nfa, patterns, config)
+    });
+    // Cloning an NFA only clones a reference to it
+    let mut ctx = CompileContext::new(nfa.clone(), config);
+    ctx.add_global(Global {
+        name: abi::ABI_VERSION_GLOBAL.into(),
+        ty: GlobalType {
+            val_type: ValType::I32,
+            mutable: false,
+            shared: false,
+        },
+        init: ConstExpr::i32_const(i32::try_from(abi::VERSION).unwrap()),
+        export: true,
+    });
+    // Imported functions come first in the function index space
+    let wasi_imports = ctx
+        .config
+        .get_wasi_grep()
+        .then(|| WasiImports::new(&mut ctx));
+    let trace_imports = ctx.config.get_trace().then(|| TraceImports::new(&mut ctx));
     let state_layout = StateLayout::new(&mut ctx)?;
     let state_funcs = StateFunctions::new(&mut ctx, &state_layout)?;
     let input_layout = InputLayout::new(&mut ctx)?;
     let input_funcs =
         InputFunctions::new(&mut ctx, &input_layout, state_funcs.pattern.lookup_start);
-    let _matching_funcs = MatchingFunctions::new(
+    let matching_funcs = MatchingFunctions::new(
         &mut ctx,
         &state_layout,
         &state_funcs,
         &input_layout,
         &input_funcs,
+        trace_imports.as_ref(),
     );
-    let module: wasm_encoder::Module = ctx.compile(&state_layout.overall)?;
+    if let Some(wasi_imports) = wasi_imports {
+        WasiFunctions::new(
+            &mut ctx,
+            &wasi_imports,
+            &input_layout,
+            &input_funcs,
+            &matching_funcs,
+        );
+    }
+    let (mut module, function_names) = ctx.compile_with_function_names(&state_layout.overall)?;
+    let states = state_funcs.inspect(&nfa, &state_layout, &function_names);
+    if let Some(metadata) = metadata {
+        module.section(&wasm_encoder::CustomSection {
+            name: METADATA_SECTION_NAME.into(),
+            data: metadata.encode().into(),
+        });
+    }
 
-    Ok(RegexBytecode {
+    let bytecode = RegexBytecode {
         bytes: module.finish().into(),
-    })
+    };
+    Ok((bytecode, states))
 }
 
 #[cfg(test)]
@@ -86,8 +148,10 @@ mod tests {
         config: Config,
     ) -> Result<RegexBytecode, Box<dyn std::error::Error>> {
         let nfa = regex_automata::nfa::thompson::NFA::new(pattern)?;
+        // Metadata contains the crate version, which would churn the snapshots
+        let config = Config::new().include_metadata(false).overwrite(config);
 
-        Ok(compile_from_nfa(nfa, config)?)
+        Ok(compile_from_nfa(nfa, None, config)?)
     }
 
     #[test]
@@ -100,7 +164,12 @@ mod tests {
     #[test]
     fn empty_pattern_list() {
         let nfa = regex_automata::nfa::thompson::NFA::new_many::<&str>(&[]).unwrap();
-        let bytecode = compile_from_nfa(nfa, Config::new().include_names(true)).unwrap();
+        let bytecode = compile_from_nfa(
+            nfa,
+            None,
+            Config::new().include_names(true).include_metadata(false),
+        )
+        .unwrap();
         let pretty = wasm_print_module(&bytecode);
         insta::assert_snapshot!(pretty);
     }
@@ -203,6 +272,28 @@ mod tests {
         insta::assert_snapshot!(pretty);
     }
 
+    #[test]
+    fn step_counter() {
+        let compiled = compile_with_config(
+            "(?:abc)+",
+            Config::new().step_counter(true).include_names(true),
+        )
+        .unwrap();
+        let pretty = wasm_print_module(&compiled);
+        insta::assert_snapshot!(pretty);
+    }
+
+    #[test]
+    fn wasi_grep() {
+        let compiled = compile_with_config(
+            "(?:abc)+",
+            Config::new().wasi_grep(true).include_names(true),
+        )
+        .unwrap();
+        let pretty = wasm_print_module(&compiled);
+        insta::assert_snapshot!(pretty);
+    }
+
     #[test]
     fn multiple_transition_tables_without_compact_data_segments() {
         let compiled = compile_with_config(
@@ -226,4 +317,133 @@ mod tests {
         let pretty = wasm_print_module(&compiled);
         insta::assert_snapshot!(pretty);
     }
+
+    #[test]
+    fn duplicate_functions() {
+        // The epsilon closures of states 3 and 5 insert the same run of states
+        let compiled = compile("(?:a*)*").unwrap();
+        let pretty = wasm_print_module(&compiled);
+        insta::assert_snapshot!(pretty);
+    }
+
+    #[test]
+    fn merged_functions_still_match() {
+        use regex_automata::{Input, nfa::thompson::pikevm::PikeVM};
+
+        let cases: [(&str, &[&str]); 2] = [
+            ("(?:a*)*", &["", "a", "aaa", "b"]),
+            ("(?:x?y?)*z", &["", "z", "xyz", "yxyyxz", "xy", "xyq", "qz"]),
+        ];
+        for (pattern, haystacks) in cases {
+            let nfa = regex_automata::nfa::thompson::NFA::new(pattern).unwrap();
+            let (bytecode, states) = compile_and_inspect(nfa.clone(), None, Config::new()).unwrap();
+
+            // Merged functions share a name, so there are fewer distinct names than
+            // functions that were generated for the states
+            let generated = states
+                .iter()
+                .flat_map(|info| {
+                    let closure_fn = match &info.epsilon_closure {
+                        inspect::EpsilonClosure::Function { name, .. } => Some(name),
+                        inspect::EpsilonClosure::Omitted | inspect::EpsilonClosure::Inlined => None,
+                    };
+                    closure_fn.into_iter().chain(&info.transition_fn)
+                })
+                .collect::<Vec<_>>();
+            let remaining = generated.iter().collect::<std::collections::HashSet<_>>();
+            assert!(
+                remaining.len() < generated.len(),
+                "no functions of [{pattern}] were merged: {generated:?}"
+            );
+
+            let pikevm = PikeVM::new_from_nfa(nfa).unwrap();
+            let mut regex = crate::engines::wasmi::Regex::new(&bytecode).unwrap();
+            for haystack in haystacks {
+                assert_eq!(
+                    regex.is_match(Input::new(haystack)),
+                    pikevm.is_match(&mut pikevm.create_cache(), *haystack),
+                    "[{pattern}] on {haystack:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn branch_hints() {
+        // Includes a sparse transition table, and the UTF-8 boundary check since
+        // the pattern can match the empty string
+        let compiled = compile_with_config(
+            "(?:ab|cd|ef)?",
+            Config::new().branch_hints(true).include_names(true),
+        )
+        .unwrap();
+        let pretty = wasm_print_module(&compiled);
+        insta::assert_snapshot!(pretty);
+    }
+
+    #[test]
+    fn metadata_section() {
+        let patterns = ["a(?<b>b)", "c"];
+        let nfa = regex_automata::nfa::thompson::NFA::new_many(&patterns).unwrap();
+        let bytecode = compile_from_nfa(
+            nfa,
+            Some(patterns.iter().map(|p| p.to_string()).collect()),
+            Config::new().include_patterns(true),
+        )
+        .unwrap();
+
+        let metadata = bytecode.metadata().unwrap().unwrap();
+        assert_eq!(metadata.abi_version(), crate::abi::VERSION);
+        assert_eq!(metadata.wahgex_version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(metadata.patterns().unwrap(), &patterns);
+        assert_eq!(metadata.pattern_len(), 2);
+        assert_eq!(
+            metadata.group_names(),
+            &[vec![None, Some("b".into())], vec![None]]
+        );
+
+        let reloaded = RegexBytecode::from_bytes(bytecode.as_ref()).unwrap();
+        assert_eq!(reloaded.metadata().unwrap(), Some(metadata));
+
+        let without_patterns = compile_from_nfa(
+            regex_automata::nfa::thompson::NFA::new_many(&patterns).unwrap(),
+            Some(patterns.iter().map(|p| p.to_string()).collect()),
+            Config::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            without_patterns.metadata().unwrap().unwrap().patterns(),
+            None
+        );
+
+        let without_metadata =
+            compile_with_config("a", Config::new().include_metadata(false)).unwrap();
+        assert_eq!(without_metadata.metadata().unwrap(), None);
+    }
+
+    #[test]
+    fn regex_context_from_bytecode() {
+        let (bytecode, context) = crate::Builder::new()
+            .configure(Config::new().step_counter(true))
+            .build_many(&[r"(?<x>a)\b", "b(c)"])
+            .unwrap();
+
+        let restored = crate::RegexContext::from_bytecode(&bytecode).unwrap();
+        assert!(restored.nfa.is_none());
+        assert_eq!(restored.config, context.config);
+        assert_eq!(restored.pattern_len(), context.pattern_len());
+        assert_eq!(restored.look_set_any(), context.look_set_any());
+        assert_eq!(
+            restored.group_info().all_group_len(),
+            context.group_info().all_group_len()
+        );
+        assert_eq!(
+            restored.group_info().to_index(0.into(), "x"),
+            context.group_info().to_index(0.into(), "x")
+        );
+
+        let without_metadata =
+            compile_with_config("a", Config::new().include_metadata(false)).unwrap();
+        assert!(crate::RegexContext::from_bytecode(&without_metadata).is_err());
+    }
 }
@@ -1,18 +1,26 @@
 //! This module defines the `CompileContext` and associated structures
 //! used for compiling a regular expression NFA into a WASM module.
 
-use std::{alloc::Layout, collections::BTreeMap, hash::Hash, mem};
+use std::{
+    alloc::Layout,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    mem,
+};
 
 use highway::{HighwayHash, Key, PortableHash};
 use regex_automata::{nfa::thompson::NFA, util::primitives::StateID};
 use wasm_encoder::{
     BranchHint, BranchHints, CodeSection, ConstExpr, DataCountSection, DataSection, Encode,
-    ExportKind, ExportSection, FunctionSection, ImportSection, IndirectNameMap, MemorySection,
-    MemoryType, Module, NameMap, NameSection, TypeSection, ValType,
+    EntityType, ExportKind, ExportSection, FunctionSection, GlobalSection, GlobalType,
+    ImportSection, IndirectNameMap, MemorySection, MemoryType, Module, NameMap, NameSection,
+    TypeSection, ValType,
+};
+use wasmparser::{
+    BinaryReader, DataKind, DataSectionReader, FunctionBody, Name, Operator, Subsection,
 };
-use wasmparser::{BinaryReader, DataKind, DataSectionReader, Name, Operator, Subsection};
 
-use crate::BuildError;
+use crate::{BuildError, abi};
 
 /// This struct contains all the input and intermediate state needed to compile
 /// the WASM module.
@@ -31,15 +39,22 @@ pub struct CompileContext {
 pub struct Sections {
     types: TypeSection,
     imports: ImportSection,
-    functions: FunctionSection,
+    num_imported_functions: u32,
+    // The type of each declared function, in index order after the imports.
+    function_types: Vec<TypeIdx>,
     memories: MemorySection,
-    exports: ExportSection,
+    haystack_maximum_pages: Option<u64>,
+    globals: GlobalSection,
+    // Encoded only after functions are deduplicated, which changes their indices.
+    exports: Vec<(String, ExportKind, u32)>,
     data_count: u32,
     data: DataSection,
 
     // Name map
-    function_names: NameMap,
+    // Indexed by function index, including the imported functions.
+    function_names: Vec<String>,
     memory_names: NameMap,
+    global_names: NameMap,
     type_names: NameMap,
     data_names: NameMap,
 
@@ -48,6 +63,13 @@ pub struct Sections {
 }
 
 impl Sections {
+    /// Sets the maximum number of pages that the haystack memory can grow to.
+    ///
+    /// By default, the haystack memory has no maximum size.
+    pub fn set_haystack_maximum_pages(&mut self, pages: u64) {
+        self.haystack_maximum_pages = Some(pages);
+    }
+
     /// Adds an active data segment to the data section.
     /// These segments are copied into a linear memory at a specified offset
     /// during instantiation. Currently, all active data segments are
@@ -68,6 +90,189 @@ impl Sections {
         self.data_count += 1;
         self.data_names.append(data_idx, &segment.name);
     }
+
+    /// Merges the declared functions which have the same type, body, and
+    /// branch hints into the first of them, and redirects every call to the
+    /// merged functions.
+    ///
+    /// Functions that only differ in which of the merged functions they call
+    /// become identical once the calls are redirected, so this repeats until
+    /// no more functions are merged. The names of the merged functions are
+    /// condensed into one, like data segments in [`compact_data_section`].
+    ///
+    /// Otherwise the bodies must be byte for byte the same. Functions that
+    /// only differ in a constant, like the next state of a byte range
+    /// transition or the position of a transition table, are not merged.
+    fn deduplicate_functions(&mut self) -> Result<DeduplicatedFunctions, BuildError> {
+        let num_imported = usize::try_from(self.num_imported_functions).unwrap();
+        let mut function_defs = Vec::with_capacity(self.function_types.len());
+        for func_idx_val in (self.num_imported_functions..).take(self.function_types.len()) {
+            let Some(mut def) = self.function_definitions.remove(&func_idx_val) else {
+                panic!("Function at index {func_idx_val} was declared but not defined.");
+            };
+            let body = mem::replace(&mut def.body, wasm_encoder::Function::new([]));
+            function_defs.push((body.into_raw_body(), def));
+        }
+
+        let num_functions = num_imported + function_defs.len();
+        // The function that each function was merged into, or itself
+        let mut canonical = (0..num_functions).collect::<Vec<_>>();
+        loop {
+            let mut any_merged = false;
+            let mut seen = HashMap::<u64, Vec<usize>>::new();
+            for (def_idx, (body, def)) in function_defs.iter().enumerate() {
+                let func_idx = num_imported + def_idx;
+                if canonical[func_idx] != func_idx {
+                    continue;
+                }
+
+                let ty = self.function_types[def_idx];
+                let hints = branch_hint_values(def);
+                let mut hasher = PortableHash::new(Key(HASH_KEY));
+                (ty, body, &hints).hash(&mut hasher);
+                let candidates = seen.entry(hasher.finalize64()).or_default();
+
+                let same = candidates.iter().copied().find(|other| {
+                    let (other_body, other_def) = &function_defs[*other];
+                    self.function_types[*other] == ty
+                        && other_body == body
+                        && branch_hint_values(other_def) == hints
+                });
+                match same {
+                    Some(other) => {
+                        canonical[func_idx] = num_imported + other;
+                        any_merged = true;
+                    },
+                    None => candidates.push(def_idx),
+                }
+            }
+
+            if !any_merged {
+                break;
+            }
+
+            // Functions are only merged into earlier ones, so this resolves chains of
+            // merges
+            for func_idx in 0..num_functions {
+                canonical[func_idx] = canonical[canonical[func_idx]];
+            }
+            for (def_idx, (body, _)) in function_defs.iter_mut().enumerate() {
+                if canonical[num_imported + def_idx] == num_imported + def_idx {
+                    redirect_calls(body, |target| {
+                        u32::try_from(canonical[usize::try_from(target).unwrap()]).unwrap()
+                    })?;
+                }
+            }
+        }
+
+        let mut function_map = Vec::with_capacity(num_functions);
+        // Imported functions are never merged, so they keep their indices
+        let mut next_func_idx_val = 0;
+        for (func_idx, canonical_idx) in canonical.iter().copied().enumerate() {
+            if func_idx == canonical_idx {
+                function_map.push(next_func_idx_val);
+                next_func_idx_val += 1;
+            } else {
+                function_map.push(function_map[canonical_idx]);
+            }
+        }
+        if usize::try_from(next_func_idx_val).unwrap() == num_functions {
+            return Ok(DeduplicatedFunctions {
+                definitions: function_defs,
+                function_map,
+            });
+        }
+
+        let mut merged_names = BTreeMap::<usize, Vec<&str>>::new();
+        for (func_idx, name) in self.function_names.iter().enumerate() {
+            merged_names
+                .entry(canonical[func_idx])
+                .or_default()
+                .push(name);
+        }
+        let function_names = merged_names
+            .values()
+            .map(|names| condense_names(names, "functions"))
+            .collect();
+
+        let mut function_types = Vec::new();
+        let mut remaining_defs = Vec::new();
+        for (def_idx, (mut body, def)) in function_defs.into_iter().enumerate() {
+            if canonical[num_imported + def_idx] != num_imported + def_idx {
+                continue;
+            }
+            redirect_calls(&mut body, |target| {
+                function_map[usize::try_from(target).unwrap()]
+            })?;
+            function_types.push(self.function_types[def_idx]);
+            remaining_defs.push((body, def));
+        }
+
+        self.function_types = function_types;
+        self.function_names = function_names;
+        Ok(DeduplicatedFunctions {
+            definitions: remaining_defs,
+            function_map,
+        })
+    }
+}
+
+/// The functions that remain after [`Sections::deduplicate_functions`].
+struct DeduplicatedFunctions {
+    /// The raw body and definition of each function, in index order.
+    definitions: Vec<(Vec<u8>, FunctionDefinition)>,
+    /// The new index of every function, by its old index.
+    function_map: Vec<u32>,
+}
+
+/// Returns the branch hints of the function as plain values, so that they can
+/// be compared and hashed.
+fn branch_hint_values(def: &FunctionDefinition) -> Option<Vec<(u32, u32)>> {
+    def.branch_hints.as_ref().map(|hints| {
+        hints
+            .iter()
+            .map(|hint| (hint.branch_func_offset, hint.branch_hint_value))
+            .collect()
+    })
+}
+
+/// Rewrites the target of every `call` instruction in the raw function body.
+///
+/// The new targets must not be larger than the old ones. They are written
+/// with the same number of bytes as the old ones, so that the offsets of the
+/// instructions that branch hints refer to don't change.
+fn redirect_calls(body: &mut [u8], new_target: impl Fn(u32) -> u32) -> Result<(), BuildError> {
+    let mut patches = Vec::new();
+    let mut operators = FunctionBody::new(BinaryReader::new(body, 0)).get_operators_reader()?;
+    while !operators.eof() {
+        let (operator, offset) = operators.read_with_offset()?;
+        if let Operator::Call { function_index } = operator {
+            let target = new_target(function_index);
+            if target != function_index {
+                // The immediate follows the single byte opcode
+                patches.push((offset + 1..operators.original_position(), target));
+            }
+        }
+    }
+
+    for (range, target) in patches {
+        write_padded_var_u32(&mut body[range], target);
+    }
+    Ok(())
+}
+
+/// Writes the value as an LEB128 integer which fills the whole slice, using
+/// redundant continuation bytes if needed.
+fn write_padded_var_u32(bytes: &mut [u8], mut value: u32) {
+    let last = bytes.len() - 1;
+    for (idx, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if idx < last {
+            *byte |= 0x80;
+        }
+    }
+    assert_eq!(value, 0, "value should fit in the existing immediate");
 }
 
 impl CompileContext {
@@ -103,6 +308,30 @@ impl CompileContext {
         TypeIdx(func_ty_idx)
     }
 
+    /// Declares a function that is imported from the host.
+    ///
+    /// Imported functions occupy the first indices of the function index space,
+    /// so all imports must be declared before any other function.
+    pub fn add_function_import(
+        &mut self,
+        module: &str,
+        sig: &FunctionTypeSignature,
+    ) -> FunctionIdx {
+        assert!(
+            self.sections.function_types.is_empty(),
+            "Function imports must be declared before any other function"
+        );
+
+        let func_ty_idx = self.declare_fn_type(sig);
+        let func_idx_val = self.sections.num_imported_functions;
+        self.sections
+            .imports
+            .import(module, sig.name, EntityType::Function(func_ty_idx.into()));
+        self.sections.function_names.push(sig.name.into());
+        self.sections.num_imported_functions += 1;
+        FunctionIdx(func_idx_val)
+    }
+
     /// Declares a function's signature (name, parameters, return types, export
     /// status).
     pub fn declare_function_with_type(
@@ -111,9 +340,9 @@ impl CompileContext {
         name: &str,
         export: bool,
     ) -> FunctionIdx {
-        let func_idx_val = self.sections.functions.len();
-        self.sections.functions.function(func_ty_idx.into());
-        self.sections.function_names.append(func_idx_val, name);
+        let func_idx_val = self.sections.num_imported_functions + self.num_declared_functions();
+        self.sections.function_types.push(func_ty_idx);
+        self.sections.function_names.push(name.into());
 
         #[cfg(test)]
         let override_export = self.config.get_export_all_functions();
@@ -123,11 +352,15 @@ impl CompileContext {
         if export || override_export {
             self.sections
                 .exports
-                .export(name, ExportKind::Func, func_idx_val);
+                .push((name.into(), ExportKind::Func, func_idx_val));
         }
         FunctionIdx(func_idx_val)
     }
 
+    fn num_declared_functions(&self) -> u32 {
+        u32::try_from(self.sections.function_types.len()).expect("function count should fit in u32")
+    }
+
     /// Declares a function's signature (name, parameters, return types, export
     /// status).
     ///
@@ -148,19 +381,32 @@ impl CompileContext {
     /// The `func_idx` must correspond to a function previously returned by
     /// `declare_function`. The definition includes the body, local names,
     /// label names, and branch hints.
-    pub fn define_function(&mut self, func_idx: FunctionIdx, def: FunctionDefinition) {
-        if func_idx.0 >= self.sections.functions.len() {
+    pub fn define_function(&mut self, func_idx: FunctionIdx, mut def: FunctionDefinition) {
+        let num_imported_functions = self.sections.num_imported_functions;
+        if func_idx.0 < num_imported_functions {
+            panic!(
+                "Defining function with index {} which is an imported function",
+                func_idx.0
+            );
+        }
+        let num_declared_functions = self.num_declared_functions();
+        if func_idx.0 >= num_imported_functions + num_declared_functions {
             panic!(
                 "Defining function with index {} which has not been declared (max declared index: \
                  {})",
                 func_idx.0,
-                if !self.sections.functions.is_empty() {
-                    self.sections.functions.len() - 1
+                if num_declared_functions != 0 {
+                    num_imported_functions + num_declared_functions - 1
                 } else {
                     0
                 }
             );
         }
+        // Hints are always computed, so they are only dropped here
+        if !self.config.get_branch_hints() {
+            def.branch_hints = None;
+        }
+        def.branch_hints = def.branch_hints.filter(|hints| !hints.is_empty());
         if self
             .sections
             .function_definitions
@@ -171,6 +417,22 @@ impl CompileContext {
         }
     }
 
+    /// Declare and define a global.
+    pub fn add_global(&mut self, global: Global) -> GlobalIdx {
+        let global_idx_val = self.sections.globals.len();
+        self.sections.globals.global(global.ty, &global.init);
+        self.sections
+            .global_names
+            .append(global_idx_val, &global.name);
+
+        if global.export {
+            self.sections
+                .exports
+                .push((global.name, ExportKind::Global, global_idx_val));
+        }
+        GlobalIdx(global_idx_val)
+    }
+
     /// Adds a block signature to the type section.
     ///
     /// This is used for block types in control flow instructions.
@@ -227,7 +489,22 @@ impl CompileContext {
 impl CompileContext {
     /// This function takes all the individual settings/functions/data
     /// segments/layouts and compiles them into a single WASM [`Module`].
-    pub fn compile(mut self, state_overall: &Layout) -> Result<Module, BuildError> {
+    #[cfg(test)]
+    pub fn compile(self, state_overall: &Layout) -> Result<Module, BuildError> {
+        self.compile_with_function_names(state_overall)
+            .map(|(module, _)| module)
+    }
+
+    /// Compiles the module like [`compile`][Self::compile], and also returns
+    /// the final name of every function, indexed by the [`FunctionIdx`] it was
+    /// declared with.
+    ///
+    /// Functions that were merged by [`Sections::deduplicate_functions`] share
+    /// the condensed name.
+    pub fn compile_with_function_names(
+        mut self,
+        state_overall: &Layout,
+    ) -> Result<(Module, Vec<String>), BuildError> {
         let mut module = Module::new();
 
         // Section order
@@ -248,13 +525,27 @@ impl CompileContext {
 
         module.section(&self.sections.imports);
 
-        module.section(&self.sections.functions);
+        let DeduplicatedFunctions {
+            definitions: function_defs,
+            function_map,
+        } = self.sections.deduplicate_functions()?;
+        let final_function_names = function_map
+            .iter()
+            .map(|func_idx_val| {
+                self.sections.function_names[usize::try_from(*func_idx_val).unwrap()].clone()
+            })
+            .collect();
+        let mut functions = FunctionSection::new();
+        for func_ty_idx in &self.sections.function_types {
+            functions.function((*func_ty_idx).into());
+        }
+        module.section(&functions);
 
         // Determine minimum (and maximum?) size based on data structure layout
         let haystack_mem_idx = self.sections.memories.len();
         self.sections.memories.memory(MemoryType {
             minimum: 1,
-            maximum: None,
+            maximum: self.sections.haystack_maximum_pages,
             // TODO: Make state memory64 default false by config
             memory64: true,
             shared: false,
@@ -273,22 +564,57 @@ impl CompileContext {
             // TODO: Use custom page size
             page_size_log2: None,
         });
+        let wasi_mem_idx = self.config.get_wasi_grep().then(|| {
+            let wasi_mem_idx = self.sections.memories.len();
+            debug_assert_eq!(wasi_mem_idx, super::wasi::WASI_MEMORY_INDEX);
+            // WASI requires a 32-bit memory for the buffers passed to the host
+            self.sections.memories.memory(MemoryType {
+                minimum: 1,
+                maximum: None,
+                memory64: false,
+                shared: false,
+                page_size_log2: None,
+            });
+            self.sections.exports.push((
+                super::wasi::WASI_MEMORY.into(),
+                ExportKind::Memory,
+                wasi_mem_idx,
+            ));
+            wasi_mem_idx
+        });
         module.section(&self.sections.memories);
 
-        self.sections
-            .exports
-            .export("haystack", ExportKind::Memory, haystack_mem_idx);
+        if !self.sections.globals.is_empty() {
+            module.section(&self.sections.globals);
+        }
+
+        self.sections.exports.push((
+            abi::HAYSTACK_MEMORY.into(),
+            ExportKind::Memory,
+            haystack_mem_idx,
+        ));
 
         #[cfg(test)]
         let export_state = self.config.get_export_state();
         #[cfg(not(test))]
         let export_state = false;
-        if export_state {
-            self.sections
-                .exports
-                .export("state", ExportKind::Memory, state_mem_idx);
+        // The host decodes the traced state sets from the state memory
+        if export_state || self.config.get_trace() {
+            self.sections.exports.push((
+                abi::STATE_MEMORY.into(),
+                ExportKind::Memory,
+                state_mem_idx,
+            ));
         }
-        module.section(&self.sections.exports);
+        let mut exports = ExportSection::new();
+        for (name, kind, index) in &self.sections.exports {
+            let index = match kind {
+                ExportKind::Func => function_map[usize::try_from(*index).unwrap()],
+                _ => *index,
+            };
+            exports.export(name, *kind, index);
+        }
+        module.section(&exports);
 
         let (data_section, data_names, data_count) = if self.config.get_compact_data_section() {
             let current_offset = module.len();
@@ -311,31 +637,23 @@ impl CompileContext {
         let mut local_names = IndirectNameMap::new();
         let mut label_names = IndirectNameMap::new();
 
-        let num_declared_functions = self.sections.functions.len();
-
-        // Ensure all declared functions have corresponding definitions.
-        // BTreeMap iteration is ordered by key, which is FunctionIdx.0.
-        // We iterate 0..num_declared_functions to ensure correct order and that all are
-        // present.
-        for func_idx_val in 0..num_declared_functions {
-            match self.sections.function_definitions.get(&func_idx_val) {
-                Some(def) => {
-                    codes.function(&def.body);
-                    local_names.append(func_idx_val, &def.locals_name_map);
-                    if let Some(labels) = &def.labels_name_map {
-                        label_names.append(func_idx_val, labels);
-                    }
-                    if let Some(hints) = &def.branch_hints {
-                        hint_section.function_hints(func_idx_val, hints.iter().copied());
-                    }
-                },
-                None => {
-                    panic!("Function at index {func_idx_val} was declared but not defined.");
-                },
+        // The definitions are already in function index order
+        for (func_idx_val, (body, def)) in
+            (self.sections.num_imported_functions..).zip(&function_defs)
+        {
+            codes.raw(body);
+            local_names.append(func_idx_val, &def.locals_name_map);
+            if let Some(labels) = &def.labels_name_map {
+                label_names.append(func_idx_val, labels);
+            }
+            if let Some(hints) = &def.branch_hints {
+                hint_section.function_hints(func_idx_val, hints.iter().copied());
             }
         }
 
-        module.section(&hint_section);
+        if !hint_section.is_empty() {
+            module.section(&hint_section);
+        }
 
         module.section(&codes);
 
@@ -344,7 +662,11 @@ impl CompileContext {
         if self.config.get_include_names() {
             let mut name_section = NameSection::new();
             {
-                name_section.functions(&self.sections.function_names);
+                let mut function_names = NameMap::new();
+                for (func_idx_val, name) in (0..).zip(&self.sections.function_names) {
+                    function_names.append(func_idx_val, name);
+                }
+                name_section.functions(&function_names);
 
                 name_section.locals(&local_names);
 
@@ -357,15 +679,42 @@ impl CompileContext {
                         .memory_names
                         .append(haystack_mem_idx, "haystack");
                     self.sections.memory_names.append(state_mem_idx, "state"); // Assuming state_mem_idx is valid
+                    if let Some(wasi_mem_idx) = wasi_mem_idx {
+                        self.sections.memory_names.append(wasi_mem_idx, "wasi");
+                    }
                 }
                 name_section.memories(&self.sections.memory_names);
 
+                if !self.sections.global_names.is_empty() {
+                    name_section.globals(&self.sections.global_names);
+                }
+
                 name_section.data(&data_names);
             }
             module.section(&name_section);
         }
 
-        Ok(module)
+        Ok((module, final_function_names))
+    }
+}
+
+const HASH_KEY: [u64; 4] = [
+    2838337935062222553,
+    17674154047873536125,
+    2441950910458579046,
+    6426572504034188637,
+];
+
+/// Returns a single name for the items which were merged together, which is
+/// either the only name or a hash of all of them.
+fn condense_names(current_names: &[&str], kind: &str) -> String {
+    if let [name] = current_names {
+        String::from(*name)
+    } else {
+        let mut hasher = PortableHash::new(Key(HASH_KEY));
+        current_names.hash(&mut hasher);
+        let name_hash = hasher.finalize64();
+        format!("{name_hash:x}_{kind}")
     }
 }
 
@@ -410,7 +759,7 @@ fn compact_data_section(
 
     let mut all_active_segments = BTreeMap::new();
 
-    for (segment, name) in section_reader.into_iter().zip(data_names.into_iter()) {
+    for (segment, name) in section_reader.into_iter().zip(data_names) {
         let segment = segment?;
         let name = name?;
         // This analysis is a super-restricted version of the same thing that
@@ -445,22 +794,8 @@ fn compact_data_section(
         }
     }
 
-    const HASH_KEY: [u64; 4] = [
-        2838337935062222553,
-        17674154047873536125,
-        2441950910458579046,
-        6426572504034188637,
-    ];
-
     fn condense_segment_names(current_names: &[&str]) -> String {
-        if let [name] = current_names {
-            String::from(*name)
-        } else {
-            let mut hasher = PortableHash::new(Key(HASH_KEY));
-            current_names.hash(&mut hasher);
-            let name_hash = hasher.finalize64();
-            format!("{name_hash:x}_segments")
-        }
+        condense_names(current_names, "segments")
     }
 
     let mut current_segment = None;
@@ -558,6 +893,15 @@ pub struct FunctionDefinition {
     pub branch_hints: Option<Vec<BranchHint>>,
 }
 
+/// Returns a hint that the `if` instruction which is added to the body next is
+/// unlikely to be taken.
+pub fn unlikely_branch(body: &wasm_encoder::Function) -> BranchHint {
+    BranchHint {
+        branch_func_offset: u32::try_from(body.byte_len()).unwrap(),
+        branch_hint_value: 0,
+    }
+}
+
 /// Contains the full definition of a function: signature and definition.
 #[derive(Debug)]
 pub struct Function {
@@ -565,6 +909,16 @@ pub struct Function {
     pub def: FunctionDefinition,
 }
 
+/// Contains the full definition of a global: its name, type, initial value,
+/// and export status.
+#[derive(Debug)]
+pub struct Global {
+    pub name: String,
+    pub ty: GlobalType,
+    pub init: ConstExpr,
+    pub export: bool,
+}
+
 /// Describes the signature of a block type (e.g., for `if`, `loop`, `block`).
 /// It includes a descriptive name, parameter types, and result types.
 #[derive(Debug)]
@@ -591,6 +945,14 @@ impl From<FunctionIdx> for u32 {
     }
 }
 
+impl FunctionIdx {
+    /// Returns the name of the function, from the names returned by
+    /// [`CompileContext::compile_with_function_names`].
+    pub fn name(self, function_names: &[String]) -> &str {
+        &function_names[usize::try_from(self.0).unwrap()]
+    }
+}
+
 /// This index type represents a pointer to a specific type, be it function or
 /// block signature.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -602,6 +964,16 @@ impl From<TypeIdx> for u32 {
     }
 }
 
+/// This index type represents a pointer to a specific global.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobalIdx(u32);
+
+impl From<GlobalIdx> for u32 {
+    fn from(idx: GlobalIdx) -> Self {
+        idx.0
+    }
+}
+
 /// This index type represents a pointer to a specific table.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TableIdx(u32);
@@ -1,7 +1,10 @@
 //! This module contains types and functions related to computing the epsilon
 //! closure of a given NFA state.
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    alloc::Layout,
+    collections::{HashMap, HashSet},
+};
 
 use regex_automata::{
     nfa::thompson::State,
@@ -9,44 +12,178 @@ use regex_automata::{
 };
 use wasm_encoder::{BlockType, NameMap, ValType};
 
-use crate::compile::{context::FunctionTypeSignature, instructions::InstructionSinkExt};
+use crate::{
+    compile::{context::FunctionTypeSignature, instructions::InstructionSinkExt},
+    inspect,
+};
 
 use super::{
     BuildError, CompileContext,
-    context::{Function, FunctionDefinition, FunctionIdx, FunctionSignature},
+    context::{ActiveDataSegment, Function, FunctionDefinition, FunctionIdx, FunctionSignature},
     lookaround::LookFunctions,
+    sparse_set::SparseSetFunctions,
+    util::repeat,
 };
 
+/// This struct describes the layout of the precomputed runs of state IDs that
+/// the epsilon closure functions insert into the next set.
+///
+/// Only the unconditional states of closures with more than one state get a
+/// run, and closures with the same states share a single run.
+///
+/// The layout also keeps the epsilon closure of every state, which are
+/// computed once here and reused by [`EpsilonClosureFunctions`].
+#[derive(Debug)]
+pub struct EpsilonClosureLayout {
+    runs: HashMap<StateID, StateRun>,
+    /// The epsilon closure of every state, indexed by state ID.
+    closures: Vec<EpsilonClosure>,
+    /// The sorted states of each epsilon closure that is small enough to be
+    /// inlined, see [`Config::inline_epsilon_closure_threshold`].
+    ///
+    /// [`Config::inline_epsilon_closure_threshold`]: crate::Config::inline_epsilon_closure_threshold
+    inline_closures: HashMap<StateID, Vec<StateID>>,
+    /// The states whose epsilon closure function would never be called, see
+    /// [`inlined_only_states`].
+    inlined_only: HashSet<StateID>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StateRun {
+    pos: usize,
+    len: usize,
+}
+
+impl EpsilonClosureLayout {
+    /// Create a new layout for the epsilon closure runs, adding a data segment
+    /// for each distinct run.
+    pub fn new(
+        ctx: &mut CompileContext,
+        mut overall: Layout,
+    ) -> Result<(Layout, Self), BuildError> {
+        let mut runs = HashMap::new();
+        let mut distinct_runs = HashMap::new();
+
+        let states = ctx.nfa.states();
+        let closures = (0..states.len())
+            .map(StateID::new)
+            .map(Result::unwrap)
+            .map(|sid| compute_epsilon_closure(sid, states))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inline_closures = all_inline_closures(ctx, &closures);
+        let inlined_only = inlined_only_states(ctx, &closures, &inline_closures);
+
+        let state_id_layout = *ctx.state_id_layout();
+        for (for_sid, closure) in (0..closures.len())
+            .map(StateID::new)
+            .map(Result::unwrap)
+            .zip(&closures)
+        {
+            if EpsilonClosureFunctions::can_omit_epsilon_closure(closure, for_sid)
+                || closure.unconditional.len() < 2
+                || inlined_only.contains(&for_sid)
+            {
+                continue;
+            }
+
+            let mut unconditional = closure.unconditional.iter().copied().collect::<Vec<_>>();
+            // Insert in the same order as the one at a time inserts did
+            unconditional.sort();
+
+            if let Some(run) = distinct_runs.get(&unconditional).copied() {
+                runs.insert(for_sid, run);
+                continue;
+            }
+
+            let (run_layout, _) = repeat(&state_id_layout, unconditional.len())?;
+            let (new_overall, pos) = overall.extend(run_layout)?;
+            overall = new_overall;
+
+            let mut data = Vec::with_capacity(run_layout.size());
+            for state_id in &unconditional {
+                // WASM assumes little endian byte ordering: https://webassembly.org/docs/portability/
+                data.extend_from_slice(&state_id.as_u32().to_le_bytes()[..state_id_layout.size()]);
+            }
+            assert_eq!(
+                run_layout.size(),
+                data.len(),
+                "Segment data length must match layout size"
+            );
+
+            ctx.sections.add_active_data_segment(ActiveDataSegment {
+                name: format!("epsilon_closure_states_{}", for_sid.as_u32()),
+                position: pos,
+                data,
+            });
+
+            let run = StateRun {
+                pos,
+                len: unconditional.len(),
+            };
+            distinct_runs.insert(unconditional, run);
+            runs.insert(for_sid, run);
+        }
+
+        Ok((
+            overall,
+            Self {
+                runs,
+                closures,
+                inline_closures,
+                inlined_only,
+            },
+        ))
+    }
+
+    fn get(&self, sid: StateID) -> Option<StateRun> {
+        self.runs.get(&sid).copied()
+    }
+
+    fn closure(&self, sid: StateID) -> &EpsilonClosure {
+        &self.closures[sid.as_usize()]
+    }
+}
+
 /// This struct contains a map of functions that are the pre-computed epsilon
 /// closure for each NFA state.
 #[derive(Debug)]
 pub struct EpsilonClosureFunctions {
     state_closures: HashMap<StateID, FunctionIdx>,
+    /// The sorted states of each epsilon closure that is small enough to be
+    /// inlined, see [`Config::inline_epsilon_closure_threshold`].
+    ///
+    /// [`Config::inline_epsilon_closure_threshold`]: crate::Config::inline_epsilon_closure_threshold
+    inline_closures: HashMap<StateID, Vec<StateID>>,
     pub branch_to_epsilon_closure: FunctionIdx,
+    pub sparse_set_insert: FunctionIdx,
 }
 
 impl EpsilonClosureFunctions {
     /// Create a new set of epsilon closure functions for the given input.
     pub fn new(
         ctx: &mut CompileContext,
-        sparse_set_insert: FunctionIdx,
+        layout: &EpsilonClosureLayout,
+        sparse_set: &SparseSetFunctions,
         look_funcs: &LookFunctions,
     ) -> Result<Self, BuildError> {
-        let state_closures = Self::all_epsilon_closure_fns(ctx, sparse_set_insert, look_funcs)?;
+        let state_closures = Self::all_epsilon_closure_fns(ctx, layout, sparse_set, look_funcs)?;
         let branch_to_epsilon_closure = ctx.add_function(Self::branch_to_epsilon_closure_fn(
             &state_closures,
-            sparse_set_insert,
+            sparse_set.insert,
         ));
 
         Ok(Self {
             state_closures,
+            inline_closures: layout.inline_closures.clone(),
             branch_to_epsilon_closure,
+            sparse_set_insert: sparse_set.insert,
         })
     }
 
     fn all_epsilon_closure_fns(
         ctx: &mut CompileContext,
-        sparse_set_insert: FunctionIdx,
+        layout: &EpsilonClosureLayout,
+        sparse_set: &SparseSetFunctions,
         look_funcs: &LookFunctions,
     ) -> Result<HashMap<StateID, FunctionIdx>, BuildError> {
         // NOTE: The indexes of the `states` array correspond to the `StateID` value.
@@ -67,15 +204,15 @@ impl EpsilonClosureFunctions {
             results_ty: &[ValType::I32],
         });
         for for_sid in (0..num_states).map(StateID::new).map(Result::unwrap) {
-            let states = ctx.nfa.states();
-            let closure = compute_epsilon_closure(for_sid, states)?;
-            if Self::can_omit_epsilon_closure(&closure, for_sid) {
+            if Self::can_omit_epsilon_closure(layout.closure(for_sid), for_sid)
+                || layout.inlined_only.contains(&for_sid)
+            {
                 continue;
             }
 
             let func_idx = ctx.declare_function_with_type(
                 epsilon_closure_fn_type,
-                &format!("epsilon_closure_s{}", for_sid.as_usize()),
+                &epsilon_closure_fn_name(for_sid),
                 false,
             );
 
@@ -83,12 +220,11 @@ impl EpsilonClosureFunctions {
         }
 
         for (for_sid, func_idx) in &state_to_epsilon_closure_fn {
-            let states = ctx.nfa.states();
-            let closure = compute_epsilon_closure(*for_sid, states)?;
             let def = Self::epsilon_closure_fn_def(
-                closure,
+                layout.closure(*for_sid),
+                layout.get(*for_sid),
                 &state_to_epsilon_closure_fn,
-                sparse_set_insert,
+                sparse_set,
                 look_funcs,
             )?;
             ctx.define_function(*func_idx, def);
@@ -97,12 +233,43 @@ impl EpsilonClosureFunctions {
         Ok(state_to_epsilon_closure_fn)
     }
 
+    /// Describes the epsilon closure function that was generated for the
+    /// given state, if any.
+    pub fn inspect(
+        &self,
+        for_sid: StateID,
+        layout: &EpsilonClosureLayout,
+        function_names: &[String],
+    ) -> inspect::EpsilonClosure {
+        let closure = layout.closure(for_sid);
+        let Some(func_idx) = self.get(for_sid) else {
+            // Otherwise the function was left out since nothing would call it
+            return if Self::can_omit_epsilon_closure(closure, for_sid) {
+                inspect::EpsilonClosure::Omitted
+            } else {
+                inspect::EpsilonClosure::Inlined
+            };
+        };
+
+        inspect::EpsilonClosure::Function {
+            name: func_idx.name(function_names).into(),
+            unconditional_len: closure.unconditional.len(),
+            lookaround_len: closure.lookaround.len(),
+            bulk_insert: layout.get(for_sid).is_some(),
+        }
+    }
+
     /// Get the epsilon closure function for the given state ID, if present.
-    #[expect(dead_code)]
     pub fn get(&self, sid: StateID) -> Option<FunctionIdx> {
         self.state_closures.get(&sid).copied()
     }
 
+    /// Get the states of the epsilon closure for the given state ID, if it
+    /// should be inlined instead of calling the epsilon closure function.
+    pub fn get_inline(&self, sid: StateID) -> Option<&[StateID]> {
+        self.inline_closures.get(&sid).map(Vec::as_slice)
+    }
+
     fn branch_to_epsilon_closure_fn(
         epsilon_closures: &HashMap<StateID, FunctionIdx>,
         sparse_set_insert: FunctionIdx,
@@ -193,12 +360,13 @@ impl EpsilonClosureFunctions {
     }
 
     fn epsilon_closure_fn_def(
-        closure: EpsilonClosure,
+        closure: &EpsilonClosure,
+        run: Option<StateRun>,
         state_to_epsilon_closure_fn: &HashMap<StateID, FunctionIdx>,
-        sparse_set_insert: FunctionIdx,
+        sparse_set: &SparseSetFunctions,
         look_funcs: &LookFunctions,
     ) -> Result<FunctionDefinition, BuildError> {
-        let mut unconditional = closure.unconditional.into_iter().collect::<Vec<_>>();
+        let mut unconditional = closure.unconditional.iter().copied().collect::<Vec<_>>();
         // need this to keep consistency of snapshot tests
         unconditional.sort();
 
@@ -219,17 +387,26 @@ impl EpsilonClosureFunctions {
 
         instructions.local_get(4);
 
-        // TODO(opt): Could optimize this by adding a bulk insert method and loading all
-        // of these from a memory location initialized by an active data segment
-        for closure_sid in unconditional {
+        if let Some(run) = run {
+            // The run holds the same states as `unconditional`, in the same order
+            debug_assert_eq!(run.len, unconditional.len());
             instructions
-                // new_next_set_len is already on the stack from the prelude or the previous call to
-                // sparse_set_insert
-                .u32_const(closure_sid.as_u32())
+                // Args needed [next_set_len, states_ptr, states_len, next_set_ptr]
+                .u64_const(u64::try_from(run.pos).unwrap())
+                .u32_const(u32::try_from(run.len).unwrap())
                 .local_get(3) // next_set_ptr
-                // TODO(opt): Instead of creating a separate function for every state's epsilon
-                // transition, have some of them be inlined depending on size.
-                .call(sparse_set_insert.into());
+                .call(sparse_set.insert_many.into());
+        } else {
+            for closure_sid in unconditional {
+                instructions
+                    // new_next_set_len is already on the stack from the prelude or the previous
+                    // call to sparse_set_insert
+                    .u32_const(closure_sid.as_u32())
+                    .local_get(3) // next_set_ptr
+                    // TODO(opt): Instead of creating a separate function for every state's
+                    // epsilon transition, have some of them be inlined depending on size.
+                    .call(sparse_set.insert.into());
+            }
         }
 
         // At this point the stack is [new_next_set_len]
@@ -251,7 +428,7 @@ impl EpsilonClosureFunctions {
                 .local_get(4)
                 .i32_ne()
                 .if_(BlockType::Empty);
-            for look in closure.lookaround {
+            for look in &closure.lookaround {
                 instructions
                     .local_get(0)
                     .local_get(1)
@@ -280,7 +457,7 @@ impl EpsilonClosureFunctions {
                         .local_get(5)
                         .u32_const(look.next.as_u32())
                         .local_get(3)
-                        .call(sparse_set_insert.into())
+                        .call(sparse_set.insert.into())
                         .local_set(5);
                 }
 
@@ -319,6 +496,78 @@ struct EpsilonLook {
     look: Look,
 }
 
+/// Collect the epsilon closures which have no lookaround and contain at most
+/// as many states as the configured inline threshold.
+fn all_inline_closures(
+    ctx: &CompileContext,
+    closures: &[EpsilonClosure],
+) -> HashMap<StateID, Vec<StateID>> {
+    let threshold = ctx.config.get_inline_epsilon_closure_threshold();
+    if threshold == 0 {
+        return HashMap::new();
+    }
+
+    (0..closures.len())
+        .map(StateID::new)
+        .map(Result::unwrap)
+        .zip(closures)
+        .filter(|(_, closure)| {
+            closure.lookaround.is_empty() && closure.unconditional.len() <= threshold
+        })
+        .map(|(for_sid, closure)| {
+            let mut unconditional = closure.unconditional.iter().copied().collect::<Vec<_>>();
+            // Insert in the same order as the epsilon closure function would
+            unconditional.sort();
+            (for_sid, unconditional)
+        })
+        .collect()
+}
+
+/// Returns the states whose epsilon closure is inlined into every transition
+/// that leads to them, so their epsilon closure function would never be
+/// called.
+fn inlined_only_states(
+    ctx: &CompileContext,
+    closures: &[EpsilonClosure],
+    inline_closures: &HashMap<StateID, Vec<StateID>>,
+) -> HashSet<StateID> {
+    if inline_closures.is_empty() {
+        return HashSet::new();
+    }
+
+    let nfa = &ctx.nfa;
+    // The states that `branch_to_epsilon_closure` may be called with, starting
+    // with those that the search loop starts from
+    let mut branched_to = HashSet::from([nfa.start_anchored(), nfa.start_unanchored()]);
+    branched_to.extend(nfa.patterns().filter_map(|pid| nfa.start_pattern(pid)));
+    for (state, closure) in nfa.states().iter().zip(closures) {
+        match state {
+            State::ByteRange { trans } if !inline_closures.contains_key(&trans.next) => {
+                branched_to.insert(trans.next);
+            },
+            State::Sparse(sparse) => {
+                branched_to.extend(sparse.transitions.iter().map(|trans| trans.next));
+            },
+            State::Dense(dense) => branched_to.extend(dense.transitions.iter().copied()),
+            _ => {},
+        }
+
+        // The epsilon closure functions call the function of the state after
+        // each passing lookaround
+        branched_to.extend(closure.lookaround.iter().map(|look| look.next));
+    }
+
+    inline_closures
+        .keys()
+        .filter(|sid| !branched_to.contains(sid))
+        .copied()
+        .collect()
+}
+
+fn epsilon_closure_fn_name(sid: StateID) -> String {
+    format!("epsilon_closure_s{}", sid.as_usize())
+}
+
 fn compute_epsilon_closure(sid: StateID, states: &[State]) -> Result<EpsilonClosure, BuildError> {
     let mut unconditional: HashSet<_> = HashSet::new();
 
@@ -555,11 +804,17 @@ mod tests {
         let overall = Layout::new::<()>();
         let (overall, sparse_set_layout) = SparseSetLayout::new(&mut ctx, overall).unwrap();
         let (overall, look_layout) = LookLayout::new(&mut ctx, overall).unwrap();
+        let (overall, epsilon_closure_layout) =
+            EpsilonClosureLayout::new(&mut ctx, overall).unwrap();
         let sparse_set_functions = SparseSetFunctions::new(&mut ctx, &sparse_set_layout);
         let look_funcs = LookFunctions::new(&mut ctx, &look_layout);
 
-        let _epsilon_closure_functions =
-            EpsilonClosureFunctions::new(&mut ctx, sparse_set_functions.insert, &look_funcs);
+        let _epsilon_closure_functions = EpsilonClosureFunctions::new(
+            &mut ctx,
+            &epsilon_closure_layout,
+            &sparse_set_functions,
+            &look_funcs,
+        );
 
         let module = ctx.compile(&overall).unwrap();
         module.finish()
@@ -1,18 +1,26 @@
 //! This module contains types and functions related to laying out the input
 //! options and haystack in the WASM memory.
 
-use std::alloc::{Layout, LayoutError};
+use std::alloc::Layout;
 
 use regex_automata::nfa::thompson::NFA;
 use wasm_encoder::{BlockType, NameMap, ValType};
 
-use crate::{compile::instructions::InstructionSinkExt, input::PrepareInputResult};
+use crate::{
+    BuildError,
+    abi::{self, AnchoredMode, PrepareInputResult},
+    compile::instructions::InstructionSinkExt,
+};
 
 use super::context::{
     BlockSignature, CompileContext, Function, FunctionDefinition, FunctionIdx, FunctionSignature,
-    TypeIdx,
+    TypeIdx, unlikely_branch,
 };
 
+/// The most pages that a 64-bit memory can have, which together span the whole
+/// 64-bit address space.
+const MAX_MEMORY64_PAGES: u64 = 1 << 48;
+
 /// Defines the memory layout for input-related data within the WebAssembly
 /// module.
 ///
@@ -27,14 +35,28 @@ impl InputLayout {
     /// Creates a new [`InputLayout`].
     ///
     /// Currently, this primarily determines the starting offset for the
-    /// haystack.
-    pub fn new(_ctx: &mut CompileContext) -> Result<Self, LayoutError> {
+    /// haystack, and the maximum size of the haystack memory if the haystack
+    /// length is limited.
+    pub fn new(ctx: &mut CompileContext) -> Result<Self, BuildError> {
         let overall = Layout::new::<()>();
 
         // We use a zero-size array here to mark the start of the haystack, since we
         // don't know the length of it until runtime.
         let (overall, haystack_start_pos) = overall.extend(Layout::array::<u8>(0)?)?;
 
+        if let Some(max_haystack_len) = ctx.config.get_max_haystack_len() {
+            let page_size = ctx.config.get_page_size();
+            let haystack_end = haystack_start_pos
+                .checked_add(max_haystack_len)
+                .ok_or_else(|| BuildError::max_haystack_len_too_large(max_haystack_len))?;
+            // Always allow at least the initial page, since the memory is created with it,
+            // and at most as many as the memory can address
+            let max_pages = u64::try_from(haystack_end.div_ceil(page_size).max(1))
+                .map_err(|_| BuildError::max_haystack_len_too_large(max_haystack_len))?
+                .min(MAX_MEMORY64_PAGES);
+            ctx.sections.set_haystack_maximum_pages(max_pages);
+        }
+
         Ok(Self {
             _overall: overall,
             haystack_start_pos,
@@ -48,8 +70,7 @@ impl InputLayout {
 /// input haystack.
 #[derive(Debug)]
 pub struct InputFunctions {
-    #[expect(dead_code)]
-    prepare_input: FunctionIdx,
+    pub prepare_input: FunctionIdx,
     pub utf8_is_boundary: Option<FunctionIdx>,
     pub start_config: FunctionIdx,
 }
@@ -68,6 +89,7 @@ impl InputFunctions {
     ) -> Self {
         let prepare_input = ctx.add_function(Self::prepare_input_fn(
             ctx.config.get_page_size(),
+            ctx.config.get_max_haystack_len(),
             input_layout,
         ));
 
@@ -128,7 +150,7 @@ impl InputFunctions {
         body.instructions()
             // if anchored == Anchored::No {
             .local_get(0)
-            .i32_const(0) // Anchored::No
+            .i32_const(AnchoredMode::No as i32)
             .i32_eq()
             .if_(BlockType::Empty)
             //  return (nfa.start_anchored(), nfa.is_always_start_anchored(), true);
@@ -139,7 +161,7 @@ impl InputFunctions {
             .end()
             // if anchored == Anchored::Yes {
             .local_get(0)
-            .i32_const(1) // Anchored::Yes
+            .i32_const(AnchoredMode::Yes as i32)
             .i32_eq()
             .if_(BlockType::Empty)
             //  return (nfa.start_anchored(), true, true);
@@ -150,7 +172,7 @@ impl InputFunctions {
             .end()
             // if anchored == Anchored::Pattern {
             .local_get(0)
-            .i32_const(2) // Anchored::Pattern
+            .i32_const(AnchoredMode::Pattern as i32)
             .i32_eq()
             .if_(BlockType::Empty)
             // (pattern_start, is_some) = pattern_lookup_start(anchored_pattern);
@@ -217,7 +239,10 @@ impl InputFunctions {
             // if at_offset >= haystack_len {
             .local_get(2)
             .local_get(1)
-            .i64_ge_u()
+            .i64_ge_u();
+        // Only the offset after the last byte of the haystack takes this branch
+        let branch_hints = vec![unlikely_branch(&body)];
+        body.instructions()
             .if_(BlockType::Empty)
             // return at_offset == haystack_len
             .local_get(2)
@@ -258,12 +283,16 @@ impl InputFunctions {
                 body,
                 locals_name_map,
                 labels_name_map: None,
-                branch_hints: None,
+                branch_hints: Some(branch_hints),
             },
         }
     }
 
-    fn prepare_input_fn(page_size: usize, input_layout: &InputLayout) -> Function {
+    fn prepare_input_fn(
+        page_size: usize,
+        max_haystack_len: Option<usize>,
+        input_layout: &InputLayout,
+    ) -> Function {
         let mut locals_name_map = NameMap::new();
         // Parameters
         locals_name_map.append(0, "haystack_len");
@@ -271,6 +300,20 @@ impl InputFunctions {
         locals_name_map.append(1, "num_new_page_required");
 
         let mut body = wasm_encoder::Function::new([(1, ValType::I64)]);
+
+        if let Some(max_haystack_len) = max_haystack_len {
+            body.instructions()
+                // if haystack_len > max_haystack_len {
+                .local_get(0) // haystack_len
+                .u64_const(u64::try_from(max_haystack_len).unwrap())
+                .i64_gt_u()
+                .if_(BlockType::Empty)
+                // return HaystackTooLarge
+                .i32_const(PrepareInputResult::HaystackTooLarge as i32)
+                .return_()
+                .end();
+        }
+
         body.instructions()
             // if haystack_len == 0 {
             .local_get(0) // haystack_len
@@ -319,7 +362,7 @@ impl InputFunctions {
 
         Function {
             sig: FunctionSignature {
-                name: "prepare_input".into(),
+                name: abi::PREPARE_INPUT.into(),
                 // [haystack_len]
                 params_ty: &[ValType::I64],
                 // [prepare_input_result]
@@ -439,4 +482,75 @@ mod tests {
             "Memory size should remain 2 pages"
         );
     }
+
+    #[test]
+    fn prepare_input_max_haystack_len() {
+        let page_size = crate::Config::DEFAULT_PAGE_SIZE;
+        let max_haystack_len = page_size + 1;
+        let mut ctx = CompileContext::new(
+            NFA::always_match(),
+            crate::Config::new()
+                .export_all_functions(true)
+                .max_haystack_len(max_haystack_len),
+        );
+
+        let state_overall = Layout::new::<()>();
+        let (state_overall, pattern_layout) = PatternLayout::new(&mut ctx, state_overall).unwrap();
+        let pattern_functions = PatternFunctions::new(&mut ctx, &pattern_layout);
+
+        let input_layout = InputLayout::new(&mut ctx).unwrap();
+        let _input_functions =
+            InputFunctions::new(&mut ctx, &input_layout, pattern_functions.lookup_start);
+
+        let module = ctx.compile(&state_overall).unwrap();
+        let module_bytes = RegexBytecode::from_bytes_unchecked(module.finish());
+        let mut regex =
+            crate::engines::wasmi::Executor::with_engine(::wasmi::Engine::default(), &module_bytes)
+                .unwrap();
+        let haystack_memory = regex
+            .instance()
+            .get_memory(regex.store(), "haystack")
+            .unwrap();
+        let prepare_input = regex
+            .instance()
+            .get_typed_func::<i64, i32>(regex.store(), "prepare_input")
+            .unwrap();
+
+        assert_eq!(
+            haystack_memory.ty(regex.store()).maximum(),
+            Some(2),
+            "maximum should be enough pages to fit the max haystack length"
+        );
+
+        let res = prepare_input
+            .call(regex.store_mut(), i64::try_from(max_haystack_len).unwrap())
+            .unwrap();
+        assert_eq!(res, PrepareInputResult::SuccessGrowth as i32);
+        assert_eq!(haystack_memory.size(regex.store()), 2);
+
+        let res = prepare_input
+            .call(
+                regex.store_mut(),
+                i64::try_from(max_haystack_len + 1).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(res, PrepareInputResult::HaystackTooLarge as i32);
+        assert_eq!(
+            haystack_memory.size(regex.store()),
+            2,
+            "memory should not grow when haystack is too large"
+        );
+    }
+
+    #[test]
+    fn prepare_input_max_haystack_len_unbounded() {
+        let nfa = NFA::new("a").unwrap();
+        let config = crate::Config::new().max_haystack_len(usize::MAX);
+        let bytecode = crate::compile::compile_from_nfa(nfa, None, config).unwrap();
+        wasmparser::validate(bytecode.as_ref()).unwrap();
+
+        let mut regex = crate::engines::wasmi::Regex::new(&bytecode).unwrap();
+        assert!(regex.is_match(regex_automata::Input::new("bab")));
+        assert!(!regex.is_match(regex_automata::Input::new("bbb")));
+    }
 }
@@ -1,22 +1,26 @@
 //! This module contains types and functions related to the actual Regex
 //! execution of `is_match`, `find`, `captures`, etc.
 
-use wasm_encoder::{BlockType, NameMap, ValType};
+use wasm_encoder::{BlockType, ConstExpr, GlobalType, NameMap, ValType};
 
-use crate::compile::instructions::InstructionSinkExt;
+use crate::{
+    abi::{self, IsMatchResult},
+    compile::instructions::InstructionSinkExt,
+};
 
 use super::{
     context::{
         BlockSignature, CompileContext, Function, FunctionDefinition, FunctionIdx,
-        FunctionSignature, TypeIdx,
+        FunctionSignature, Global, GlobalIdx, unlikely_branch,
     },
     input::{InputFunctions, InputLayout},
     state::{StateFunctions, StateLayout},
+    trace::TraceImports,
 };
 
 #[derive(Debug)]
 pub struct MatchingFunctions {
-    _is_match: FunctionIdx,
+    pub is_match: FunctionIdx,
 }
 
 impl MatchingFunctions {
@@ -26,41 +30,58 @@ impl MatchingFunctions {
         state_funcs: &StateFunctions,
         input_layout: &InputLayout,
         input_funcs: &InputFunctions,
+        trace_imports: Option<&TraceImports>,
     ) -> Self {
-        let start_config_is_some_block_sig = ctx.add_block_signature(BlockSignature {
-            name: "start_config_is_some",
-            params_ty: &[ValType::I32, ValType::I32],
-            results_ty: &[ValType::I32, ValType::I32],
-        });
-
-        let is_match_block_sig = ctx.add_block_signature(BlockSignature {
-            name: "make_current_transitions_is_match",
-            params_ty: &[ValType::I32],
-            results_ty: &[],
+        let step_budget = ctx.config.get_step_counter().then(|| {
+            ctx.add_global(Global {
+                name: abi::STEP_BUDGET_GLOBAL.into(),
+                ty: GlobalType {
+                    val_type: ValType::I64,
+                    mutable: true,
+                    shared: false,
+                },
+                // All bits set, so that a host which never sets a budget is effectively
+                // unlimited
+                init: ConstExpr::i64_const(-1),
+                export: true,
+            })
         });
 
-        let is_match = ctx.add_function(Self::is_match_fn(
+        let is_match_fn = Self::is_match_fn(
+            ctx,
             state_layout,
             state_funcs,
             input_layout,
             input_funcs,
-            start_config_is_some_block_sig,
-            is_match_block_sig,
-        ));
+            step_budget,
+            trace_imports.map(|imports| imports.trace),
+        );
+        let is_match = ctx.add_function(is_match_fn);
 
-        Self {
-            _is_match: is_match,
-        }
+        Self { is_match }
     }
 
     fn is_match_fn(
+        ctx: &mut CompileContext,
         state_layout: &StateLayout,
         state_funcs: &StateFunctions,
         input_layout: &InputLayout,
         input_funcs: &InputFunctions,
-        start_config_is_some_block_sig: TypeIdx,
-        is_match_block_sig: TypeIdx,
+        step_budget: Option<GlobalIdx>,
+        trace: Option<FunctionIdx>,
     ) -> Function {
+        let start_config_is_some_block_sig = ctx.add_block_signature(BlockSignature {
+            name: "start_config_is_some",
+            params_ty: &[ValType::I32, ValType::I32],
+            results_ty: &[ValType::I32, ValType::I32],
+        });
+
+        let is_match_block_sig = ctx.add_block_signature(BlockSignature {
+            name: "make_current_transitions_is_match",
+            params_ty: &[ValType::I32],
+            results_ty: &[],
+        });
+
         let mut locals_name_map = NameMap::new();
         // Parameters
         locals_name_map.append(0, "anchored");
@@ -97,6 +118,12 @@ impl MatchingFunctions {
         //         return false;
         //     }
         //
+        //     // Only present if the step counter is enabled
+        //     if step_budget == 0 {
+        //         return budget_exceeded;
+        //     }
+        //     step_budget = step_budget - 1;
+        //
         //     if curr_set_len == 0 && is_anchored && at_offset > span_start {
         //         return false;
         //     }
@@ -106,6 +133,10 @@ impl MatchingFunctions {
         //     }
         //
         //     new_next_set_len, is_match = make_current_transitions(haystack_ptr, haystack_len, at_offset, curr_set_ptr, curr_set_len, next_set_ptr, next_set_len)
+        //
+        //     // Only present if tracing is enabled
+        //     trace(at_offset, curr_set_ptr, curr_set_len);
+        //
         //     if is_match && utf8_is_boundary(haystack_ptr, haystack_len, at_offset) {
         //         return true;
         //     }
@@ -131,7 +162,7 @@ impl MatchingFunctions {
             // return false;
             .drop()
             .drop()
-            .bool_const(false)
+            .i32_const(IsMatchResult::NoMatch as i32)
             .return_()
             .end()
             .local_set(11) // is_anchored
@@ -150,12 +181,34 @@ impl MatchingFunctions {
             // if at_offset > span_end {
             .local_get(5) // at_offset
             .local_get(3) // span_end
-            .i64_gt_u()
+            .i64_gt_u();
+        // The search only ends once, while the loop runs for every offset
+        let mut branch_hints = vec![unlikely_branch(&body)];
+        body.instructions()
             .if_(BlockType::Empty)
             // return false;
-            .bool_const(false)
+            .i32_const(IsMatchResult::NoMatch as i32)
             .return_()
-            .end()
+            .end();
+
+        if let Some(step_budget) = step_budget {
+            body.instructions()
+                // if step_budget == 0 {
+                .global_get(step_budget.into())
+                .i64_eqz()
+                .if_(BlockType::Empty)
+                // return budget_exceeded;
+                .i32_const(IsMatchResult::BudgetExceeded as i32)
+                .return_()
+                .end()
+                // step_budget = step_budget - 1;
+                .global_get(step_budget.into())
+                .i64_const(1)
+                .i64_sub()
+                .global_set(step_budget.into());
+        }
+
+        body.instructions()
             // if curr_set_len == 0 && is_anchored && at_offset > span_start {
             .local_get(8) // curr_set_len
             .i32_const(0)
@@ -165,10 +218,12 @@ impl MatchingFunctions {
             .local_get(2) // span_start
             .i64_gt_u()
             .i32_and()
-            .i32_and()
+            .i32_and();
+        branch_hints.push(unlikely_branch(&body));
+        body.instructions()
             .if_(BlockType::Empty)
             // return false;
-            .bool_const(false)
+            .i32_const(IsMatchResult::NoMatch as i32)
             .return_()
             .end()
             // if !is_anchored || at_offset == span_start {
@@ -202,6 +257,15 @@ impl MatchingFunctions {
             .local_get(9) // next_set_len
             .call(state_funcs.transition.make_current_transitions.into());
 
+        if let Some(trace) = trace {
+            body.instructions()
+                // trace(at_offset, curr_set_ptr, curr_set_len);
+                .local_get(5) // at_offset
+                .local_get(6) // curr_set_ptr
+                .local_get(8) // curr_set_len
+                .call(trace.into());
+        }
+
         // stack: [new_next_set_len, is_match]
         // if is_match && utf8_is_boundary(haystack_ptr, haystack_len, at_offset)
 
@@ -217,10 +281,11 @@ impl MatchingFunctions {
                 .i32_and();
         }
 
+        branch_hints.push(unlikely_branch(&body));
         body.instructions()
             .if_(BlockType::FunctionType(is_match_block_sig.into()))
             .drop()
-            .bool_const(true)
+            .i32_const(IsMatchResult::Match as i32)
             .return_()
             .else_()
             // next_set_len = new_next_set_len;
@@ -247,12 +312,12 @@ impl MatchingFunctions {
             .br(0) // continue loop
             .end()
             // } end loop
-            .bool_const(false)
+            .i32_const(IsMatchResult::NoMatch as i32)
             .end();
 
         Function {
             sig: FunctionSignature {
-                name: "is_match".into(),
+                name: abi::IS_MATCH.into(),
                 // [anchored, anchored_pattern, span_start, span_end, haystack_len]
                 params_ty: &[
                     ValType::I32,
@@ -269,7 +334,7 @@ impl MatchingFunctions {
                 body,
                 locals_name_map,
                 labels_name_map: Some(labels_name_map),
-                branch_hints: None,
+                branch_hints: Some(branch_hints),
             },
         }
     }
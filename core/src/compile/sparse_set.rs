@@ -20,7 +20,7 @@ and based my implementation off the same file.
 
 use std::alloc::{Layout, LayoutError};
 
-use wasm_encoder::{BlockType, NameMap, ValType};
+use wasm_encoder::{BlockType, InstructionSink, NameMap, ValType};
 
 use super::{
     CompileContext,
@@ -106,6 +106,7 @@ pub struct SparseSetFunctions {
     #[expect(dead_code)]
     pub contains: FunctionIdx,
     pub insert: FunctionIdx,
+    pub insert_many: FunctionIdx,
 }
 
 impl SparseSetFunctions {
@@ -114,8 +115,13 @@ impl SparseSetFunctions {
     pub fn new(ctx: &mut CompileContext, layout: &SparseSetLayout) -> Self {
         let contains = ctx.add_function(Self::contains_fn(layout));
         let insert = ctx.add_function(Self::insert_fn(layout, contains));
+        let insert_many = ctx.add_function(Self::insert_many_fn(layout));
 
-        Self { contains, insert }
+        Self {
+            contains,
+            insert,
+            insert_many,
+        }
     }
 
     /// Returns a WASM function that will check whether a given state ID is
@@ -263,6 +269,213 @@ impl SparseSetFunctions {
             },
         }
     }
+
+    /// Returns a WASM function that will insert a run of state IDs, stored in
+    /// the state memory, into this set and return the new length of the set.
+    ///
+    /// The run must not contain any duplicate state IDs. The states before the
+    /// first one that is already in the set are copied to the end of the dense
+    /// array in one go, and only the sparse array is updated one state at a
+    /// time. The remaining states are then checked and inserted one at a time.
+    fn insert_many_fn(layout: &SparseSetLayout) -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Parameters
+        locals_name_map.append(0, "set_len");
+        locals_name_map.append(1, "states_ptr");
+        locals_name_map.append(2, "states_len");
+        locals_name_map.append(3, "set_ptr");
+        // Locals
+        locals_name_map.append(4, "loop_index");
+        locals_name_map.append(5, "state_id");
+        locals_name_map.append(6, "index");
+        locals_name_map.append(7, "copy_len");
+
+        let state_id_size: i64 = layout.state_id_layout.size().try_into().unwrap();
+        let sparse_array_offset: u64 = layout.sparse_array_offset.try_into().unwrap();
+
+        // Pushes `states[loop_index]`
+        let load_state = |instructions: &mut InstructionSink<'_>| {
+            instructions
+                .local_get(4) // loop_index
+                .i64_extend_i32_u()
+                .i64_const(state_id_size)
+                .i64_mul()
+                .local_get(1) // states_ptr
+                .i64_add()
+                .state_id_load(0, &layout.state_id_layout);
+        };
+        // Branches to the given depth, plus the `if` this adds, if `state_id` is
+        // already in the set
+        let br_if_contains = |instructions: &mut InstructionSink<'_>, depth: u32| {
+            instructions
+                // let index = self.sparse[id];
+                .local_get(5) // state_id
+                .i64_extend_i32_u()
+                .i64_const(state_id_size)
+                .i64_mul()
+                .local_get(3) // set_ptr
+                .i64_add()
+                .state_id_load(sparse_array_offset, &layout.state_id_layout)
+                .local_tee(6) // index
+                // if index.as_usize() < self.len() && self.dense[index] == id {
+                .local_get(0) // set_len
+                .i32_lt_u()
+                .if_(BlockType::Empty)
+                .local_get(6) // index
+                .i64_extend_i32_u()
+                .i64_const(state_id_size)
+                .i64_mul()
+                .local_get(3) // set_ptr
+                .i64_add()
+                .state_id_load(0, &layout.state_id_layout)
+                .local_get(5) // state_id
+                .i32_eq()
+                .br_if(depth + 1)
+                .end();
+        };
+
+        let mut body = wasm_encoder::Function::new([(4, ValType::I32)]);
+        let mut instructions = body.instructions();
+        instructions
+            // let copy_len = states.iter().position(|id|
+            // self.contains(id)).unwrap_or(states.len());
+            .block(BlockType::Empty)
+            .loop_(BlockType::Empty)
+            .local_get(4) // loop_index
+            .local_get(2) // states_len
+            .i32_ge_u()
+            .br_if(1);
+        load_state(&mut instructions);
+        instructions.local_set(5); // state_id
+        // Depth: 0=`loop`, 1=`block`
+        br_if_contains(&mut instructions, 1);
+        instructions
+            .local_get(4) // loop_index
+            .i32_const(1)
+            .i32_add()
+            .local_set(4) // loop_index
+            .br(0)
+            .end() // end loop
+            .end() // end block
+            .local_get(4) // loop_index
+            .local_set(7) // copy_len
+            // self.dense[len..len + copy_len].copy_from_slice(&states[..copy_len]);
+            .local_get(0) // set_len
+            .i64_extend_i32_u()
+            .i64_const(state_id_size)
+            .i64_mul()
+            .local_get(3) // set_ptr
+            .i64_add()
+            .local_get(1) // states_ptr
+            .local_get(7) // copy_len
+            .i64_extend_i32_u()
+            .i64_const(state_id_size)
+            .i64_mul()
+            .memory_copy(1, 1)
+            // for (i, state_id) in states[..copy_len].enumerate() {
+            //     self.sparse[state_id] = len + i;
+            // }
+            .i32_const(0)
+            .local_set(4) // loop_index
+            .block(BlockType::Empty)
+            .loop_(BlockType::Empty)
+            .local_get(4) // loop_index
+            .local_get(7) // copy_len
+            .i32_ge_u()
+            .br_if(1);
+        load_state(&mut instructions);
+        instructions
+            .i64_extend_i32_u()
+            .i64_const(state_id_size)
+            .i64_mul()
+            .local_get(3) // set_ptr
+            .i64_add()
+            .local_get(0) // set_len
+            .local_get(4) // loop_index
+            .i32_add()
+            .state_id_store(sparse_array_offset, &layout.state_id_layout)
+            .local_get(4) // loop_index
+            .i32_const(1)
+            .i32_add()
+            .local_set(4) // loop_index
+            .br(0)
+            .end() // end loop
+            .end() // end block
+            // len += copy_len;
+            .local_get(0) // set_len
+            .local_get(7) // copy_len
+            .i32_add()
+            .local_set(0) // set_len
+            // for state_id in &states[copy_len..] {
+            //     if !self.contains(state_id) {
+            //         self.dense[len] = state_id;
+            //         self.sparse[state_id] = len;
+            //         len += 1;
+            //     }
+            // }
+            .block(BlockType::Empty)
+            .loop_(BlockType::Empty)
+            .local_get(4) // loop_index
+            .local_get(2) // states_len
+            .i32_ge_u()
+            .br_if(1)
+            .block(BlockType::Empty);
+        load_state(&mut instructions);
+        instructions.local_set(5); // state_id
+        // Depth: 0=skip `block`
+        br_if_contains(&mut instructions, 0);
+        instructions
+            // self.dense[len] = state_id;
+            .local_get(0) // set_len
+            .i64_extend_i32_u()
+            .i64_const(state_id_size)
+            .i64_mul()
+            .local_get(3) // set_ptr
+            .i64_add()
+            .local_get(5) // state_id
+            .state_id_store(0, &layout.state_id_layout)
+            // self.sparse[state_id] = len;
+            .local_get(5) // state_id
+            .i64_extend_i32_u()
+            .i64_const(state_id_size)
+            .i64_mul()
+            .local_get(3) // set_ptr
+            .i64_add()
+            .local_get(0) // set_len
+            .state_id_store(sparse_array_offset, &layout.state_id_layout)
+            // len += 1;
+            .local_get(0) // set_len
+            .i32_const(1)
+            .i32_add()
+            .local_set(0) // set_len
+            .end() // end skip block
+            .local_get(4) // loop_index
+            .i32_const(1)
+            .i32_add()
+            .local_set(4) // loop_index
+            .br(0)
+            .end() // end loop
+            .end() // end block
+            .local_get(0) // set_len
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "sparse_set_insert_many".into(),
+                // [set_len, states_ptr, states_len, set_ptr]
+                params_ty: &[ValType::I32, ValType::I64, ValType::I32, ValType::I64],
+                // [new_set_len]
+                results_ty: &[ValType::I32],
+                export: false,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: None,
+                branch_hints: None,
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -292,7 +505,7 @@ pub mod tests {
 
     pub fn get_sparse_set_fns(
         instance: &wasmi::Instance,
-        store: &wasmi::Store<()>,
+        store: &wasmi::Store<crate::engines::wasmi::HostState>,
     ) -> (
         SetContainsFn, // contains: (ptr, len, id) -> bool
         SetInsertFn,   // insert: (len, id, ptr) -> new_len
@@ -484,6 +697,107 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_insert_many() {
+        let overall = Layout::new::<()>();
+        let state_id_layout = Layout::new::<u32>();
+
+        // Layout one sparse array at offset 0 with 5 states capacity.
+        let (overall, sparse_set_layout) =
+            SparseSetLayout::with_num_states(5, overall, &state_id_layout).unwrap();
+        let module_bytes = compile_test_module(&sparse_set_layout);
+        let module_bytes = RegexBytecode::from_bytes_unchecked(module_bytes);
+        let mut regex =
+            crate::engines::wasmi::Executor::with_engine(::wasmi::Engine::default(), &module_bytes)
+                .unwrap();
+        let (contains, insert) = get_sparse_set_fns(regex.instance(), regex.store());
+        let insert_many = regex
+            .instance()
+            .get_typed_func::<(i32, i64, i32, i64), i32>(regex.store(), "sparse_set_insert_many")
+            .unwrap();
+
+        let state_memory = regex.instance().get_memory(regex.store(), "state").unwrap();
+
+        // Place the runs of states after the set
+        let runs_ptr = overall.size();
+        let runs: [u32; 6] = [3, 1, 4, 4, 0, 2];
+        let runs_bytes = runs
+            .iter()
+            .flat_map(|state_id| state_id.to_le_bytes())
+            .collect::<Vec<_>>();
+        state_memory.data_mut(regex.store_mut())[runs_ptr..(runs_ptr + runs_bytes.len())]
+            .copy_from_slice(&runs_bytes);
+        let run_ptr = |index: usize| i64::try_from(runs_ptr + index * 4).unwrap();
+
+        let set_ptr = 0;
+
+        // None of the states are present, so the whole run is copied
+        let set_len = insert_many
+            .call(regex.store_mut(), (0, run_ptr(0), 3, set_ptr))
+            .unwrap();
+        assert_eq!(set_len, 3);
+
+        // State 4 is already present, so only the others are inserted
+        let set_len = insert_many
+            .call(regex.store_mut(), (set_len, run_ptr(3), 3, set_ptr))
+            .unwrap();
+        assert_eq!(set_len, 5);
+
+        // All the states are present
+        let set_len = insert_many
+            .call(regex.store_mut(), (set_len, run_ptr(0), 6, set_ptr))
+            .unwrap();
+        assert_eq!(set_len, 5);
+
+        for state_id in 0..5 {
+            let res = contains
+                .call(regex.store_mut(), (set_ptr, set_len, state_id))
+                .unwrap();
+            assert_eq!(res, true as i32, "{state_id} should be present");
+        }
+
+        #[rustfmt::skip]
+        assert_eq!(
+            &state_memory.data(regex.store())[..(state_id_layout.size() * 5 * 2)],
+            &[
+                3, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0,
+                3, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0,
+            ]
+        );
+
+        // Copying into a set with stale sparse entries keeps them consistent
+        let set_len = insert.call(regex.store_mut(), (0, 2, set_ptr)).unwrap();
+        let set_len = insert_many
+            .call(regex.store_mut(), (set_len, run_ptr(0), 3, set_ptr))
+            .unwrap();
+        assert_eq!(set_len, 4);
+        for (state_id, expected) in [(0, false), (1, true), (2, true), (3, true), (4, true)] {
+            let res = contains
+                .call(regex.store_mut(), (set_ptr, set_len, state_id))
+                .unwrap();
+            assert_eq!(res, expected as i32, "{state_id} presence");
+        }
+
+        // The states before the first present one are copied, and the ones after it
+        // are inserted one at a time
+        let set_len = insert.call(regex.store_mut(), (0, 1, set_ptr)).unwrap();
+        let set_len = insert_many
+            .call(regex.store_mut(), (set_len, run_ptr(0), 3, set_ptr))
+            .unwrap();
+        assert_eq!(set_len, 3);
+        #[rustfmt::skip]
+        assert_eq!(
+            &state_memory.data(regex.store())[..(state_id_layout.size() * 3)],
+            &[1, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]
+        );
+        for (state_id, expected) in [(0, false), (1, true), (2, false), (3, true), (4, true)] {
+            let res = contains
+                .call(regex.store_mut(), (set_ptr, set_len, state_id))
+                .unwrap();
+            assert_eq!(res, expected as i32, "{state_id} presence");
+        }
+    }
+
     #[test]
     fn test_state_id_larger_than_one_byte() {
         let overall = Layout::new::<()>();
@@ -1,11 +1,15 @@
 //! This module contains type and functions related to the entire runtime state
 //! of the engine.
 
-use std::alloc::{Layout, LayoutError};
+use std::alloc::Layout;
+
+use regex_automata::{nfa::thompson::NFA, util::primitives::StateID};
+
+use crate::inspect;
 
 use super::{
     BuildError, CompileContext,
-    epsilon_closure::EpsilonClosureFunctions,
+    epsilon_closure::{EpsilonClosureFunctions, EpsilonClosureLayout},
     lookaround::{LookFunctions, LookLayout},
     pattern::{PatternFunctions, PatternLayout},
     sparse_set::{SparseSetFunctions, SparseSetLayout},
@@ -20,6 +24,7 @@ pub struct StateLayout {
     /// structures.
     pub overall: Layout,
     transition: TransitionLayout,
+    epsilon_closure: EpsilonClosureLayout,
     pub first_sparse_set: SparseSetLayout,
     pub second_sparse_set: SparseSetLayout,
     pattern: PatternLayout,
@@ -29,12 +34,13 @@ pub struct StateLayout {
 impl StateLayout {
     /// Creates a new `StateLayout` by sequentially arranging layouts for
     /// various components.
-    pub fn new(ctx: &mut CompileContext) -> Result<Self, LayoutError> {
+    pub fn new(ctx: &mut CompileContext) -> Result<Self, BuildError> {
         // Using a ZST to start the layout so that we have minimal alignment
         // requirements
         let overall = Layout::new::<()>();
         let (overall, pattern) = PatternLayout::new(ctx, overall)?;
         let (overall, transition) = TransitionLayout::new(ctx, overall)?;
+        let (overall, epsilon_closure) = EpsilonClosureLayout::new(ctx, overall)?;
         let (overall, look) = LookLayout::new(ctx, overall)?;
         let (overall, first_sparse_set) = SparseSetLayout::new(ctx, overall)?;
         let (overall, second_sparse_set) = SparseSetLayout::new(ctx, overall)?;
@@ -44,6 +50,7 @@ impl StateLayout {
         Ok(Self {
             overall,
             transition,
+            epsilon_closure,
             first_sparse_set,
             second_sparse_set,
             pattern,
@@ -71,7 +78,8 @@ impl StateFunctions {
         // have the same
         let sparse_set = SparseSetFunctions::new(ctx, &layout.first_sparse_set);
         let look_funcs = LookFunctions::new(ctx, &layout.look);
-        let epsilon_closure = EpsilonClosureFunctions::new(ctx, sparse_set.insert, &look_funcs)?;
+        let epsilon_closure =
+            EpsilonClosureFunctions::new(ctx, &layout.epsilon_closure, &sparse_set, &look_funcs)?;
         let transition = TransitionFunctions::new(ctx, &epsilon_closure, &layout.transition);
         let pattern = PatternFunctions::new(ctx, &layout.pattern);
 
@@ -82,4 +90,36 @@ impl StateFunctions {
             pattern,
         })
     }
+
+    /// Describes the functions and lookup tables that were generated for
+    /// every state of the NFA.
+    ///
+    /// The `function_names` are those returned by
+    /// [`CompileContext::compile_with_function_names`].
+    pub fn inspect(
+        &self,
+        nfa: &NFA,
+        layout: &StateLayout,
+        function_names: &[String],
+    ) -> Vec<inspect::StateInfo> {
+        (0..nfa.states().len())
+            .map(StateID::new)
+            .map(Result::unwrap)
+            .map(|sid| {
+                let (transition_fn, inlined_closure) = self.transition.inspect(sid, function_names);
+                inspect::StateInfo {
+                    id: sid,
+                    state: nfa.state(sid).clone(),
+                    epsilon_closure: self.epsilon_closure.inspect(
+                        sid,
+                        &layout.epsilon_closure,
+                        function_names,
+                    ),
+                    transition_fn,
+                    inlined_closure,
+                    lookup_table: layout.transition.inspect(sid),
+                }
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,47 @@
+//! This module contains types and functions related to reporting the active
+//! NFA states to the host, see [`Config::trace`][crate::Config::trace].
+
+use wasm_encoder::{ConstExpr, GlobalType, ValType};
+
+use crate::abi;
+
+use super::context::{CompileContext, FunctionIdx, FunctionTypeSignature, Global};
+
+/// Holds the index of the function imported from the host to receive the
+/// active NFA states.
+#[derive(Debug)]
+pub struct TraceImports {
+    pub trace: FunctionIdx,
+}
+
+impl TraceImports {
+    /// Declares the trace import, and the global which tells the host how to
+    /// decode the state IDs.
+    ///
+    /// This must be called before any other function is declared.
+    pub fn new(ctx: &mut CompileContext) -> Self {
+        let trace = ctx.add_function_import(
+            abi::TRACE_MODULE,
+            &FunctionTypeSignature {
+                name: abi::TRACE_FUNCTION,
+                // [at_offset, set_ptr, set_len]
+                params_ty: &[ValType::I64, ValType::I64, ValType::I32],
+                results_ty: &[],
+            },
+        );
+
+        let state_id_size = i32::try_from(ctx.state_id_layout().size()).unwrap();
+        ctx.add_global(Global {
+            name: abi::STATE_ID_SIZE_GLOBAL.into(),
+            ty: GlobalType {
+                val_type: ValType::I32,
+                mutable: false,
+                shared: false,
+            },
+            init: ConstExpr::i32_const(state_id_size),
+            export: true,
+        });
+
+        Self { trace }
+    }
+}
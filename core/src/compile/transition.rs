@@ -11,15 +11,15 @@ use regex_automata::{
     nfa::thompson::{DenseTransitions, NFA, SparseTransitions, State, Transition},
     util::primitives::StateID,
 };
-use wasm_encoder::{BlockType, InstructionSink, MemArg, NameMap, ValType};
+use wasm_encoder::{BlockType, BranchHint, InstructionSink, MemArg, NameMap, ValType};
 
-use crate::compile::context::FunctionTypeSignature;
+use crate::{compile::context::FunctionTypeSignature, inspect};
 
 use super::{
     CompileContext,
     context::{
         ActiveDataSegment, BlockSignature, Function, FunctionDefinition, FunctionIdx,
-        FunctionSignature, TypeIdx,
+        FunctionSignature, TypeIdx, unlikely_branch,
     },
     epsilon_closure::EpsilonClosureFunctions,
     instructions::InstructionSinkExt,
@@ -201,6 +201,21 @@ impl TransitionLayout {
     fn get(&self, sid: StateID) -> Option<LookupTable> {
         self.lookup_tables.get(&sid).copied()
     }
+
+    /// Describes the lookup table that was laid out for the given state.
+    pub fn inspect(&self, sid: StateID) -> inspect::LookupTable {
+        match self.get(sid) {
+            Some(LookupTable::Sparse(table)) => inspect::LookupTable::Sparse {
+                len: table.range_table_len,
+            },
+            Some(LookupTable::Dense(_)) => inspect::LookupTable::Dense,
+            None => inspect::LookupTable::None,
+        }
+    }
+}
+
+fn transition_fn_name(sid: StateID) -> String {
+    format!("transition_s{}", sid.as_usize())
 }
 
 fn flatten_sparse_transition(
@@ -245,8 +260,10 @@ fn flatten_dense_transition(dense: &[StateID], state_id_layout: &Layout) -> Vec<
 /// [`PikeVM`][regex_automata::nfa::thompson::pikevm::PikeVM].
 #[derive(Debug)]
 pub struct TransitionFunctions {
-    #[expect(dead_code)]
     state_transitions: BTreeMap<StateID, FunctionIdx>,
+    /// The states of the epsilon closure that each transition function
+    /// inserts directly, instead of branching to the closure function.
+    inlined_closures: BTreeMap<StateID, Vec<StateID>>,
     #[expect(dead_code)]
     branch_to_transition: FunctionIdx,
     pub make_current_transitions: FunctionIdx,
@@ -262,6 +279,7 @@ impl TransitionFunctions {
     ) -> Self {
         // NOTE: The indexes of the `states` array correspond to the `StateID` value.
         let mut state_transitions = BTreeMap::new();
+        let mut inlined_closures = BTreeMap::new();
 
         let transition_fn_type = ctx.declare_fn_type(&FunctionTypeSignature {
             name: "transition",
@@ -283,20 +301,23 @@ impl TransitionFunctions {
                 continue;
             }
 
-            let transition_fn_def = Self::transition_fn(
+            let (transition_fn_def, inlined_closure) = Self::transition_fn(
                 for_sid,
                 ctx.nfa.states(),
-                epsilon_closures.branch_to_epsilon_closure,
+                epsilon_closures,
                 transition_layout.get(for_sid),
                 ctx.state_id_layout(),
             );
             let transition_idx = ctx.declare_function_with_type(
                 transition_fn_type,
-                &format!("transition_s{}", for_sid.as_usize()),
+                &transition_fn_name(for_sid),
                 false,
             );
             ctx.define_function(transition_idx, transition_fn_def);
             state_transitions.insert(for_sid, transition_idx);
+            if let Some(inlined_closure) = inlined_closure {
+                inlined_closures.insert(for_sid, inlined_closure);
+            }
         }
 
         let branch_to_transition = if !state_transitions.is_empty() {
@@ -322,6 +343,7 @@ impl TransitionFunctions {
 
         Self {
             state_transitions,
+            inlined_closures,
             branch_to_transition,
             make_current_transitions,
         }
@@ -643,10 +665,10 @@ impl TransitionFunctions {
     fn transition_fn(
         for_sid: StateID,
         states: &[State],
-        branch_to_epsilon_closure: FunctionIdx,
+        epsilon_closures: &EpsilonClosureFunctions,
         lookup_table: Option<LookupTable>,
         state_id_layout: &Layout,
-    ) -> FunctionDefinition {
+    ) -> (FunctionDefinition, Option<Vec<StateID>>) {
         let mut locals_name_map = NameMap::new();
         // Parameters
         locals_name_map.append(0, "haystack_ptr");
@@ -703,7 +725,8 @@ impl TransitionFunctions {
             &states[for_sid.as_usize()],
             &mut locals_name_map,
         ));
-        let mut instructions = body.instructions();
+        let mut branch_hints = Vec::new();
+        let mut inlined_closure = None;
         match &states[for_sid.as_usize()] {
             State::Fail
             | State::Look { .. }
@@ -717,45 +740,82 @@ impl TransitionFunctions {
                 );
             },
             State::ByteRange { trans } => {
+                let mut instructions = body.instructions();
                 Self::non_terminal_transition_prefix(&mut instructions);
                 Self::byte_range_transition_body(&mut instructions, trans);
-                Self::non_terminal_transition_suffix(&mut instructions, branch_to_epsilon_closure);
+                // The next state is known here, so small closures can skip the branch
+                if let Some(closure) = epsilon_closures.get_inline(trans.next) {
+                    Self::inline_epsilon_closure_suffix(
+                        &mut instructions,
+                        closure,
+                        epsilon_closures.sparse_set_insert,
+                    );
+                    inlined_closure = Some(closure.to_vec());
+                } else {
+                    Self::non_terminal_transition_suffix(
+                        &mut instructions,
+                        epsilon_closures.branch_to_epsilon_closure,
+                    );
+                }
             },
             State::Sparse(_) => {
                 // We don't need the transition data here, since we've already emitted the
                 // lookup tables
                 let sparse_table = lookup_table.unwrap().unwrap_sparse();
-                Self::non_terminal_transition_prefix(&mut instructions);
+                Self::non_terminal_transition_prefix(&mut body.instructions());
                 Self::sparse_transition_body(
-                    &mut instructions,
+                    &mut body,
                     sparse_table,
                     &mut labels_name_map,
+                    &mut branch_hints,
                     state_id_layout,
                 );
-                Self::non_terminal_transition_suffix(&mut instructions, branch_to_epsilon_closure);
+                Self::non_terminal_transition_suffix(
+                    &mut body.instructions(),
+                    epsilon_closures.branch_to_epsilon_closure,
+                );
             },
             State::Dense(_) => {
                 // We don't need the transition data here, since we've already emitted the
                 // lookup tables
                 let dense_table = lookup_table.unwrap().unwrap_dense();
+                let mut instructions = body.instructions();
                 Self::non_terminal_transition_prefix(&mut instructions);
                 Self::dense_transition_body(&mut instructions, dense_table, state_id_layout);
-                Self::non_terminal_transition_suffix(&mut instructions, branch_to_epsilon_closure);
+                Self::non_terminal_transition_suffix(
+                    &mut instructions,
+                    epsilon_closures.branch_to_epsilon_closure,
+                );
             },
             State::Match { .. } => {
                 // TODO: Need to update for pattern matches
                 // return Some(...)
-                instructions.local_get(4).bool_const(true);
+                body.instructions().local_get(4).bool_const(true);
             },
         }
-        instructions.end();
+        body.instructions().end();
 
-        FunctionDefinition {
+        let def = FunctionDefinition {
             body,
             locals_name_map,
             labels_name_map: Some(labels_name_map),
-            branch_hints: None,
-        }
+            branch_hints: Some(branch_hints),
+        };
+        (def, inlined_closure)
+    }
+
+    /// Returns the name of the transition function that was generated for the
+    /// given state, and the epsilon closure that it inserts inline, if any.
+    pub fn inspect(
+        &self,
+        sid: StateID,
+        function_names: &[String],
+    ) -> (Option<String>, Option<Vec<StateID>>) {
+        let transition_fn = self
+            .state_transitions
+            .get(&sid)
+            .map(|func_idx| func_idx.name(function_names).into());
+        (transition_fn, self.inlined_closures.get(&sid).cloned())
     }
 
     /// Return true if the given state needs a transition function.
@@ -823,10 +883,31 @@ impl TransitionFunctions {
             .bool_const(false);
     }
 
-    fn sparse_transition_body(
+    fn inline_epsilon_closure_suffix(
         instructions: &mut InstructionSink<'_>,
+        closure: &[StateID],
+        sparse_set_insert: FunctionIdx,
+    ) {
+        // The closure has no lookaround, so unlike `non_terminal_transition_suffix`
+        // the haystack and offset are not needed
+        instructions.local_get(4); // next_set_len
+        for closure_sid in closure {
+            instructions
+                // new_next_set_len is already on the stack from the previous
+                // sparse_set_insert
+                .u32_const(closure_sid.as_u32())
+                .local_get(3) // next_set_ptr
+                .call(sparse_set_insert.into());
+        }
+        // return None
+        instructions.bool_const(false);
+    }
+
+    fn sparse_transition_body(
+        body: &mut wasm_encoder::Function,
         sparse_table: SparseTable,
         labels_name_map: &mut NameMap,
+        branch_hints: &mut Vec<BranchHint>,
         state_id_layout: &Layout,
     ) {
         // Range table is laid out as `[(start: u8, end: u8), ...]`
@@ -861,7 +942,7 @@ impl TransitionFunctions {
         // }
         // ... continue to epsilon
 
-        instructions
+        body.instructions()
             .i32_const(0)
             .local_set(7) // loop_index
             // This block is needed so that we can break out of the loop
@@ -873,7 +954,11 @@ impl TransitionFunctions {
                 u32::try_from(sparse_table.range_table_len)
                     .expect("table length should fit within u32"),
             )
-            .i32_ge_u()
+            .i32_ge_u();
+        // Both misses leave the loop, so they are taken at most once while the
+        // loop may run for every range in the table
+        branch_hints.push(unlikely_branch(body));
+        body.instructions()
             .if_(BlockType::Empty)
             // return None
             .local_get(4) // next_set_len
@@ -885,15 +970,18 @@ impl TransitionFunctions {
             .i64_extend_i32_u()
             .u64_const(u64::try_from(sparse_table.range_lookup_table_stride).unwrap())
             .i64_mul()
+            // start is at offset 0
             .i32_load8_u(MemArg {
-                offset: u64::try_from(sparse_table.range_table_pos).unwrap(), // start is at offset 0
+                offset: u64::try_from(sparse_table.range_table_pos).unwrap(),
                 align: 0,
                 memory_index: 1,
             })
             .local_tee(8) // transition_start
             // if start > byte {
             .local_get(5) // byte
-            .i32_gt_u()
+            .i32_gt_u();
+        branch_hints.push(unlikely_branch(body));
+        body.instructions()
             .if_(BlockType::Empty)
             // return None
             .local_get(4) // next_set_len
@@ -906,8 +994,9 @@ impl TransitionFunctions {
             .i64_extend_i32_u()
             .u64_const(u64::try_from(sparse_table.range_lookup_table_stride).unwrap())
             .i64_mul()
+            // end is at offset 1
             .i32_load8_u(MemArg {
-                offset: u64::try_from(sparse_table.range_table_pos).unwrap() + 1, // end is at offset 1
+                offset: u64::try_from(sparse_table.range_table_pos).unwrap() + 1,
                 align: 0,
                 memory_index: 1,
             })
@@ -1001,6 +1090,7 @@ mod tests {
     use crate::{
         RegexBytecode,
         compile::{
+            epsilon_closure::EpsilonClosureLayout,
             lookaround::{LookFunctions, LookLayout},
             sparse_set::{SparseSetFunctions, SparseSetLayout, tests::get_sparse_set_fns},
         },
@@ -1011,12 +1101,14 @@ mod tests {
     fn branch_to_transition_test_closure(
         nfa: NFA,
         haystack: &[u8],
+        config: crate::Config,
     ) -> impl FnMut(i32, usize, &[u8], bool) + '_ {
         let mut ctx = CompileContext::new(
             nfa,
             crate::Config::new()
                 .export_all_functions(true)
-                .export_state(true),
+                .export_state(true)
+                .overwrite(config),
         );
 
         // We're going to assume all states use less then u8::MAX states
@@ -1027,9 +1119,15 @@ mod tests {
         let sparse_set_functions = SparseSetFunctions::new(&mut ctx, &sparse_set_layout);
         let (overall, look_layout) = LookLayout::new(&mut ctx, overall).unwrap();
         let look_funcs = LookFunctions::new(&mut ctx, &look_layout);
-        let epsilon_closures =
-            EpsilonClosureFunctions::new(&mut ctx, sparse_set_functions.insert, &look_funcs)
-                .unwrap();
+        let (overall, epsilon_closure_layout) =
+            EpsilonClosureLayout::new(&mut ctx, overall).unwrap();
+        let epsilon_closures = EpsilonClosureFunctions::new(
+            &mut ctx,
+            &epsilon_closure_layout,
+            &sparse_set_functions,
+            &look_funcs,
+        )
+        .unwrap();
         let (overall, transition_layout) = TransitionLayout::new(&mut ctx, overall).unwrap();
         let _transition_functions =
             TransitionFunctions::new(&mut ctx, &epsilon_closures, &transition_layout);
@@ -1112,7 +1210,7 @@ mod tests {
         //      000008: MATCH(0)
         let nfa = NFA::new("(?:abc)+").unwrap();
 
-        let mut test = branch_to_transition_test_closure(nfa, b"abc");
+        let mut test = branch_to_transition_test_closure(nfa, b"abc", crate::Config::new());
 
         // State 0:
         test(0, 0, &[], false);
@@ -1172,7 +1270,7 @@ mod tests {
         //      000008: MATCH(0)
         let nfa = NFA::new("ac|bc|dc|e|g").unwrap();
 
-        let mut test = branch_to_transition_test_closure(nfa, b"acbcdceg");
+        let mut test = branch_to_transition_test_closure(nfa, b"acbcdceg", crate::Config::new());
 
         // State 0: binary-union(2, 1)
         for offset in [0, 2, 4, 6, 7] {
@@ -1233,7 +1331,7 @@ mod tests {
         //  000013: MATCH(0)
         let nfa = NFA::new("^hell worm$").unwrap();
 
-        let mut test = branch_to_transition_test_closure(nfa, b"hell worm");
+        let mut test = branch_to_transition_test_closure(nfa, b"hell worm", crate::Config::new());
 
         test(0, 0, &[], false);
         test(1, 0, &[], false);
@@ -1251,6 +1349,35 @@ mod tests {
         test(13, 0, &[], true);
     }
 
+    #[test]
+    fn branch_to_inlined_transition() {
+        let config = crate::Config::new().inline_epsilon_closure_threshold(4);
+
+        // See `branch_to_normal_transition` for the NFA
+        let nfa = NFA::new("(?:abc)+").unwrap();
+        let mut test = branch_to_transition_test_closure(nfa, b"abc", config);
+
+        // Closure of state 0 has 4 states
+        test(1, 0, &[0, 1, 2, 3], false);
+        test(3, 0, &[4], false);
+        test(3, 1, &[], false);
+        test(4, 1, &[5], false);
+        // Closure of state 6 has 4 states
+        test(5, 2, &[3, 6, 7, 8], false);
+        test(5, 1, &[], false);
+        test(8, 0, &[], true);
+
+        // See `branch_to_simple_lookaround_transitions` for the NFA
+        let nfa = NFA::new("^hell worm$").unwrap();
+        let mut test = branch_to_transition_test_closure(nfa, b"hell worm", config);
+
+        test(2, 0, &[3], false);
+        test(9, 7, &[10], false);
+        // Closure of state 11 has a lookaround, so it is not inlined
+        test(10, 8, &[11, 12, 13], false);
+        test(10, 7, &[], false);
+    }
+
     // It seems like `DenseTransitions` are not constructed in the internal
     // `regex-automata` code
 
@@ -1272,13 +1399,19 @@ mod tests {
         let (overall, current_set_layout) = SparseSetLayout::new(&mut ctx, overall).unwrap();
         let (overall, next_set_layout) = SparseSetLayout::new(&mut ctx, overall).unwrap();
         let (overall, look_layout) = LookLayout::new(&mut ctx, overall).unwrap();
+        let (overall, epsilon_closure_layout) =
+            EpsilonClosureLayout::new(&mut ctx, overall).unwrap();
 
         let sparse_set_functions = SparseSetFunctions::new(&mut ctx, &current_set_layout);
         let look_funcs = LookFunctions::new(&mut ctx, &look_layout);
 
-        let epsilon_closures =
-            EpsilonClosureFunctions::new(&mut ctx, sparse_set_functions.insert, &look_funcs)
-                .unwrap();
+        let epsilon_closures = EpsilonClosureFunctions::new(
+            &mut ctx,
+            &epsilon_closure_layout,
+            &sparse_set_functions,
+            &look_funcs,
+        )
+        .unwrap();
 
         let (overall, transition_layout) = TransitionLayout::new(&mut ctx, overall).unwrap();
         let _transition_functions =
@@ -0,0 +1,1432 @@
+//! This module contains types and functions related to compiling a standalone
+//! WASI command that filters lines of text, see
+//! [`Config::wasi_grep`][crate::Config::wasi_grep].
+//!
+//! The command reads stdin into a buffer in its own 32-bit memory, since WASI
+//! only accepts pointers into 32-bit memories. Each complete line is then
+//! copied into the haystack memory and searched using the same
+//! `prepare_input` and `is_match` functions that a host would call.
+
+use wasm_encoder::{BlockType, ConstExpr, GlobalType, InstructionSink, MemArg, NameMap, ValType};
+
+use crate::{
+    abi::{AnchoredMode, IsMatchResult, PrepareInputResult},
+    compile::instructions::InstructionSinkExt,
+};
+
+use super::{
+    context::{
+        CompileContext, Function, FunctionDefinition, FunctionIdx, FunctionSignature,
+        FunctionTypeSignature, Global, GlobalIdx,
+    },
+    input::{InputFunctions, InputLayout},
+    matching::MatchingFunctions,
+};
+
+/// The name of the module that the WASI functions are imported from.
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+/// The name of the exported memory that the WASI host reads from and writes
+/// to.
+pub const WASI_MEMORY: &str = "memory";
+
+/// The index of the WASI memory, after the haystack and state memories.
+pub const WASI_MEMORY_INDEX: u32 = 2;
+
+// Layout of the WASI memory. The fixed-size scratch areas come first, and the
+// remainder of the memory is used for the argument strings and then the input
+// buffer.
+
+/// The iovec passed to `fd_read`.
+const READ_IOVEC_PTR: u32 = 0;
+/// The number of bytes read or written by `fd_read` and `fd_write`.
+const RESULT_PTR: u32 = 8;
+/// The number of arguments, written by `args_sizes_get`.
+const ARGC_PTR: u32 = 12;
+/// The size of the argument strings, written by `args_sizes_get`.
+const ARGV_BUF_SIZE_PTR: u32 = 16;
+/// A single newline byte, which is written after every selected line.
+const NEWLINE_PTR: u32 = 20;
+/// Numbers are formatted backwards from this address, and it is followed by
+/// a separator byte.
+const NUMBER_END_PTR: u32 = 64;
+/// The iovecs passed to `fd_write`, room for three.
+const WRITE_IOVECS_PTR: u32 = 72;
+/// The usage message, written when the arguments are invalid.
+const USAGE_PTR: u32 = 96;
+/// The start of the argument pointers and strings, and then the input buffer.
+const BUFFER_START_PTR: u32 = 512;
+
+/// Selects the lines that don't match, set by `-v`.
+const FLAG_INVERT: i32 = 1 << 0;
+/// Only writes the number of selected lines, set by `-c`.
+const FLAG_COUNT: i32 = 1 << 1;
+/// Prefixes each selected line with its line number, set by `-n`.
+const FLAG_LINE_NUMBER: i32 = 1 << 2;
+
+/// The exit code used when an error occurs, which matches `grep`.
+const EXIT_ERROR: i32 = 2;
+
+/// The WASI `errno` returned when a write makes no progress.
+const ERRNO_IO: i32 = 29;
+
+const USAGE: &str = "usage: [-c] [-n] [-v] < input\n  -c  only print a count of selected lines\n  \
+                     -n  prefix each selected line with its line number\n  -v  select lines that \
+                     don't match\n";
+
+/// Holds indices to the functions imported from the WASI host.
+#[derive(Debug)]
+pub struct WasiImports {
+    fd_read: FunctionIdx,
+    fd_write: FunctionIdx,
+    args_get: FunctionIdx,
+    args_sizes_get: FunctionIdx,
+    proc_exit: FunctionIdx,
+}
+
+impl WasiImports {
+    /// Declares the WASI imports.
+    ///
+    /// This must be called before any other function is declared.
+    pub fn new(ctx: &mut CompileContext) -> Self {
+        let fd_read = ctx.add_function_import(
+            WASI_MODULE,
+            &FunctionTypeSignature {
+                name: "fd_read",
+                // [fd, iovs, iovs_len, nread]
+                params_ty: &[ValType::I32, ValType::I32, ValType::I32, ValType::I32],
+                // [errno]
+                results_ty: &[ValType::I32],
+            },
+        );
+        let fd_write = ctx.add_function_import(
+            WASI_MODULE,
+            &FunctionTypeSignature {
+                name: "fd_write",
+                // [fd, iovs, iovs_len, nwritten]
+                params_ty: &[ValType::I32, ValType::I32, ValType::I32, ValType::I32],
+                // [errno]
+                results_ty: &[ValType::I32],
+            },
+        );
+        let args_get = ctx.add_function_import(
+            WASI_MODULE,
+            &FunctionTypeSignature {
+                name: "args_get",
+                // [argv, argv_buf]
+                params_ty: &[ValType::I32, ValType::I32],
+                // [errno]
+                results_ty: &[ValType::I32],
+            },
+        );
+        let args_sizes_get = ctx.add_function_import(
+            WASI_MODULE,
+            &FunctionTypeSignature {
+                name: "args_sizes_get",
+                // [argc, argv_buf_size]
+                params_ty: &[ValType::I32, ValType::I32],
+                // [errno]
+                results_ty: &[ValType::I32],
+            },
+        );
+        let proc_exit = ctx.add_function_import(
+            WASI_MODULE,
+            &FunctionTypeSignature {
+                name: "proc_exit",
+                // [exit_code]
+                params_ty: &[ValType::I32],
+                results_ty: &[],
+            },
+        );
+
+        Self {
+            fd_read,
+            fd_write,
+            args_get,
+            args_sizes_get,
+            proc_exit,
+        }
+    }
+}
+
+/// Holds the globals that track the state of the command between lines.
+#[derive(Debug)]
+struct WasiGlobals {
+    flags: GlobalIdx,
+    line_number: GlobalIdx,
+    selected_count: GlobalIdx,
+}
+
+/// Holds indices to the functions that implement the WASI command.
+#[derive(Debug)]
+pub struct WasiFunctions {
+    #[expect(dead_code)]
+    start: FunctionIdx,
+}
+
+impl WasiFunctions {
+    /// Creates and registers the functions of the WASI command, including the
+    /// exported `_start` function.
+    pub fn new(
+        ctx: &mut CompileContext,
+        imports: &WasiImports,
+        input_layout: &InputLayout,
+        input_funcs: &InputFunctions,
+        matching_funcs: &MatchingFunctions,
+    ) -> Self {
+        let mut add_global = |name: &str, val_type: ValType| {
+            ctx.add_global(Global {
+                name: name.into(),
+                ty: GlobalType {
+                    val_type,
+                    mutable: true,
+                    shared: false,
+                },
+                init: match val_type {
+                    ValType::I64 => ConstExpr::i64_const(0),
+                    _ => ConstExpr::i32_const(0),
+                },
+                export: false,
+            })
+        };
+        let globals = WasiGlobals {
+            flags: add_global("wasi_flags", ValType::I32),
+            line_number: add_global("wasi_line_number", ValType::I64),
+            selected_count: add_global("wasi_selected_count", ValType::I64),
+        };
+
+        let page_size = u32::try_from(ctx.config.get_page_size()).unwrap();
+
+        let parse_args = ctx.add_function(Self::parse_args_fn(imports, page_size));
+        let write_number = ctx.add_function(Self::write_number_fn());
+        let write_all = ctx.add_function(Self::write_all_fn(imports));
+        let grep_line = ctx.add_function(Self::grep_line_fn(
+            &globals,
+            input_layout,
+            input_funcs,
+            matching_funcs,
+            write_number,
+            write_all,
+        ));
+        let start = ctx.add_function(Self::start_fn(
+            imports,
+            &globals,
+            page_size,
+            parse_args,
+            write_number,
+            write_all,
+            grep_line,
+        ));
+
+        Self { start }
+    }
+
+    fn parse_args_fn(imports: &WasiImports, page_size: u32) -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Locals
+        locals_name_map.append(0, "argc");
+        locals_name_map.append(1, "required_size");
+        locals_name_map.append(2, "flags");
+        locals_name_map.append(3, "arg_idx");
+        locals_name_map.append(4, "arg_ptr");
+        locals_name_map.append(5, "byte");
+
+        let mut labels_name_map = NameMap::new();
+        labels_name_map.append(0, "args_done");
+        labels_name_map.append(1, "args_loop");
+        labels_name_map.append(2, "arg_done");
+        labels_name_map.append(3, "arg_loop");
+
+        // Sketch:
+        // ```rust
+        // if args_sizes_get(&argc, &argv_buf_size) != 0 {
+        //     return -1;
+        // }
+        // required_size = BUFFER_START + argc * 4 + argv_buf_size;
+        // if required_size > memory_size as u64 {
+        //     if !memory_grow(required_size - memory_size) {
+        //         return -1;
+        //     }
+        // }
+        // let argv = BUFFER_START;
+        // if args_get(argv, argv + argc * 4) != 0 {
+        //     return -1;
+        // }
+        // for arg_idx in 1..argc {
+        //     arg_ptr = argv[arg_idx];
+        //     if arg_ptr[0] != '-' || arg_ptr[1] == 0 {
+        //         return -1;
+        //     }
+        //     for byte in arg_ptr[1..] {
+        //         match byte {
+        //             'v' => flags |= FLAG_INVERT,
+        //             'c' => flags |= FLAG_COUNT,
+        //             'n' => flags |= FLAG_LINE_NUMBER,
+        //             _ => return -1,
+        //         }
+        //     }
+        // }
+        // return flags;
+        // ```
+
+        let mut body = wasm_encoder::Function::new([(6, ValType::I32)]);
+        let mut instructions = body.instructions();
+        instructions
+            // if args_sizes_get(&argc, &argv_buf_size) != 0 {
+            .u32_const(ARGC_PTR)
+            .u32_const(ARGV_BUF_SIZE_PTR)
+            .call(imports.args_sizes_get.into())
+            .if_(BlockType::Empty)
+            // return -1;
+            .i32_const(-1)
+            .return_()
+            .end()
+            // required_size = BUFFER_START + argc * 4 + argv_buf_size;
+            .i32_const(0)
+            .i32_load(wasi_mem_arg(ARGC_PTR, 2))
+            .local_tee(0)
+            .i32_const(4)
+            .i32_mul()
+            .u32_const(BUFFER_START_PTR)
+            .i32_add()
+            .i32_const(0)
+            .i32_load(wasi_mem_arg(ARGV_BUF_SIZE_PTR, 2))
+            .i32_add()
+            .local_set(1)
+            // if required_size > memory_size as u64 {
+            .local_get(1)
+            .i64_extend_i32_u()
+            .memory_size(WASI_MEMORY_INDEX)
+            .i64_extend_i32_u()
+            .i64_const(i64::from(page_size))
+            .i64_mul()
+            .i64_gt_u()
+            .if_(BlockType::Empty)
+            // if !memory_grow(((required_size - 1) / page_size) + 1 - memory_pages) {
+            .local_get(1)
+            .i32_const(1)
+            .i32_sub()
+            .u32_const(page_size)
+            .i32_div_u()
+            .i32_const(1)
+            .i32_add()
+            .memory_size(WASI_MEMORY_INDEX)
+            .i32_sub()
+            .memory_grow(WASI_MEMORY_INDEX)
+            .i32_const(-1)
+            .i32_eq()
+            .if_(BlockType::Empty)
+            // return -1;
+            .i32_const(-1)
+            .return_()
+            .end()
+            .end()
+            // if args_get(argv, argv + argc * 4) != 0 {
+            .u32_const(BUFFER_START_PTR)
+            .local_get(0)
+            .i32_const(4)
+            .i32_mul()
+            .u32_const(BUFFER_START_PTR)
+            .i32_add()
+            .call(imports.args_get.into())
+            .if_(BlockType::Empty)
+            // return -1;
+            .i32_const(-1)
+            .return_()
+            .end()
+            // The first argument is the program name
+            .i32_const(1)
+            .local_set(3)
+            .block(BlockType::Empty) // args_done
+            .loop_(BlockType::Empty) // args_loop
+            // if arg_idx >= argc { break; }
+            .local_get(3)
+            .local_get(0)
+            .i32_ge_u()
+            .br_if(1)
+            // arg_ptr = argv[arg_idx];
+            .local_get(3)
+            .i32_const(4)
+            .i32_mul()
+            .i32_load(wasi_mem_arg(BUFFER_START_PTR, 2))
+            .local_tee(4)
+            // if arg_ptr[0] != '-' || arg_ptr[1] == 0 {
+            .i32_load8_u(wasi_mem_arg(0, 0))
+            .i32_const(i32::from(b'-'))
+            .i32_ne()
+            .local_get(4)
+            .i32_load8_u(wasi_mem_arg(1, 0))
+            .i32_eqz()
+            .i32_or()
+            .if_(BlockType::Empty)
+            // return -1;
+            .i32_const(-1)
+            .return_()
+            .end()
+            .block(BlockType::Empty) // arg_done
+            .loop_(BlockType::Empty) // arg_loop
+            // arg_ptr += 1;
+            .local_get(4)
+            .i32_const(1)
+            .i32_add()
+            .local_tee(4)
+            // if byte == 0 { break; }
+            .i32_load8_u(wasi_mem_arg(0, 0))
+            .local_tee(5)
+            .i32_eqz()
+            .br_if(1);
+        for (flag_byte, flag) in [
+            (b'v', FLAG_INVERT),
+            (b'c', FLAG_COUNT),
+            (b'n', FLAG_LINE_NUMBER),
+        ] {
+            instructions
+                // if byte == flag_byte {
+                .local_get(5)
+                .i32_const(i32::from(flag_byte))
+                .i32_eq()
+                .if_(BlockType::Empty)
+                // flags |= flag;
+                .local_get(2)
+                .i32_const(flag)
+                .i32_or()
+                .local_set(2)
+                // continue;
+                .br(1)
+                .end();
+        }
+        instructions
+            // Unknown flag
+            .i32_const(-1)
+            .return_()
+            .end() // arg_loop
+            .end() // arg_done
+            // arg_idx += 1;
+            .local_get(3)
+            .i32_const(1)
+            .i32_add()
+            .local_set(3)
+            .br(0)
+            .end() // args_loop
+            .end() // args_done
+            // return flags;
+            .local_get(2)
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "wasi_parse_args".into(),
+                params_ty: &[],
+                // [flags]
+                results_ty: &[ValType::I32],
+                export: false,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: Some(labels_name_map),
+                branch_hints: None,
+            },
+        }
+    }
+
+    fn write_number_fn() -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Parameters
+        locals_name_map.append(0, "number");
+        // Locals
+        locals_name_map.append(1, "ptr");
+
+        // Sketch:
+        // ```rust
+        // ptr = NUMBER_END;
+        // loop {
+        //     ptr -= 1;
+        //     *ptr = '0' + (number % 10);
+        //     number /= 10;
+        //     if number == 0 { break; }
+        // }
+        // return ptr;
+        // ```
+
+        let mut body = wasm_encoder::Function::new([(1, ValType::I32)]);
+        body.instructions()
+            // ptr = NUMBER_END;
+            .u32_const(NUMBER_END_PTR)
+            .local_set(1)
+            .loop_(BlockType::Empty)
+            // ptr -= 1;
+            .local_get(1)
+            .i32_const(1)
+            .i32_sub()
+            .local_tee(1)
+            // *ptr = '0' + (number % 10);
+            .local_get(0)
+            .i64_const(10)
+            .i64_rem_u()
+            .i32_wrap_i64()
+            .i32_const(i32::from(b'0'))
+            .i32_add()
+            .i32_store8(wasi_mem_arg(0, 0))
+            // number /= 10;
+            .local_get(0)
+            .i64_const(10)
+            .i64_div_u()
+            .local_tee(0)
+            // if number != 0 { continue; }
+            .i64_const(0)
+            .i64_ne()
+            .br_if(0)
+            .end()
+            // return ptr;
+            .local_get(1)
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "wasi_write_number".into(),
+                // [number]
+                params_ty: &[ValType::I64],
+                // [start_ptr]
+                results_ty: &[ValType::I32],
+                export: false,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: None,
+                branch_hints: None,
+            },
+        }
+    }
+
+    fn write_all_fn(imports: &WasiImports) -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Parameters
+        locals_name_map.append(0, "fd");
+        locals_name_map.append(1, "iovs");
+        locals_name_map.append(2, "iovs_len");
+        // Locals
+        locals_name_map.append(3, "written");
+        locals_name_map.append(4, "errno");
+
+        let mut labels_name_map = NameMap::new();
+        labels_name_map.append(0, "write_loop");
+        labels_name_map.append(1, "skip_done");
+        labels_name_map.append(2, "skip_loop");
+
+        // Sketch:
+        // ```rust
+        // written = 0;
+        // loop {
+        //     loop {
+        //         if iovs_len == 0 {
+        //             return 0;
+        //         }
+        //         if written < iovs[0].buf_len {
+        //             break;
+        //         }
+        //         written -= iovs[0].buf_len;
+        //         iovs += 1;
+        //         iovs_len -= 1;
+        //     }
+        //     iovs[0].buf += written;
+        //     iovs[0].buf_len -= written;
+        //     errno = fd_write(fd, iovs, iovs_len, &written);
+        //     if errno != 0 {
+        //         return errno;
+        //     }
+        //     if written == 0 {
+        //         return ERRNO_IO;
+        //     }
+        // }
+        // ```
+        //
+        // The iovecs are modified in place, so they can't be reused
+        // afterwards.
+
+        let mut body = wasm_encoder::Function::new([(2, ValType::I32)]);
+        body.instructions()
+            .loop_(BlockType::Empty) // write_loop
+            .block(BlockType::Empty) // skip_done
+            .loop_(BlockType::Empty) // skip_loop
+            // if iovs_len == 0 {
+            .local_get(2)
+            .i32_eqz()
+            .if_(BlockType::Empty)
+            // return 0;
+            .i32_const(0)
+            .return_()
+            .end()
+            // if written < iovs[0].buf_len { break; }
+            .local_get(3)
+            .local_get(1)
+            .i32_load(wasi_mem_arg(4, 2))
+            .i32_lt_u()
+            .br_if(1)
+            // written -= iovs[0].buf_len;
+            .local_get(3)
+            .local_get(1)
+            .i32_load(wasi_mem_arg(4, 2))
+            .i32_sub()
+            .local_set(3)
+            // iovs += 1;
+            .local_get(1)
+            .i32_const(8)
+            .i32_add()
+            .local_set(1)
+            // iovs_len -= 1;
+            .local_get(2)
+            .i32_const(1)
+            .i32_sub()
+            .local_set(2)
+            .br(0)
+            .end() // skip_loop
+            .end() // skip_done
+            // iovs[0].buf += written;
+            .local_get(1)
+            .local_get(1)
+            .i32_load(wasi_mem_arg(0, 2))
+            .local_get(3)
+            .i32_add()
+            .i32_store(wasi_mem_arg(0, 2))
+            // iovs[0].buf_len -= written;
+            .local_get(1)
+            .local_get(1)
+            .i32_load(wasi_mem_arg(4, 2))
+            .local_get(3)
+            .i32_sub()
+            .i32_store(wasi_mem_arg(4, 2))
+            // errno = fd_write(fd, iovs, iovs_len, &written);
+            .local_get(0)
+            .local_get(1)
+            .local_get(2)
+            .u32_const(RESULT_PTR)
+            .call(imports.fd_write.into())
+            .local_tee(4)
+            // if errno != 0 {
+            .if_(BlockType::Empty)
+            // return errno;
+            .local_get(4)
+            .return_()
+            .end()
+            .i32_const(0)
+            .i32_load(wasi_mem_arg(RESULT_PTR, 2))
+            .local_tee(3)
+            // if written == 0 {
+            .i32_eqz()
+            .if_(BlockType::Empty)
+            // return ERRNO_IO;
+            .i32_const(ERRNO_IO)
+            .return_()
+            .end()
+            .br(0)
+            .end() // write_loop
+            .unreachable()
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "wasi_write_all".into(),
+                // [fd, iovs, iovs_len]
+                params_ty: &[ValType::I32, ValType::I32, ValType::I32],
+                // [errno]
+                results_ty: &[ValType::I32],
+                export: false,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: Some(labels_name_map),
+                branch_hints: None,
+            },
+        }
+    }
+
+    fn grep_line_fn(
+        globals: &WasiGlobals,
+        input_layout: &InputLayout,
+        input_funcs: &InputFunctions,
+        matching_funcs: &MatchingFunctions,
+        write_number: FunctionIdx,
+        write_all: FunctionIdx,
+    ) -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Parameters
+        locals_name_map.append(0, "line_ptr");
+        locals_name_map.append(1, "line_len");
+        // Locals
+        locals_name_map.append(2, "is_match_result");
+        locals_name_map.append(3, "prefix_ptr");
+        locals_name_map.append(4, "prefix_len");
+
+        // Sketch:
+        // ```rust
+        // line_number += 1;
+        // if prepare_input(line_len) == HaystackTooLarge {
+        //     return EXIT_ERROR;
+        // }
+        // haystack[..line_len] = line_ptr[..line_len];
+        // is_match_result = is_match(Anchored::No, 0, 0, line_len, line_len);
+        // if is_match_result == BudgetExceeded {
+        //     return EXIT_ERROR;
+        // }
+        // if (is_match_result == Match) == (flags & FLAG_INVERT != 0) {
+        //     return 0;
+        // }
+        // selected_count += 1;
+        // if flags & FLAG_COUNT != 0 {
+        //     return 0;
+        // }
+        // if flags & FLAG_LINE_NUMBER != 0 {
+        //     prefix_ptr = write_number(line_number);
+        //     *NUMBER_END = ':';
+        //     prefix_len = NUMBER_END + 1 - prefix_ptr;
+        // }
+        // if write_all(STDOUT, [prefix, line, newline]) != 0 {
+        //     return EXIT_ERROR;
+        // }
+        // return 0;
+        // ```
+
+        let haystack_start_pos = u64::try_from(input_layout.haystack_start_pos).unwrap();
+        let mut body = wasm_encoder::Function::new([(3, ValType::I32)]);
+        body.instructions()
+            // line_number += 1;
+            .global_get(globals.line_number.into())
+            .i64_const(1)
+            .i64_add()
+            .global_set(globals.line_number.into())
+            // if prepare_input(line_len) == HaystackTooLarge {
+            .local_get(1)
+            .i64_extend_i32_u()
+            .call(input_funcs.prepare_input.into())
+            .i32_const(PrepareInputResult::HaystackTooLarge as i32)
+            .i32_eq()
+            .if_(BlockType::Empty)
+            // return EXIT_ERROR;
+            .i32_const(EXIT_ERROR)
+            .return_()
+            .end()
+            // haystack[..line_len] = line_ptr[..line_len];
+            .u64_const(haystack_start_pos)
+            .local_get(0)
+            .local_get(1)
+            .memory_copy(0, WASI_MEMORY_INDEX)
+            // is_match_result = is_match(Anchored::No, 0, 0, line_len, line_len);
+            .i32_const(AnchoredMode::No as i32)
+            .i32_const(0)
+            .i64_const(0)
+            .local_get(1)
+            .i64_extend_i32_u()
+            .local_get(1)
+            .i64_extend_i32_u()
+            .call(matching_funcs.is_match.into())
+            .local_tee(2)
+            // if is_match_result == BudgetExceeded {
+            .i32_const(IsMatchResult::BudgetExceeded as i32)
+            .i32_eq()
+            .if_(BlockType::Empty)
+            // return EXIT_ERROR;
+            .i32_const(EXIT_ERROR)
+            .return_()
+            .end()
+            // if (is_match_result == Match) == (flags & FLAG_INVERT != 0) {
+            .local_get(2)
+            .i32_const(IsMatchResult::Match as i32)
+            .i32_eq()
+            .global_get(globals.flags.into())
+            .i32_const(FLAG_INVERT)
+            .i32_and()
+            .i32_const(0)
+            .i32_ne()
+            .i32_eq()
+            .if_(BlockType::Empty)
+            // return 0;
+            .i32_const(0)
+            .return_()
+            .end()
+            // selected_count += 1;
+            .global_get(globals.selected_count.into())
+            .i64_const(1)
+            .i64_add()
+            .global_set(globals.selected_count.into())
+            // if flags & FLAG_COUNT != 0 {
+            .global_get(globals.flags.into())
+            .i32_const(FLAG_COUNT)
+            .i32_and()
+            .if_(BlockType::Empty)
+            // return 0;
+            .i32_const(0)
+            .return_()
+            .end()
+            // if flags & FLAG_LINE_NUMBER != 0 {
+            .global_get(globals.flags.into())
+            .i32_const(FLAG_LINE_NUMBER)
+            .i32_and()
+            .if_(BlockType::Empty)
+            // prefix_ptr = write_number(line_number);
+            .global_get(globals.line_number.into())
+            .call(write_number.into())
+            .local_set(3)
+            // *NUMBER_END = ':';
+            .i32_const(0)
+            .i32_const(i32::from(b':'))
+            .i32_store8(wasi_mem_arg(NUMBER_END_PTR, 0))
+            // prefix_len = NUMBER_END + 1 - prefix_ptr;
+            .u32_const(NUMBER_END_PTR + 1)
+            .local_get(3)
+            .i32_sub()
+            .local_set(4)
+            .end()
+            // iovecs = [prefix, line, newline];
+            .i32_const(0)
+            .local_get(3)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR, 2))
+            .i32_const(0)
+            .local_get(4)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 4, 2))
+            .i32_const(0)
+            .local_get(0)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 8, 2))
+            .i32_const(0)
+            .local_get(1)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 12, 2))
+            .i32_const(0)
+            .u32_const(NEWLINE_PTR)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 16, 2))
+            .i32_const(0)
+            .i32_const(1)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 20, 2))
+            // if write_all(STDOUT, iovecs) != 0 {
+            .i32_const(1)
+            .u32_const(WRITE_IOVECS_PTR)
+            .i32_const(3)
+            .call(write_all.into())
+            .if_(BlockType::Empty)
+            // return EXIT_ERROR;
+            .i32_const(EXIT_ERROR)
+            .return_()
+            .end()
+            // return 0;
+            .i32_const(0)
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "wasi_grep_line".into(),
+                // [line_ptr, line_len]
+                params_ty: &[ValType::I32, ValType::I32],
+                // [exit_code]
+                results_ty: &[ValType::I32],
+                export: false,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: None,
+                branch_hints: None,
+            },
+        }
+    }
+
+    fn start_fn(
+        imports: &WasiImports,
+        globals: &WasiGlobals,
+        page_size: u32,
+        parse_args: FunctionIdx,
+        write_number: FunctionIdx,
+        write_all: FunctionIdx,
+        grep_line: FunctionIdx,
+    ) -> Function {
+        let mut locals_name_map = NameMap::new();
+        // Locals
+        locals_name_map.append(0, "buffer_len");
+        locals_name_map.append(1, "line_start");
+        locals_name_map.append(2, "scan_pos");
+        locals_name_map.append(3, "is_eof");
+        locals_name_map.append(4, "tmp");
+
+        let mut labels_name_map = NameMap::new();
+        labels_name_map.append(0, "read_done");
+        labels_name_map.append(1, "read_loop");
+        labels_name_map.append(2, "scan_done");
+        labels_name_map.append(3, "scan_loop");
+
+        // Sketch:
+        // ```rust
+        // *NEWLINE = '\n';
+        // tmp = parse_args();
+        // if tmp < 0 {
+        //     write_all(STDERR, USAGE);
+        //     proc_exit(EXIT_ERROR);
+        // }
+        // flags = tmp;
+        // loop {
+        //     if BUFFER_START + buffer_len as u64 == memory_size as u64 {
+        //         memory_grow(memory_size);
+        //     }
+        //     nread = fd_read(STDIN, &buffer[buffer_len..]);
+        //     is_eof = nread == 0;
+        //     buffer_len += nread;
+        //     while scan_pos < buffer_len {
+        //         if buffer[scan_pos] == '\n' {
+        //             grep_line(&buffer[line_start..scan_pos]);
+        //             line_start = scan_pos + 1;
+        //         }
+        //         scan_pos += 1;
+        //     }
+        //     if is_eof {
+        //         if line_start < buffer_len {
+        //             grep_line(&buffer[line_start..buffer_len]);
+        //         }
+        //         break;
+        //     }
+        //     if line_start != 0 {
+        //         buffer.copy_within(line_start..buffer_len, 0);
+        //     }
+        //     buffer_len -= line_start;
+        //     scan_pos = buffer_len;
+        //     line_start = 0;
+        // }
+        // if flags & FLAG_COUNT != 0 {
+        //     write_all(STDOUT, format!("{selected_count}\n"));
+        // }
+        // proc_exit(selected_count == 0);
+        // ```
+        //
+        // Every call that fails exits with `EXIT_ERROR`.
+
+        let exit_on_error = |instructions: &mut InstructionSink<'_>| {
+            instructions
+                .if_(BlockType::Empty)
+                .i32_const(EXIT_ERROR)
+                .call(imports.proc_exit.into())
+                .return_()
+                .end();
+        };
+
+        let mut body = wasm_encoder::Function::new([(5, ValType::I32)]);
+        let mut instructions = body.instructions();
+        instructions
+            // *NEWLINE = '\n';
+            .i32_const(0)
+            .i32_const(i32::from(b'\n'))
+            .i32_store8(wasi_mem_arg(NEWLINE_PTR, 0))
+            // tmp = parse_args();
+            .call(parse_args.into())
+            .local_tee(4)
+            // if tmp < 0 {
+            .i32_const(0)
+            .i32_lt_s()
+            .if_(BlockType::Empty);
+        // USAGE = "...";
+        for (idx, chunk) in USAGE.as_bytes().chunks(8).enumerate() {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            instructions
+                .i32_const(0)
+                .i64_const(i64::from_le_bytes(bytes))
+                .i64_store(wasi_mem_arg(USAGE_PTR + u32::try_from(idx * 8).unwrap(), 0));
+        }
+        instructions
+            // write_all(STDERR, USAGE);
+            .i32_const(0)
+            .u32_const(USAGE_PTR)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR, 2))
+            .i32_const(0)
+            .u32_const(u32::try_from(USAGE.len()).unwrap())
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 4, 2))
+            .i32_const(2)
+            .u32_const(WRITE_IOVECS_PTR)
+            .i32_const(1)
+            .call(write_all.into())
+            .drop()
+            // proc_exit(EXIT_ERROR);
+            .i32_const(EXIT_ERROR)
+            .call(imports.proc_exit.into())
+            .return_()
+            .end()
+            // flags = tmp;
+            .local_get(4)
+            .global_set(globals.flags.into())
+            .block(BlockType::Empty) // read_done
+            .loop_(BlockType::Empty) // read_loop
+            // if BUFFER_START + buffer_len as u64 == memory_size as u64 {
+            .local_get(0)
+            .i64_extend_i32_u()
+            .u64_const(u64::from(BUFFER_START_PTR))
+            .i64_add()
+            .memory_size(WASI_MEMORY_INDEX)
+            .i64_extend_i32_u()
+            .i64_const(i64::from(page_size))
+            .i64_mul()
+            .i64_eq()
+            .if_(BlockType::Empty)
+            // memory_grow(memory_size);
+            .memory_size(WASI_MEMORY_INDEX)
+            .memory_grow(WASI_MEMORY_INDEX)
+            .i32_const(-1)
+            .i32_eq();
+        exit_on_error(&mut instructions);
+        instructions
+            .end()
+            // nread = fd_read(STDIN, &buffer[buffer_len..]);
+            .i32_const(0)
+            .u32_const(BUFFER_START_PTR)
+            .local_get(0)
+            .i32_add()
+            .i32_store(wasi_mem_arg(READ_IOVEC_PTR, 2))
+            // The length is computed as a 64-bit value, since a full 32-bit
+            // memory is 2^32 bytes
+            .i32_const(0)
+            .memory_size(WASI_MEMORY_INDEX)
+            .i64_extend_i32_u()
+            .i64_const(i64::from(page_size))
+            .i64_mul()
+            .u64_const(u64::from(BUFFER_START_PTR))
+            .i64_sub()
+            .local_get(0)
+            .i64_extend_i32_u()
+            .i64_sub()
+            .i32_wrap_i64()
+            .i32_store(wasi_mem_arg(READ_IOVEC_PTR + 4, 2))
+            .i32_const(0)
+            .u32_const(READ_IOVEC_PTR)
+            .i32_const(1)
+            .u32_const(RESULT_PTR)
+            .call(imports.fd_read.into());
+        exit_on_error(&mut instructions);
+        instructions
+            // is_eof = nread == 0;
+            .i32_const(0)
+            .i32_load(wasi_mem_arg(RESULT_PTR, 2))
+            .local_tee(4)
+            .i32_eqz()
+            .local_set(3)
+            // buffer_len += nread;
+            .local_get(0)
+            .local_get(4)
+            .i32_add()
+            .local_set(0)
+            .block(BlockType::Empty) // scan_done
+            .loop_(BlockType::Empty) // scan_loop
+            // while scan_pos < buffer_len {
+            .local_get(2)
+            .local_get(0)
+            .i32_ge_u()
+            .br_if(1)
+            // if buffer[scan_pos] == '\n' {
+            .local_get(2)
+            .i32_load8_u(wasi_mem_arg(BUFFER_START_PTR, 0))
+            .i32_const(i32::from(b'\n'))
+            .i32_eq()
+            .if_(BlockType::Empty)
+            // grep_line(&buffer[line_start..scan_pos]);
+            .u32_const(BUFFER_START_PTR)
+            .local_get(1)
+            .i32_add()
+            .local_get(2)
+            .local_get(1)
+            .i32_sub()
+            .call(grep_line.into());
+        exit_on_error(&mut instructions);
+        instructions
+            // line_start = scan_pos + 1;
+            .local_get(2)
+            .i32_const(1)
+            .i32_add()
+            .local_set(1)
+            .end()
+            // scan_pos += 1;
+            .local_get(2)
+            .i32_const(1)
+            .i32_add()
+            .local_set(2)
+            .br(0)
+            .end() // scan_loop
+            .end() // scan_done
+            // if is_eof {
+            .local_get(3)
+            .if_(BlockType::Empty)
+            // if line_start < buffer_len {
+            .local_get(1)
+            .local_get(0)
+            .i32_lt_u()
+            .if_(BlockType::Empty)
+            // grep_line(&buffer[line_start..buffer_len]);
+            .u32_const(BUFFER_START_PTR)
+            .local_get(1)
+            .i32_add()
+            .local_get(0)
+            .local_get(1)
+            .i32_sub()
+            .call(grep_line.into());
+        exit_on_error(&mut instructions);
+        instructions
+            .end()
+            // break;
+            .br(2)
+            .end()
+            // if line_start != 0 {
+            .local_get(1)
+            .if_(BlockType::Empty)
+            // buffer.copy_within(line_start..buffer_len, 0);
+            .u32_const(BUFFER_START_PTR)
+            .u32_const(BUFFER_START_PTR)
+            .local_get(1)
+            .i32_add()
+            .local_get(0)
+            .local_get(1)
+            .i32_sub()
+            .memory_copy(WASI_MEMORY_INDEX, WASI_MEMORY_INDEX)
+            .end()
+            // buffer_len -= line_start;
+            .local_get(0)
+            .local_get(1)
+            .i32_sub()
+            .local_tee(0)
+            // scan_pos = buffer_len;
+            .local_set(2)
+            // line_start = 0;
+            .i32_const(0)
+            .local_set(1)
+            .br(0)
+            .end() // read_loop
+            .end() // read_done
+            // if flags & FLAG_COUNT != 0 {
+            .global_get(globals.flags.into())
+            .i32_const(FLAG_COUNT)
+            .i32_and()
+            .if_(BlockType::Empty)
+            // write_all(STDOUT, format!("{selected_count}\n"));
+            .global_get(globals.selected_count.into())
+            .call(write_number.into())
+            .local_set(4)
+            .i32_const(0)
+            .i32_const(i32::from(b'\n'))
+            .i32_store8(wasi_mem_arg(NUMBER_END_PTR, 0))
+            .i32_const(0)
+            .local_get(4)
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR, 2))
+            .i32_const(0)
+            .u32_const(NUMBER_END_PTR + 1)
+            .local_get(4)
+            .i32_sub()
+            .i32_store(wasi_mem_arg(WRITE_IOVECS_PTR + 4, 2))
+            .i32_const(1)
+            .u32_const(WRITE_IOVECS_PTR)
+            .i32_const(1)
+            .call(write_all.into());
+        exit_on_error(&mut instructions);
+        instructions
+            .end()
+            // proc_exit(selected_count == 0);
+            .global_get(globals.selected_count.into())
+            .i64_eqz()
+            .call(imports.proc_exit.into())
+            .end();
+
+        Function {
+            sig: FunctionSignature {
+                name: "_start".into(),
+                params_ty: &[],
+                results_ty: &[],
+                export: true,
+            },
+            def: FunctionDefinition {
+                body,
+                locals_name_map,
+                labels_name_map: Some(labels_name_map),
+                branch_hints: None,
+            },
+        }
+    }
+}
+
+/// Returns the [`MemArg`] for an access to the WASI memory with a static
+/// offset and the given alignment, as a power of two.
+fn wasi_mem_arg(offset: u32, align: u32) -> MemArg {
+    MemArg {
+        offset: u64::from(offset),
+        align,
+        memory_index: WASI_MEMORY_INDEX,
+    }
+}
+
+#[cfg(all(test, feature = "wasmi"))]
+mod tests {
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    use crate::{Builder, Config, RegexBytecode};
+
+    use super::*;
+
+    /// The state of the fake WASI host.
+    #[derive(Debug, Default)]
+    struct Host {
+        args: Vec<&'static str>,
+        stdin: Vec<u8>,
+        stdin_pos: usize,
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+        /// The most bytes that a single `fd_write` accepts, if limited.
+        max_write: Option<usize>,
+    }
+
+    fn wasi_memory(caller: &Caller<'_, Host>) -> wasmi::Memory {
+        caller
+            .get_export(WASI_MEMORY)
+            .and_then(|export| export.into_memory())
+            .unwrap()
+    }
+
+    fn read_u32(memory: &[u8], ptr: i32) -> usize {
+        let ptr = usize::try_from(ptr).unwrap();
+        u32::from_le_bytes(memory[ptr..ptr + 4].try_into().unwrap()) as usize
+    }
+
+    fn write_u32(memory: &mut [u8], ptr: usize, value: usize) {
+        memory[ptr..ptr + 4].copy_from_slice(&u32::try_from(value).unwrap().to_le_bytes());
+    }
+
+    /// Runs the `_start` function of the module, returning the exit code,
+    /// stdout, and stderr.
+    fn run(bytecode: &RegexBytecode, args: &[&'static str], stdin: &str) -> (i32, String, String) {
+        run_on_host(
+            bytecode,
+            Host {
+                args: [&["grep"], args].concat(),
+                stdin: stdin.as_bytes().to_vec(),
+                ..Host::default()
+            },
+        )
+    }
+
+    /// Runs the `_start` function of the module on the given host.
+    fn run_on_host(bytecode: &RegexBytecode, host: Host) -> (i32, String, String) {
+        let engine = Engine::default();
+        let module = Module::new(&engine, bytecode).unwrap();
+        let mut store = Store::new(&engine, host);
+        let mut linker = Linker::<Host>::new(&engine);
+        linker
+            .func_wrap(
+                WASI_MODULE,
+                "fd_read",
+                |mut caller: Caller<'_, Host>, fd: i32, iovs: i32, iovs_len: i32, nread: i32| {
+                    assert_eq!((fd, iovs_len), (0, 1));
+                    let memory = wasi_memory(&caller);
+                    let (memory, host) = memory.data_and_store_mut(&mut caller);
+                    let buf_ptr = read_u32(memory, iovs);
+                    let buf_len = read_u32(memory, iovs + 4);
+                    // Read in small chunks to exercise lines split across reads
+                    let len = buf_len.min(5).min(host.stdin.len() - host.stdin_pos);
+                    memory[buf_ptr..buf_ptr + len]
+                        .copy_from_slice(&host.stdin[host.stdin_pos..host.stdin_pos + len]);
+                    host.stdin_pos += len;
+                    write_u32(memory, usize::try_from(nread).unwrap(), len);
+                    0
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                WASI_MODULE,
+                "fd_write",
+                |mut caller: Caller<'_, Host>, fd: i32, iovs: i32, iovs_len: i32, nwritten: i32| {
+                    let memory = wasi_memory(&caller);
+                    let (memory, host) = memory.data_and_store_mut(&mut caller);
+                    let out = match fd {
+                        1 => &mut host.stdout,
+                        2 => &mut host.stderr,
+                        _ => return 8, // EBADF
+                    };
+                    let mut remaining = host.max_write.unwrap_or(usize::MAX);
+                    let mut written = 0;
+                    for idx in 0..iovs_len {
+                        let buf_ptr = read_u32(memory, iovs + idx * 8);
+                        let buf_len = read_u32(memory, iovs + idx * 8 + 4).min(remaining);
+                        out.extend_from_slice(&memory[buf_ptr..buf_ptr + buf_len]);
+                        written += buf_len;
+                        remaining -= buf_len;
+                    }
+                    write_u32(memory, usize::try_from(nwritten).unwrap(), written);
+                    0
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                WASI_MODULE,
+                "args_sizes_get",
+                |mut caller: Caller<'_, Host>, argc: i32, argv_buf_size: i32| {
+                    let memory = wasi_memory(&caller);
+                    let (memory, host) = memory.data_and_store_mut(&mut caller);
+                    let buf_size = host.args.iter().map(|arg| arg.len() + 1).sum();
+                    write_u32(memory, usize::try_from(argc).unwrap(), host.args.len());
+                    write_u32(memory, usize::try_from(argv_buf_size).unwrap(), buf_size);
+                    0
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                WASI_MODULE,
+                "args_get",
+                |mut caller: Caller<'_, Host>, argv: i32, argv_buf: i32| {
+                    let memory = wasi_memory(&caller);
+                    let (memory, host) = memory.data_and_store_mut(&mut caller);
+                    let mut argv = usize::try_from(argv).unwrap();
+                    let mut argv_buf = usize::try_from(argv_buf).unwrap();
+                    for arg in &host.args {
+                        write_u32(memory, argv, argv_buf);
+                        memory[argv_buf..argv_buf + arg.len()].copy_from_slice(arg.as_bytes());
+                        memory[argv_buf + arg.len()] = 0;
+                        argv += 4;
+                        argv_buf += arg.len() + 1;
+                    }
+                    0
+                },
+            )
+            .unwrap()
+            .func_wrap(
+                WASI_MODULE,
+                "proc_exit",
+                |code: i32| -> Result<(), wasmi::Error> { Err(wasmi::Error::i32_exit(code)) },
+            )
+            .unwrap();
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let start = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+        let exit_code = start
+            .call(&mut store, ())
+            .unwrap_err()
+            .i32_exit_status()
+            .unwrap();
+
+        let host = store.into_data();
+        (
+            exit_code,
+            String::from_utf8(host.stdout).unwrap(),
+            String::from_utf8(host.stderr).unwrap(),
+        )
+    }
+
+    fn compile(patterns: &[&str], config: Config) -> RegexBytecode {
+        let (bytecode, _) = Builder::new()
+            .configure(config.wasi_grep(true))
+            .build_many(patterns)
+            .unwrap();
+        bytecode
+    }
+
+    const INPUT: &str = "apple pie\nbanana split\ncherry tart\nblueberry muffin";
+
+    #[test]
+    fn selects_matching_lines() {
+        let bytecode = compile(&["an", "rr"], Config::new());
+
+        assert_eq!(
+            run(&bytecode, &[], INPUT),
+            (
+                0,
+                "banana split\ncherry tart\nblueberry muffin\n".into(),
+                String::new()
+            )
+        );
+        assert_eq!(
+            run(&bytecode, &[], "apple\n"),
+            (1, String::new(), String::new())
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let bytecode = compile(&["^b"], Config::new());
+
+        assert_eq!(run(&bytecode, &["-v"], INPUT).1, "apple pie\ncherry tart\n");
+        assert_eq!(
+            run(&bytecode, &["-n"], INPUT).1,
+            "2:banana split\n4:blueberry muffin\n"
+        );
+        assert_eq!(
+            run(&bytecode, &["-vn"], INPUT).1,
+            "1:apple pie\n3:cherry tart\n"
+        );
+        assert_eq!(
+            run(&bytecode, &["-c"], INPUT),
+            (0, "2\n".into(), String::new())
+        );
+        assert_eq!(
+            run(&bytecode, &["-c", "-v"], "banana"),
+            (1, "0\n".into(), String::new())
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let bytecode = compile(&["a"], Config::new());
+
+        for args in [&["-x"][..], &["-"], &["pattern"]] {
+            let (exit_code, stdout, stderr) = run(&bytecode, args, INPUT);
+            assert_eq!(exit_code, EXIT_ERROR);
+            assert_eq!(stdout, "");
+            assert_eq!(stderr, USAGE);
+        }
+    }
+
+    #[test]
+    fn short_writes() {
+        let bytecode = compile(&["^b"], Config::new());
+        let short_writes = |args: &[&'static str], stdin: &str| {
+            run_on_host(
+                &bytecode,
+                Host {
+                    args: [&["grep"], args].concat(),
+                    stdin: stdin.as_bytes().to_vec(),
+                    max_write: Some(3),
+                    ..Host::default()
+                },
+            )
+        };
+
+        assert_eq!(
+            short_writes(&["-n"], INPUT),
+            (
+                0,
+                "2:banana split\n4:blueberry muffin\n".into(),
+                String::new()
+            )
+        );
+        assert_eq!(
+            short_writes(&["-c"], &"banana\n".repeat(12345)),
+            (0, "12345\n".into(), String::new())
+        );
+        assert_eq!(
+            short_writes(&["-x"], INPUT),
+            (EXIT_ERROR, String::new(), USAGE.into())
+        );
+    }
+
+    #[test]
+    fn long_lines_grow_buffer() {
+        let bytecode = compile(&["z$"], Config::new());
+        let line = "a".repeat(2 * Config::DEFAULT_PAGE_SIZE);
+        let input = format!("{line}z\n{line}\n");
+
+        let (exit_code, stdout, _) = run(&bytecode, &["-n"], &input);
+        assert_eq!(exit_code, 0);
+        assert_eq!(stdout, format!("1:{line}z\n"));
+    }
+
+    #[test]
+    fn haystack_too_large() {
+        let bytecode = compile(&["a"], Config::new().max_haystack_len(4));
+
+        assert_eq!(
+            run(&bytecode, &[], "abc\nabcde\n"),
+            (EXIT_ERROR, "abc\n".into(), String::new())
+        );
+    }
+}
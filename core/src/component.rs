@@ -0,0 +1,451 @@
+//! This module wraps compiled regular expression modules into [WebAssembly
+//! components][component-model], so that they can be called by any component
+//! model host without knowing the [memory-copy protocol][crate::abi].
+//!
+//! The component implements the world described by [`WIT`]. The core module is
+//! embedded unchanged, alongside a small adapter module which owns the 32-bit
+//! memory that the canonical ABI lowers arguments into, and copies the
+//! haystack into the 64-bit haystack memory of the core module.
+//!
+//! Only `is-match` is exposed, since that is the only search that the core
+//! module implements.
+//!
+//! [component-model]: https://component-model.bytecodealliance.org/
+
+use wasm_encoder::{
+    BlockType, CanonicalOption, CodeSection, ComponentBuilder, ComponentExportKind,
+    ComponentTypeRef, ComponentValType, EntityType, ExportKind, ExportSection, Function,
+    FunctionSection, ImportSection, MemorySection, MemoryType, Module, ModuleArg, PrimitiveValType,
+    TypeSection, ValType,
+};
+
+use wasmparser::{Parser, Payload};
+
+use crate::{
+    BuildError, RegexBytecode,
+    abi::{self, IsMatchResult, PrepareInputResult},
+};
+
+/// The WIT definition of the world implemented by components created with
+/// [`wrap`].
+pub const WIT: &str = r#"package wahgex:regex@0.1.0;
+
+world regex {
+    /// Mirrors `regex_automata::Anchored`.
+    variant anchored {
+        no,
+        yes,
+        pattern(u32),
+    }
+
+    /// Mirrors the search options of `regex_automata::Input`.
+    record input-opts {
+        earliest: bool,
+        anchored: anchored,
+    }
+
+    /// Returns true if the haystack contains a match.
+    ///
+    /// Traps if the haystack is longer than the configured maximum haystack
+    /// length, or if the search exhausted its step budget.
+    export is-match: func(haystack: list<u8>, opts: input-opts) -> bool;
+}
+"#;
+
+/// The name of the instance import in the adapter module that is satisfied by
+/// the core regex module.
+const REGEX_IMPORT_MODULE: &str = "regex";
+
+/// Wraps a compiled regular expression module into a component implementing
+/// the [`WIT`] world.
+///
+/// Returns an error if the module imports any functions, since the component
+/// only provides the adapter to it. This rejects modules compiled with
+/// [`Config::trace`][crate::Config::trace] or
+/// [`Config::wasi_grep`][crate::Config::wasi_grep].
+pub fn wrap(bytecode: &RegexBytecode) -> Result<Vec<u8>, BuildError> {
+    for payload in Parser::new(0).parse_all(bytecode.as_ref()) {
+        if let Payload::ImportSection(reader) = payload? {
+            if let Some(import) = reader.into_iter().next() {
+                let import = import?;
+                return Err(BuildError::unsupported_import(import.module, import.name));
+            }
+        }
+    }
+
+    let adapter = adapter_module();
+
+    let mut component = ComponentBuilder::default();
+    let regex_module = component.core_module_raw(bytecode.as_ref());
+    let adapter_module = component.core_module(&adapter);
+
+    let regex_instance = component.core_instantiate(regex_module, []);
+    let adapter_instance = component.core_instantiate(
+        adapter_module,
+        [(REGEX_IMPORT_MODULE, ModuleArg::Instance(regex_instance))],
+    );
+    let core_is_match = component.core_alias_export(adapter_instance, "is-match", ExportKind::Func);
+    let memory = component.core_alias_export(adapter_instance, "memory", ExportKind::Memory);
+    let realloc = component.core_alias_export(adapter_instance, "cabi_realloc", ExportKind::Func);
+
+    // Types used by exported functions must themselves be exported, so each one
+    // is defined in terms of the exported index of the previous
+    let (anchored, encoder) = component.type_defined();
+    encoder.variant([
+        ("no", None, None),
+        ("yes", None, None),
+        ("pattern", Some(PrimitiveValType::U32.into()), None),
+    ]);
+    let anchored = component.export("anchored", ComponentExportKind::Type, anchored, None);
+
+    let (input_opts, encoder) = component.type_defined();
+    encoder.record([
+        ("earliest", PrimitiveValType::Bool.into()),
+        ("anchored", ComponentValType::Type(anchored)),
+    ]);
+    let input_opts = component.export("input-opts", ComponentExportKind::Type, input_opts, None);
+
+    let (haystack, encoder) = component.type_defined();
+    encoder.list(PrimitiveValType::U8);
+
+    let (is_match_ty, mut encoder) = component.type_function();
+    encoder
+        .params([
+            ("haystack", ComponentValType::Type(haystack)),
+            ("opts", ComponentValType::Type(input_opts)),
+        ])
+        .result(Some(PrimitiveValType::Bool.into()));
+
+    let is_match = component.lift_func(
+        core_is_match,
+        is_match_ty,
+        [
+            CanonicalOption::Memory(memory),
+            CanonicalOption::Realloc(realloc),
+        ],
+    );
+    component.export(
+        "is-match",
+        ComponentExportKind::Func,
+        is_match,
+        Some(ComponentTypeRef::Func(is_match_ty)),
+    );
+
+    Ok(component.finish())
+}
+
+/// Builds the core module which sits between the canonical ABI and the core
+/// regex module.
+///
+/// The haystack is lowered into the adapter's memory by the host, and then
+/// copied into the haystack memory of the regex module before searching.
+fn adapter_module() -> Module {
+    let mut types = TypeSection::new();
+    // [haystack_len] -> [prepare_input_result]
+    let prepare_input_ty = types.len();
+    types.ty().function([ValType::I64], [ValType::I32]);
+    // [anchored, anchored_pattern, span_start, span_end, haystack_len] ->
+    // [is_match_result]
+    let is_match_ty = types.len();
+    types.ty().function(
+        [
+            ValType::I32,
+            ValType::I32,
+            ValType::I64,
+            ValType::I64,
+            ValType::I64,
+        ],
+        [ValType::I32],
+    );
+    // [old_ptr, old_size, align, new_size] -> [ptr]
+    let realloc_ty = types.len();
+    types.ty().function([ValType::I32; 4], [ValType::I32]);
+    // [haystack_ptr, haystack_len, earliest, anchored, anchored_pattern] ->
+    // [is_match]
+    let adapter_is_match_ty = types.len();
+    types.ty().function([ValType::I32; 5], [ValType::I32]);
+
+    let mut imports = ImportSection::new();
+    imports.import(
+        REGEX_IMPORT_MODULE,
+        abi::PREPARE_INPUT,
+        EntityType::Function(prepare_input_ty),
+    );
+    imports.import(
+        REGEX_IMPORT_MODULE,
+        abi::IS_MATCH,
+        EntityType::Function(is_match_ty),
+    );
+    imports.import(
+        REGEX_IMPORT_MODULE,
+        abi::HAYSTACK_MEMORY,
+        EntityType::Memory(MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: true,
+            shared: false,
+            page_size_log2: None,
+        }),
+    );
+    let prepare_input_func = 0;
+    let is_match_func = 1;
+    let haystack_memory = 0;
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    let adapter_memory = 1;
+
+    let mut functions = FunctionSection::new();
+    functions.function(realloc_ty);
+    functions.function(adapter_is_match_ty);
+    let realloc_func = 2;
+    let adapter_is_match_func = 3;
+
+    let mut exports = ExportSection::new();
+    exports.export("memory", ExportKind::Memory, adapter_memory);
+    exports.export("cabi_realloc", ExportKind::Func, realloc_func);
+    exports.export("is-match", ExportKind::Func, adapter_is_match_func);
+
+    let mut codes = CodeSection::new();
+    codes.function(&realloc_fn(adapter_memory));
+    codes.function(&adapter_is_match_fn(
+        prepare_input_func,
+        is_match_func,
+        haystack_memory,
+        adapter_memory,
+    ));
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&functions)
+        .section(&memories)
+        .section(&exports)
+        .section(&codes);
+    module
+}
+
+/// Builds the `cabi_realloc` function used by the host to allocate space for
+/// the haystack.
+///
+/// Only a single allocation is ever live, so this always returns a pointer to
+/// the start of memory, after growing it to fit.
+fn realloc_fn(adapter_memory: u32) -> Function {
+    // Parameters: [old_ptr, old_size, align, new_size]
+    // Locals: [additional_pages]
+
+    // Sketch:
+    // ```
+    // additional_pages = ((new_size + page_size - 1) / page_size) - memory.size;
+    // if additional_pages > 0 {
+    //     if memory.grow(additional_pages) == -1 {
+    //         unreachable;
+    //     }
+    // }
+    // return 0;
+    // ```
+
+    let page_size_log2 = crate::Config::DEFAULT_PAGE_SIZE.trailing_zeros();
+
+    let mut body = Function::new([(1, ValType::I32)]);
+    body.instructions()
+        // additional_pages = ((new_size + page_size - 1) / page_size) - memory.size;
+        // Computed in 64 bits so that the rounding can't overflow
+        .local_get(3) // new_size
+        .i64_extend_i32_u()
+        .i64_const(i64::try_from(crate::Config::DEFAULT_PAGE_SIZE - 1).unwrap())
+        .i64_add()
+        .i64_const(i64::from(page_size_log2))
+        .i64_shr_u()
+        .i32_wrap_i64()
+        .memory_size(adapter_memory)
+        .i32_sub()
+        .local_tee(4) // additional_pages
+        // if additional_pages > 0 {
+        .i32_const(0)
+        .i32_gt_s()
+        .if_(BlockType::Empty)
+        // if memory.grow(additional_pages) == -1 {
+        .local_get(4)
+        .memory_grow(adapter_memory)
+        .i32_const(-1)
+        .i32_eq()
+        .if_(BlockType::Empty)
+        .unreachable()
+        .end()
+        .end()
+        // return 0;
+        .i32_const(0)
+        .end();
+    body
+}
+
+/// Builds the `is-match` function which is lifted into the component export.
+fn adapter_is_match_fn(
+    prepare_input_func: u32,
+    is_match_func: u32,
+    haystack_memory: u32,
+    adapter_memory: u32,
+) -> Function {
+    // Parameters: [haystack_ptr, haystack_len, earliest, anchored,
+    // anchored_pattern] Locals: [is_match_result]
+
+    // Sketch:
+    // ```
+    // if prepare_input(haystack_len) == HaystackTooLarge {
+    //     unreachable;
+    // }
+    // memory.copy(haystack[0..], adapter[haystack_ptr..], haystack_len);
+    // is_match_result = is_match(anchored, anchored_pattern, 0, haystack_len, haystack_len);
+    // if is_match_result == BudgetExceeded {
+    //     unreachable;
+    // }
+    // return is_match_result;
+    // ```
+    //
+    // The `earliest` option is ignored, since `is_match` always stops at the
+    // earliest match.
+
+    let mut body = Function::new([(1, ValType::I32)]);
+    body.instructions()
+        // if prepare_input(haystack_len) == HaystackTooLarge {
+        .local_get(1) // haystack_len
+        .i64_extend_i32_u()
+        .call(prepare_input_func)
+        .i32_const(PrepareInputResult::HaystackTooLarge as i32)
+        .i32_eq()
+        .if_(BlockType::Empty)
+        .unreachable()
+        .end()
+        // memory.copy(haystack[0..], adapter[haystack_ptr..], haystack_len);
+        .i64_const(0)
+        .local_get(0) // haystack_ptr
+        .local_get(1) // haystack_len
+        .memory_copy(haystack_memory, adapter_memory)
+        // is_match_result = is_match(anchored, anchored_pattern, 0, haystack_len,
+        // haystack_len);
+        .local_get(3) // anchored
+        .local_get(4) // anchored_pattern
+        .i64_const(0)
+        .local_get(1) // haystack_len
+        .i64_extend_i32_u()
+        .local_get(1) // haystack_len
+        .i64_extend_i32_u()
+        .call(is_match_func)
+        .local_tee(5) // is_match_result
+        // if is_match_result == BudgetExceeded {
+        .i32_const(IsMatchResult::BudgetExceeded as i32)
+        .i32_eq()
+        .if_(BlockType::Empty)
+        .unreachable()
+        .end()
+        // return is_match_result;
+        .local_get(5)
+        .end();
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmparser::{ComponentExternalKind, Validator};
+
+    use crate::{Builder, Config, engines::wasmi::Executor};
+
+    use super::*;
+
+    #[test]
+    fn component_is_valid() {
+        let (bytecode, _) = Builder::new().build("a+b").unwrap();
+        let component = wrap(&bytecode).unwrap();
+
+        Validator::new().validate_all(&component).unwrap();
+
+        let mut exports = Vec::new();
+        for payload in Parser::new(0).parse_all(&component) {
+            if let Payload::ComponentExportSection(reader) = payload.unwrap() {
+                for export in reader {
+                    let export = export.unwrap();
+                    exports.push((export.name.0, export.kind));
+                }
+            }
+        }
+        assert_eq!(
+            exports,
+            [
+                ("anchored", ComponentExternalKind::Type),
+                ("input-opts", ComponentExternalKind::Type),
+                ("is-match", ComponentExternalKind::Func),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_imports() {
+        for config in [Config::new().trace(true), Config::new().wasi_grep(true)] {
+            let (bytecode, _) = Builder::new().configure(config).build("a+b").unwrap();
+            let err = wrap(&bytecode).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("can't be provided inside of a component")
+            );
+        }
+    }
+
+    #[test]
+    fn adapter_is_match() {
+        let (bytecode, _) = Builder::new().build("a+b").unwrap();
+        let adapter = RegexBytecode::from_bytes_unchecked(adapter_module().finish());
+
+        // Link the adapter against the regex module at the core level, the same
+        // way the component instantiates them
+        let engine = wasmi::Engine::default();
+        let mut regex = Executor::with_engine(engine.clone(), &bytecode).unwrap();
+        let adapter = wasmi::Module::new(&engine, adapter.as_ref()).unwrap();
+        let mut linker = wasmi::Linker::new(&engine);
+        for export in [abi::PREPARE_INPUT, abi::IS_MATCH, abi::HAYSTACK_MEMORY] {
+            let item = regex.instance().get_export(regex.store(), export).unwrap();
+            linker.define(REGEX_IMPORT_MODULE, export, item).unwrap();
+        }
+        let adapter = linker
+            .instantiate(regex.store_mut(), &adapter)
+            .unwrap()
+            .start(regex.store_mut())
+            .unwrap();
+
+        let memory = adapter.get_memory(regex.store(), "memory").unwrap();
+        let realloc = adapter
+            .get_typed_func::<(i32, i32, i32, i32), i32>(regex.store(), "cabi_realloc")
+            .unwrap();
+        let is_match = adapter
+            .get_typed_func::<(i32, i32, i32, i32, i32), i32>(regex.store(), "is-match")
+            .unwrap();
+
+        let mut search = |haystack: &[u8], anchored: abi::AnchoredMode| {
+            let len = i32::try_from(haystack.len()).unwrap();
+            let ptr = realloc.call(regex.store_mut(), (0, 0, 1, len)).unwrap();
+            memory
+                .write(regex.store_mut(), usize::try_from(ptr).unwrap(), haystack)
+                .unwrap();
+            is_match
+                .call(regex.store_mut(), (ptr, len, 0, anchored as i32, 0))
+                .unwrap()
+        };
+
+        assert_eq!(search(b"xxaab", abi::AnchoredMode::No), 1);
+        assert_eq!(search(b"xxaab", abi::AnchoredMode::Yes), 0);
+        assert_eq!(search(b"aab", abi::AnchoredMode::Yes), 1);
+        assert_eq!(search(b"xxaa", abi::AnchoredMode::No), 0);
+
+        // Larger than a single page of both memories
+        let mut haystack = vec![b'x'; 3 * crate::Config::DEFAULT_PAGE_SIZE];
+        haystack.extend_from_slice(b"ab");
+        assert_eq!(search(&haystack, abi::AnchoredMode::No), 1);
+    }
+}
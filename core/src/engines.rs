@@ -1,5 +1,38 @@
 //! This module contains other modules related to running [`wahgex`][crate]
 //! on specific WASM engines.
 
+use regex_automata::util::primitives::StateID;
+
 #[cfg(feature = "wasmi")]
 pub mod wasmi;
+
+#[cfg(feature = "web")]
+pub mod web;
+
+/// The NFA states that were active at a single offset of a search, see
+/// [`Config::trace`][crate::Config::trace].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The offset in the haystack.
+    pub at_offset: usize,
+    /// The active NFA states, in the order they were added to the set.
+    pub states: Vec<StateID>,
+}
+
+/// Decodes a traced set of states, where each state ID is a little-endian
+/// integer that is `state_id_size` bytes wide.
+///
+/// Returns `None` if the state IDs are not valid.
+fn decode_trace_set(bytes: &[u8], state_id_size: usize) -> Option<Vec<StateID>> {
+    if !matches!(state_id_size, 1 | 2 | 4) {
+        return None;
+    }
+    bytes
+        .chunks_exact(state_id_size)
+        .map(|bytes| {
+            let mut le_bytes = [0; 4];
+            le_bytes[..bytes.len()].copy_from_slice(bytes);
+            StateID::new(usize::try_from(u32::from_le_bytes(le_bytes)).ok()?).ok()
+        })
+        .collect()
+}
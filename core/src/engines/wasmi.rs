@@ -2,39 +2,113 @@
 //! using [`wasmi`].
 
 pub use wasmi::Engine;
-use wasmi::{Instance, Linker, Memory, Module, Store, TypedFunc};
+use wasmi::{Caller, Extern, Global, Instance, Linker, Memory, Module, Store, TypedFunc, Val};
 
-use crate::{RegexBytecode, common_input_validation, input::InputOpts};
+use super::{TraceStep, decode_trace_set};
+use crate::{
+    MatchError, RegexBytecode,
+    abi::{self, IsMatchResult, PrepareInputResult},
+    common_input_validation,
+    input::InputOpts,
+};
 
 #[derive(Debug)]
 pub(crate) struct Executor {
-    _engine: Engine,
-    _module: Module,
-    store: Store<()>,
+    engine: Engine,
+    module: Module,
+    store: Store<HostState>,
     instance: Instance,
 }
 
+/// The data owned by the store, which the imported functions write to.
+#[derive(Debug, Default)]
+pub(crate) struct HostState {
+    /// The steps recorded by the `trace` import during the current search.
+    trace: Vec<TraceStep>,
+}
+
 impl Executor {
     /// Creates a new `Executor` with the given `wasmi` engine and
     /// `RegexBytecode`.
     pub fn with_engine(engine: Engine, bytecode: &RegexBytecode) -> Result<Self, wasmi::Error> {
         let module = Module::new(&engine, bytecode)?;
-        let mut store = Store::new(&engine, ());
-        let linker = Linker::<()>::new(&engine);
-        let instance = linker
-            .instantiate(&mut store, &module)
-            .unwrap()
-            .start(&mut store)
-            .unwrap();
+        let (store, instance) = Self::instantiate(&engine, &module)?;
 
         Ok(Self {
-            _engine: engine,
-            _module: module,
+            engine,
+            module,
             store,
             instance,
         })
     }
 
+    /// Replaces the store and instance with new ones, releasing all memory
+    /// that was grown by the previous instance.
+    pub fn reinstantiate(&mut self) -> Result<(), wasmi::Error> {
+        let (store, instance) = Self::instantiate(&self.engine, &self.module)?;
+        self.store = store;
+        self.instance = instance;
+        Ok(())
+    }
+
+    fn instantiate(
+        engine: &Engine,
+        module: &Module,
+    ) -> Result<(Store<HostState>, Instance), wasmi::Error> {
+        let mut store = Store::new(engine, HostState::default());
+        let mut linker = Linker::<HostState>::new(engine);
+        // Only modules compiled with `Config::trace` import this
+        linker.func_wrap(abi::TRACE_MODULE, abi::TRACE_FUNCTION, Self::trace)?;
+        let instance = linker.instantiate(&mut store, module)?.start(&mut store)?;
+
+        Ok((store, instance))
+    }
+
+    /// Decodes the set of active states from the state memory, and records it
+    /// as the next step of the trace.
+    fn trace(
+        mut caller: Caller<'_, HostState>,
+        at_offset: i64,
+        set_ptr: i64,
+        set_len: i32,
+    ) -> Result<(), wasmi::Error> {
+        let state_id_size = caller
+            .get_export(abi::STATE_ID_SIZE_GLOBAL)
+            .and_then(Extern::into_global)
+            .and_then(|global| global.get(&caller).i32())
+            .ok_or_else(|| {
+                wasmi::Error::new(format!(
+                    "module is missing the `{}` global",
+                    abi::STATE_ID_SIZE_GLOBAL
+                ))
+            })?;
+        let state = caller
+            .get_export(abi::STATE_MEMORY)
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| {
+                wasmi::Error::new(format!(
+                    "module is missing the `{}` memory",
+                    abi::STATE_MEMORY
+                ))
+            })?;
+
+        let state_id_size = usize::try_from(state_id_size).unwrap();
+        let set_ptr = usize::try_from(set_ptr).unwrap();
+        let set_len = usize::try_from(set_len).unwrap();
+        let dense = state
+            .data(&caller)
+            .get(set_ptr..set_ptr + set_len * state_id_size)
+            .ok_or_else(|| wasmi::Error::new("traced set is out of bounds"))?;
+        let states = decode_trace_set(dense, state_id_size)
+            .ok_or_else(|| wasmi::Error::new("traced set contains an invalid state ID"))?;
+
+        caller.data_mut().trace.push(TraceStep {
+            at_offset: usize::try_from(at_offset).unwrap(),
+            states,
+        });
+        Ok(())
+    }
+
     /// Returns a reference to the underlying `wasmi` instance.
     #[cfg(test)]
     pub(crate) fn instance(&self) -> &Instance {
@@ -43,13 +117,13 @@ impl Executor {
 
     /// Returns a reference to the `wasmi` store.
     #[cfg(test)]
-    pub(crate) fn store(&self) -> &Store<()> {
+    pub(crate) fn store(&self) -> &Store<HostState> {
         &self.store
     }
 
     /// Returns a mutable reference to the `wasmi` store.
     #[cfg(test)]
-    pub(crate) fn store_mut(&mut self) -> &mut Store<()> {
+    pub(crate) fn store_mut(&mut self) -> &mut Store<HostState> {
         &mut self.store
     }
 }
@@ -59,33 +133,32 @@ impl Executor {
 #[derive(Debug)]
 pub struct Regex {
     executor: Executor,
+    exports: Exports,
+    fuel_limit: Option<u64>,
+    step_limit: Option<u64>,
+}
+
+/// The functions, memories, and globals exported by a compiled regular
+/// expression module.
+#[derive(Debug)]
+struct Exports {
     prepare_input: TypedFunc<i64, i32>,
     is_match: TypedFunc<(i32, i32, i64, i64, i64), i32>,
     haystack: Memory,
+    step_budget: Option<Global>,
 }
 
-impl Regex {
-    /// Creates a new `Regex` instance with the default `wasmi` engine.
-    ///
-    /// This is a convenience function that uses the default [`Engine`]
-    /// configuration. For more control over the engine, use
-    /// [`with_engine`][Self::with_engine].
-    pub fn new(bytecode: &RegexBytecode) -> Result<Self, wasmi::Error> {
-        Self::with_engine(Engine::default(), bytecode)
-    }
-
-    /// Creates a new `Regex` instance with the given `wasmi` engine.
+impl Exports {
+    /// Looks up all the exports from the current instance of the executor.
     ///
     /// # Panics
     ///
-    /// This function will panic if the provided `RegexBytecode` is not
-    /// well-formed and is missing any of the expected functions or memory.
-    pub fn with_engine(engine: Engine, bytecode: &RegexBytecode) -> Result<Self, wasmi::Error> {
-        let executor = Executor::with_engine(engine, bytecode)?;
-
+    /// This function will panic if the instance is missing any of the
+    /// expected functions or memory.
+    fn new(executor: &Executor) -> Self {
         let prepare_input = executor
             .instance
-            .get_typed_func::<i64, i32>(&executor.store, "prepare_input")
+            .get_typed_func::<i64, i32>(&executor.store, abi::PREPARE_INPUT)
             .expect(
                 "If the `RegexBytecode` passed is well-formed, then there must be a \
                  `prepare_input` function",
@@ -93,73 +166,240 @@ impl Regex {
         let is_match = executor
             .instance
             // [anchored, anchored_pattern, span_start, span_end, haystack_len]
-            .get_typed_func::<(i32, i32, i64, i64, i64), i32>(&executor.store, "is_match")
+            .get_typed_func::<(i32, i32, i64, i64, i64), i32>(&executor.store, abi::IS_MATCH)
             .expect(
                 "If the `RegexBytecode` passed is well-formed, then there must be a `is_match` \
                  function",
             );
         let haystack: Memory = executor
             .instance
-            .get_memory(&executor.store, "haystack")
+            .get_memory(&executor.store, abi::HAYSTACK_MEMORY)
             .expect(
                 "If the `RegexBytecode` passed is well-formed, then there must be a `haystack` \
                  memory",
             );
+        // Only present if the module was compiled with `Config::step_counter` enabled
+        let step_budget = executor
+            .instance
+            .get_global(&executor.store, abi::STEP_BUDGET_GLOBAL);
 
-        Ok(Self {
-            executor,
+        Self {
             prepare_input,
             is_match,
             haystack,
+            step_budget,
+        }
+    }
+}
+
+impl Regex {
+    /// Creates a new `Regex` instance with the default `wasmi` engine.
+    ///
+    /// This is a convenience function that uses the default [`Engine`]
+    /// configuration. For more control over the engine, use
+    /// [`with_engine`][Self::with_engine].
+    pub fn new(bytecode: &RegexBytecode) -> Result<Self, wasmi::Error> {
+        Self::with_engine(Engine::default(), bytecode)
+    }
+
+    /// Creates a new `Regex` instance with the given `wasmi` engine.
+    ///
+    /// Returns an error if the module implements a different
+    /// [ABI version][abi::VERSION].
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the provided `RegexBytecode` is not
+    /// well-formed and is missing any of the expected functions or memory.
+    pub fn with_engine(engine: Engine, bytecode: &RegexBytecode) -> Result<Self, wasmi::Error> {
+        let executor = Executor::with_engine(engine, bytecode)?;
+
+        let abi_version = executor
+            .instance
+            .get_global(&executor.store, abi::ABI_VERSION_GLOBAL)
+            .and_then(|global| global.get(&executor.store).i32())
+            .map(|version| version as u32);
+        if abi_version != Some(abi::VERSION) {
+            return Err(wasmi::Error::new(format!(
+                "module implements ABI version {abi_version:?}, but version {} is required",
+                abi::VERSION
+            )));
+        }
+
+        let exports = Exports::new(&executor);
+
+        Ok(Self {
+            executor,
+            exports,
+            fuel_limit: None,
+            step_limit: None,
         })
     }
 
+    /// Returns the current size of the haystack memory in bytes.
+    ///
+    /// The haystack memory grows to fit the largest haystack that has been
+    /// searched so far, and never shrinks on its own. See
+    /// [`shrink_haystack`][Self::shrink_haystack] to release it.
+    pub fn haystack_capacity(&self) -> usize {
+        self.exports.haystack.data_size(&self.executor.store)
+    }
+
+    /// Releases the haystack memory if it has grown larger than
+    /// `max_capacity` bytes, returning `true` if it was released.
+    ///
+    /// WASM memories can't shrink, so this re-instantiates the module with a
+    /// fresh store. The fuel and step limits are preserved.
+    pub fn shrink_haystack(&mut self, max_capacity: usize) -> Result<bool, wasmi::Error> {
+        if self.haystack_capacity() <= max_capacity {
+            return Ok(false);
+        }
+
+        self.executor.reinstantiate()?;
+        self.exports = Exports::new(&self.executor);
+        Ok(true)
+    }
+
+    /// Sets the maximum amount of fuel that each search is allowed to consume.
+    ///
+    /// This requires that the [`Engine`] was created with fuel metering
+    /// enabled, see [`wasmi::Config::consume_fuel`]. Passing `None` removes
+    /// the limit.
+    ///
+    /// If a search exhausts its fuel, then [`try_is_match`][Self::try_is_match]
+    /// will return an error for which
+    /// [`MatchError::is_budget_exceeded`] is `true`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fuel metering is not enabled on the [`Engine`].
+    pub fn set_fuel_limit(&mut self, fuel: Option<u64>) -> Result<(), wasmi::Error> {
+        if fuel.is_some() {
+            // This fails if fuel metering is disabled
+            let _ = self.executor.store.get_fuel()?;
+        }
+        self.fuel_limit = fuel;
+        Ok(())
+    }
+
+    /// Sets the maximum number of haystack offsets that each search is
+    /// allowed to visit.
+    ///
+    /// This is the engine-independent counterpart to
+    /// [`set_fuel_limit`][Self::set_fuel_limit], and requires that the module
+    /// was compiled with [`Config::step_counter`][crate::Config::step_counter]
+    /// enabled. Passing `None` removes the limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module was not compiled with a step counter.
+    pub fn set_step_limit(&mut self, steps: Option<u64>) -> Result<(), wasmi::Error> {
+        if steps.is_some() && self.exports.step_budget.is_none() {
+            return Err(wasmi::Error::new(
+                "module was not compiled with `Config::step_counter` enabled",
+            ));
+        }
+        self.step_limit = steps;
+        Ok(())
+    }
+
     /// Checks if the given input matches the regular expression.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the search fails, for example by exceeding
+    /// a configured fuel or step limit. Use
+    /// [`try_is_match`][Self::try_is_match] to handle those failures.
     pub fn is_match(&mut self, input: regex_automata::Input<'_>) -> bool {
+        self.try_is_match(input)
+            .expect("search should not fail or exceed its budget")
+    }
+
+    /// Checks if the given input matches the regular expression, returning an
+    /// error if the search could not be completed.
+    pub fn try_is_match(&mut self, input: regex_automata::Input<'_>) -> Result<bool, MatchError> {
         common_input_validation(&input);
 
+        self.reset_budget()?;
+        self.executor.store.data_mut().trace.clear();
+
         let haystack = input.haystack();
-        let _success = self
+        let prepare_input_result = self
+            .exports
             .prepare_input
-            .call(&mut self.executor.store, haystack.len().try_into().unwrap())
-            .expect("execution should not trap");
+            .call(&mut self.executor.store, haystack.len().try_into().unwrap())?;
+
+        if prepare_input_result == PrepareInputResult::HaystackTooLarge as i32 {
+            return Err(MatchError::haystack_too_large(haystack.len()));
+        } else if prepare_input_result != PrepareInputResult::SuccessNoGrowth as i32
+            && prepare_input_result != PrepareInputResult::SuccessGrowth as i32
+        {
+            panic!("unexpected value from prepare_input: {prepare_input_result}");
+        }
 
-        self.haystack.data_mut(&mut self.executor.store)[0..haystack.len()]
+        self.exports.haystack.data_mut(&mut self.executor.store)[0..haystack.len()]
             .copy_from_slice(haystack);
 
         let input_opts = InputOpts::new(&input);
 
-        let is_match_result = self
-            .is_match
-            .call(
-                &mut self.executor.store,
-                (
-                    input_opts.anchored,
-                    input_opts.anchored_pattern,
-                    i64::from_ne_bytes(
-                        u64::try_from(input.get_span().start).unwrap().to_ne_bytes(),
-                    ),
-                    i64::from_ne_bytes(u64::try_from(input.get_span().end).unwrap().to_ne_bytes()),
-                    i64::from_ne_bytes(u64::try_from(haystack.len()).unwrap().to_ne_bytes()),
-                ),
-            )
-            .expect("execution should not trap");
-
-        if is_match_result == (true as i32) {
-            true
-        } else if is_match_result == (false as i32) {
-            false
+        let is_match_result = self.exports.is_match.call(
+            &mut self.executor.store,
+            (
+                input_opts.anchored,
+                input_opts.anchored_pattern,
+                i64::from_ne_bytes(u64::try_from(input.get_span().start).unwrap().to_ne_bytes()),
+                i64::from_ne_bytes(u64::try_from(input.get_span().end).unwrap().to_ne_bytes()),
+                i64::from_ne_bytes(u64::try_from(haystack.len()).unwrap().to_ne_bytes()),
+            ),
+        )?;
+
+        if is_match_result == IsMatchResult::Match as i32 {
+            Ok(true)
+        } else if is_match_result == IsMatchResult::NoMatch as i32 {
+            Ok(false)
+        } else if is_match_result == IsMatchResult::BudgetExceeded as i32 {
+            Err(MatchError::budget_exceeded())
         } else {
             panic!("unexpected value from is_match: {is_match_result}");
         }
     }
+
+    /// Returns the NFA states that were active at each offset visited by the
+    /// most recent search, in the order they were visited.
+    ///
+    /// This is always empty unless the module was compiled with
+    /// [`Config::trace`][crate::Config::trace] enabled.
+    pub fn trace(&self) -> &[TraceStep] {
+        &self.executor.store.data().trace
+    }
+
+    /// Refill the fuel and step budgets so that each search starts from the
+    /// configured limits.
+    fn reset_budget(&mut self) -> Result<(), wasmi::Error> {
+        let store = &mut self.executor.store;
+        // If fuel metering is enabled on the engine, then every search needs some
+        // fuel even when no limit was configured.
+        if store.get_fuel().is_ok() {
+            store.set_fuel(self.fuel_limit.unwrap_or(u64::MAX))?;
+        }
+
+        if let Some(step_budget) = self.exports.step_budget {
+            let steps = self.step_limit.unwrap_or(u64::MAX);
+            step_budget.set(store, Val::I64(i64::from_ne_bytes(steps.to_ne_bytes())))?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use regex_automata::Input;
+    use regex_automata::{
+        Input,
+        nfa::thompson::{State, WhichCaptures},
+    };
 
-    use crate::Builder;
+    use crate::{Builder, Config, RegexNFAConfig};
 
     use super::*;
 
@@ -169,4 +409,145 @@ mod tests {
         let mut regex = Regex::new(&bytecode).unwrap();
         assert!(!regex.is_match(Input::new("")));
     }
+
+    #[test]
+    fn step_limit_exceeded() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().step_counter(true))
+            .build("z")
+            .unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        let haystack = "a".repeat(100);
+
+        regex.set_step_limit(Some(10)).unwrap();
+        let err = regex.try_is_match(Input::new(&haystack)).unwrap_err();
+        assert!(err.is_budget_exceeded());
+
+        // The budget is refilled before every search
+        assert!(regex.try_is_match(Input::new("aaaaz")).unwrap());
+
+        regex.set_step_limit(None).unwrap();
+        assert!(!regex.try_is_match(Input::new(&haystack)).unwrap());
+    }
+
+    #[test]
+    fn step_limit_requires_step_counter() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.set_step_limit(Some(10)).is_err());
+        assert!(regex.set_step_limit(None).is_ok());
+    }
+
+    #[test]
+    fn fuel_limit_exceeded() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let mut regex = Regex::with_engine(Engine::new(&config), &bytecode).unwrap();
+        let haystack = "a".repeat(100);
+
+        // No limit configured still allows searches to complete
+        assert!(!regex.try_is_match(Input::new(&haystack)).unwrap());
+
+        regex.set_fuel_limit(Some(100)).unwrap();
+        let err = regex.try_is_match(Input::new(&haystack)).unwrap_err();
+        assert!(err.is_budget_exceeded());
+
+        regex.set_fuel_limit(None).unwrap();
+        assert!(regex.try_is_match(Input::new("aaaz")).unwrap());
+    }
+
+    #[test]
+    fn fuel_limit_requires_fuel_metering() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.set_fuel_limit(Some(100)).is_err());
+    }
+
+    #[test]
+    fn haystack_too_large() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().max_haystack_len(10))
+            .build("z")
+            .unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+
+        assert!(regex.try_is_match(Input::new("aaaaaaaaaz")).unwrap());
+        let err = regex.try_is_match(Input::new("aaaaaaaaaaz")).unwrap_err();
+        assert!(err.is_haystack_too_large());
+    }
+
+    #[test]
+    fn trace_steps() {
+        let (bytecode, context) = Builder::new()
+            .configure(Config::new().trace(true))
+            .thompson(RegexNFAConfig::new().which_captures(WhichCaptures::None))
+            .build("ab")
+            .unwrap();
+        let nfa = context.nfa.unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+
+        assert!(regex.is_match(Input::new("ab").anchored(regex_automata::Anchored::Yes)));
+        let trace = regex.trace();
+        assert_eq!(
+            trace.iter().map(|step| step.at_offset).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        let states = |step: &TraceStep| {
+            step.states
+                .iter()
+                .map(|sid| nfa.state(*sid).clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(matches!(
+            states(&trace[0])[..],
+            [State::ByteRange { trans }] if trans.start == b'a'
+        ));
+        assert!(matches!(
+            states(&trace[1])[..],
+            [State::ByteRange { trans }] if trans.start == b'b'
+        ));
+        assert!(matches!(states(&trace[2])[..], [State::Match { .. }]));
+
+        // Each search replaces the previous trace, and an anchored search stops
+        // once no states are left
+        assert!(!regex.is_match(Input::new("ba").anchored(regex_automata::Anchored::Yes)));
+        assert_eq!(
+            regex
+                .trace()
+                .iter()
+                .map(|step| step.at_offset)
+                .collect::<Vec<_>>(),
+            [0]
+        );
+    }
+
+    #[test]
+    fn trace_disabled() {
+        let (bytecode, _) = Builder::new().build("ab").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.is_match(Input::new("xab")));
+        assert!(regex.trace().is_empty());
+    }
+
+    #[test]
+    fn shrink_haystack() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        let page_size = Config::DEFAULT_PAGE_SIZE;
+        assert_eq!(regex.haystack_capacity(), page_size);
+
+        let haystack = "a".repeat(4 * page_size);
+        assert!(!regex.is_match(Input::new(&haystack)));
+        assert_eq!(regex.haystack_capacity(), 4 * page_size);
+
+        assert!(!regex.shrink_haystack(4 * page_size).unwrap());
+        assert_eq!(regex.haystack_capacity(), 4 * page_size);
+
+        assert!(regex.shrink_haystack(page_size).unwrap());
+        assert_eq!(regex.haystack_capacity(), page_size);
+
+        // The new instance is still usable
+        assert!(regex.is_match(Input::new("aaz")));
+    }
 }
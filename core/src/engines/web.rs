@@ -0,0 +1,540 @@
+//! Utilities used to run [`wahgex`][crate] compiled regular expressions
+//! using the `WebAssembly` API of a JavaScript host, through [`js_sys`].
+//!
+//! This lets Rust code that is itself running in a browser execute the
+//! compiled modules on the browser's optimizing WASM engine, rather than on
+//! an interpreter that was compiled into the same WASM binary. The functions
+//! in this module are only usable on `wasm32` targets running in a JavaScript
+//! host, and will panic anywhere else.
+
+use std::{
+    cell::{Ref, RefCell},
+    rc::Rc,
+};
+
+use js_sys::{
+    Array, ArrayBuffer, Function, Object, Reflect, Uint8Array, WebAssembly,
+    wasm_bindgen::{JsCast, JsValue, closure::Closure},
+};
+
+use super::{TraceStep, decode_trace_set};
+use crate::{
+    MatchError, RegexBytecode,
+    abi::{self, IsMatchResult, PrepareInputResult},
+    common_input_validation,
+    input::InputOpts,
+};
+
+/// The main entry point for executing a compiled regular expression with the
+/// `WebAssembly` API of the JavaScript host.
+#[derive(Debug)]
+pub struct Regex {
+    module: WebAssembly::Module,
+    exports: Exports,
+    step_limit: Option<u64>,
+    trace: Rc<RefCell<TraceRecorder>>,
+    /// The `trace` import, which must be kept alive for as long as the
+    /// instance may call it.
+    trace_fn: Closure<dyn FnMut(i64, i64, i32) -> Result<(), JsValue>>,
+}
+
+/// The state shared with the `trace` import.
+#[derive(Debug, Default)]
+struct TraceRecorder {
+    /// The state memory and the size of each state ID, only present if the
+    /// module was compiled with `Config::trace` enabled.
+    state: Option<(WebAssembly::Memory, usize)>,
+    /// The steps recorded during the current search.
+    steps: Vec<TraceStep>,
+}
+
+impl TraceRecorder {
+    /// Decodes the set of active states from the state memory, and records it
+    /// as the next step of the trace.
+    fn record(&mut self, at_offset: i64, set_ptr: i64, set_len: i32) -> Result<(), JsValue> {
+        let (state, state_id_size) = self.state.as_ref().ok_or_else(|| {
+            js_sys::Error::new(&format!(
+                "module is missing the `{}` memory or the `{}` global",
+                abi::STATE_MEMORY,
+                abi::STATE_ID_SIZE_GLOBAL
+            ))
+        })?;
+
+        let out_of_bounds = || js_sys::Error::new("traced set is out of bounds");
+        let set_ptr = u32::try_from(set_ptr).map_err(|_| out_of_bounds())?;
+        let set_len = u32::try_from(set_len)
+            .ok()
+            .and_then(|len| len.checked_mul(u32::try_from(*state_id_size).ok()?))
+            .ok_or_else(out_of_bounds)?;
+        let dense = Uint8Array::new_with_byte_offset_and_length(&state.buffer(), set_ptr, set_len);
+        let states = decode_trace_set(&dense.to_vec(), *state_id_size)
+            .ok_or_else(|| js_sys::Error::new("traced set contains an invalid state ID"))?;
+
+        self.steps.push(TraceStep {
+            at_offset: usize::try_from(at_offset).unwrap(),
+            states,
+        });
+        Ok(())
+    }
+}
+
+/// The functions, memories, and globals exported by a compiled regular
+/// expression module.
+#[derive(Debug)]
+struct Exports {
+    prepare_input: Function,
+    is_match: Function,
+    haystack: WebAssembly::Memory,
+    step_budget: Option<WebAssembly::Global>,
+}
+
+impl Exports {
+    /// Looks up all the exports from the given instance.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the instance is missing any of the
+    /// expected functions or memory.
+    fn new(instance: &WebAssembly::Instance) -> Self {
+        let exports = instance.exports();
+        let prepare_input = get_export(&exports, abi::PREPARE_INPUT).expect(
+            "If the `RegexBytecode` passed is well-formed, then there must be a `prepare_input` \
+             function",
+        );
+        let is_match = get_export(&exports, abi::IS_MATCH).expect(
+            "If the `RegexBytecode` passed is well-formed, then there must be a `is_match` \
+             function",
+        );
+        let haystack = get_export(&exports, abi::HAYSTACK_MEMORY).expect(
+            "If the `RegexBytecode` passed is well-formed, then there must be a `haystack` memory",
+        );
+        // Only present if the module was compiled with `Config::step_counter` enabled
+        let step_budget = get_export(&exports, abi::STEP_BUDGET_GLOBAL);
+
+        Self {
+            prepare_input,
+            is_match,
+            haystack,
+            step_budget,
+        }
+    }
+}
+
+/// Returns the export with the given name, if it exists and has the expected
+/// type.
+fn get_export<T: JsCast>(exports: &Object, name: &str) -> Option<T> {
+    Reflect::get(exports, &JsValue::from_str(name))
+        .ok()?
+        .dyn_into()
+        .ok()
+}
+
+impl Regex {
+    /// Creates a new `Regex` instance by synchronously compiling the given
+    /// module.
+    ///
+    /// Browsers may refuse to synchronously compile large modules on the main
+    /// thread. In that case, compile the module asynchronously with
+    /// `WebAssembly.compile` and use [`from_module`][Self::from_module].
+    pub fn new(bytecode: &RegexBytecode) -> Result<Self, JsValue> {
+        let module = WebAssembly::Module::new(&Uint8Array::from(bytecode.as_ref()).into())?;
+        Self::from_module(module)
+    }
+
+    /// Creates a new `Regex` instance from an already compiled module.
+    ///
+    /// Returns an error if the module implements a different
+    /// [ABI version][abi::VERSION].
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the provided module was not compiled from
+    /// a well-formed `RegexBytecode` and is missing any of the expected
+    /// functions or memory.
+    pub fn from_module(module: WebAssembly::Module) -> Result<Self, JsValue> {
+        let trace = Rc::new(RefCell::new(TraceRecorder::default()));
+        let trace_fn = {
+            let trace = Rc::clone(&trace);
+            Closure::<dyn FnMut(i64, i64, i32) -> Result<(), JsValue>>::new(
+                move |at_offset, set_ptr, set_len| {
+                    trace.borrow_mut().record(at_offset, set_ptr, set_len)
+                },
+            )
+        };
+        let instance = Self::instantiate(&module, &trace, &trace_fn)?;
+
+        let abi_version =
+            get_export::<WebAssembly::Global>(&instance.exports(), abi::ABI_VERSION_GLOBAL)
+                .and_then(|global| global.value().as_f64())
+                .map(|version| version as u32);
+        if abi_version != Some(abi::VERSION) {
+            return Err(js_sys::Error::new(&format!(
+                "module implements ABI version {abi_version:?}, but version {} is required",
+                abi::VERSION
+            ))
+            .into());
+        }
+
+        let exports = Exports::new(&instance);
+
+        Ok(Self {
+            module,
+            exports,
+            step_limit: None,
+            trace,
+            trace_fn,
+        })
+    }
+
+    /// Instantiates the module, and points the `trace` import at the state
+    /// memory of the new instance.
+    fn instantiate(
+        module: &WebAssembly::Module,
+        trace: &RefCell<TraceRecorder>,
+        trace_fn: &Closure<dyn FnMut(i64, i64, i32) -> Result<(), JsValue>>,
+    ) -> Result<WebAssembly::Instance, JsValue> {
+        // Only modules compiled with `Config::trace` import this
+        let trace_imports = Object::new();
+        Reflect::set(
+            &trace_imports,
+            &JsValue::from_str(abi::TRACE_FUNCTION),
+            trace_fn.as_ref(),
+        )?;
+        let imports = Object::new();
+        Reflect::set(
+            &imports,
+            &JsValue::from_str(abi::TRACE_MODULE),
+            &trace_imports,
+        )?;
+        let instance = WebAssembly::Instance::new(module, &imports)?;
+
+        let exports = instance.exports();
+        let state = get_export::<WebAssembly::Memory>(&exports, abi::STATE_MEMORY);
+        let state_id_size = get_export::<WebAssembly::Global>(&exports, abi::STATE_ID_SIZE_GLOBAL)
+            .and_then(|global| global.value().as_f64())
+            .map(|size| size as usize);
+        trace.borrow_mut().state = state.zip(state_id_size);
+
+        Ok(instance)
+    }
+
+    /// Returns the current size of the haystack memory in bytes.
+    ///
+    /// The haystack memory grows to fit the largest haystack that has been
+    /// searched so far, and never shrinks on its own. See
+    /// [`shrink_haystack`][Self::shrink_haystack] to release it.
+    pub fn haystack_capacity(&self) -> usize {
+        self.exports
+            .haystack
+            .buffer()
+            .unchecked_into::<ArrayBuffer>()
+            .byte_length() as usize
+    }
+
+    /// Releases the haystack memory if it has grown larger than
+    /// `max_capacity` bytes, returning `true` if it was released.
+    ///
+    /// WASM memories can't shrink, so this re-instantiates the module. The
+    /// step limit is preserved.
+    pub fn shrink_haystack(&mut self, max_capacity: usize) -> Result<bool, JsValue> {
+        if self.haystack_capacity() <= max_capacity {
+            return Ok(false);
+        }
+
+        let instance = Self::instantiate(&self.module, &self.trace, &self.trace_fn)?;
+        self.exports = Exports::new(&instance);
+        Ok(true)
+    }
+
+    /// Sets the maximum number of haystack offsets that each search is
+    /// allowed to visit.
+    ///
+    /// Browsers don't offer fuel metering, so this is the only way to bound a
+    /// search. It requires that the module was compiled with
+    /// [`Config::step_counter`][crate::Config::step_counter] enabled. Passing
+    /// `None` removes the limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module was not compiled with a step counter.
+    pub fn set_step_limit(&mut self, steps: Option<u64>) -> Result<(), JsValue> {
+        if steps.is_some() && self.exports.step_budget.is_none() {
+            return Err(js_sys::Error::new(
+                "module was not compiled with `Config::step_counter` enabled",
+            )
+            .into());
+        }
+        self.step_limit = steps;
+        Ok(())
+    }
+
+    /// Checks if the given input matches the regular expression.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the search fails, for example by exceeding
+    /// a configured step limit. Use [`try_is_match`][Self::try_is_match] to
+    /// handle those failures.
+    pub fn is_match(&mut self, input: regex_automata::Input<'_>) -> bool {
+        self.try_is_match(input)
+            .expect("search should not fail or exceed its budget")
+    }
+
+    /// Checks if the given input matches the regular expression, returning an
+    /// error if the search could not be completed.
+    pub fn try_is_match(&mut self, input: regex_automata::Input<'_>) -> Result<bool, MatchError> {
+        common_input_validation(&input);
+
+        self.reset_budget();
+        self.trace.borrow_mut().steps.clear();
+
+        let haystack = input.haystack();
+        let prepare_input_result = call(
+            &self.exports.prepare_input,
+            &Array::of1(&JsValue::from(u64::try_from(haystack.len()).unwrap())),
+        )?;
+
+        if prepare_input_result == PrepareInputResult::HaystackTooLarge as i32 {
+            return Err(MatchError::haystack_too_large(haystack.len()));
+        } else if prepare_input_result != PrepareInputResult::SuccessNoGrowth as i32
+            && prepare_input_result != PrepareInputResult::SuccessGrowth as i32
+        {
+            panic!("unexpected value from prepare_input: {prepare_input_result}");
+        }
+
+        // The buffer is replaced whenever the memory grows, so it must be fetched
+        // after `prepare_input`
+        Uint8Array::new_with_byte_offset_and_length(
+            &self.exports.haystack.buffer(),
+            0,
+            haystack.len().try_into().unwrap(),
+        )
+        .copy_from(haystack);
+
+        let input_opts = InputOpts::new(&input);
+
+        let is_match_result = call(
+            &self.exports.is_match,
+            &Array::of5(
+                &JsValue::from(input_opts.anchored),
+                &JsValue::from(input_opts.anchored_pattern),
+                &JsValue::from(u64::try_from(input.get_span().start).unwrap()),
+                &JsValue::from(u64::try_from(input.get_span().end).unwrap()),
+                &JsValue::from(u64::try_from(haystack.len()).unwrap()),
+            ),
+        )?;
+
+        if is_match_result == IsMatchResult::Match as i32 {
+            Ok(true)
+        } else if is_match_result == IsMatchResult::NoMatch as i32 {
+            Ok(false)
+        } else if is_match_result == IsMatchResult::BudgetExceeded as i32 {
+            Err(MatchError::budget_exceeded())
+        } else {
+            panic!("unexpected value from is_match: {is_match_result}");
+        }
+    }
+
+    /// Returns the NFA states that were active at each offset visited by the
+    /// most recent search, in the order they were visited.
+    ///
+    /// This is always empty unless the module was compiled with
+    /// [`Config::trace`][crate::Config::trace] enabled.
+    pub fn trace(&self) -> Ref<'_, [TraceStep]> {
+        Ref::map(self.trace.borrow(), |trace| trace.steps.as_slice())
+    }
+
+    /// Refill the step budget so that each search starts from the configured
+    /// limit.
+    fn reset_budget(&mut self) {
+        if let Some(step_budget) = &self.exports.step_budget {
+            let steps = self.step_limit.unwrap_or(u64::MAX);
+            // `i64` globals are exposed to JavaScript as signed `BigInt`s
+            step_budget.set_value(&JsValue::from(i64::from_ne_bytes(steps.to_ne_bytes())));
+        }
+    }
+}
+
+/// Calls an exported function that returns an `i32`.
+fn call(function: &Function, args: &Array) -> Result<i32, JsValue> {
+    let result = function.apply(&JsValue::UNDEFINED, args)?;
+    Ok(result
+        .as_f64()
+        .expect("exported function should return an i32") as i32)
+}
+
+#[cfg(all(test, target_arch = "wasm32", feature = "compile"))]
+mod tests {
+    use regex_automata::{
+        Input,
+        nfa::thompson::{State, WhichCaptures},
+    };
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use crate::{Builder, Config, RegexNFAConfig};
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn empty_pattern_empty_haystack() {
+        let (bytecode, _) = Builder::new().build_many::<&str>(&[]).unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(!regex.is_match(Input::new("")));
+    }
+
+    #[wasm_bindgen_test]
+    fn is_match() {
+        let (bytecode, _) = Builder::new().build_many(&["a+b", r"\d{3}"]).unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.is_match(Input::new("xxaab")));
+        assert!(regex.is_match(Input::new("x123")));
+        assert!(!regex.is_match(Input::new("ab12")));
+        assert!(!regex.is_match(Input::new("xxaab").anchored(regex_automata::Anchored::Yes)));
+    }
+
+    #[wasm_bindgen_test]
+    fn incompatible_abi() {
+        use wasm_encoder::{
+            ConstExpr, ExportKind, ExportSection, GlobalSection, GlobalType, Module, ValType,
+        };
+
+        let mut globals = GlobalSection::new();
+        globals.global(
+            GlobalType {
+                val_type: ValType::I32,
+                mutable: false,
+                shared: false,
+            },
+            &ConstExpr::i32_const(i32::try_from(abi::VERSION).unwrap() + 1),
+        );
+        let mut exports = ExportSection::new();
+        exports.export(abi::ABI_VERSION_GLOBAL, ExportKind::Global, 0);
+        let mut module = Module::new();
+        module.section(&globals).section(&exports);
+
+        let bytecode = RegexBytecode::from_bytes_unchecked(module.finish());
+        let err = Regex::new(&bytecode).unwrap_err();
+        let message = String::from(err.dyn_into::<js_sys::Error>().unwrap().message());
+        assert!(message.contains("ABI version"));
+    }
+
+    #[wasm_bindgen_test]
+    fn step_limit_exceeded() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().step_counter(true))
+            .build("z")
+            .unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        let haystack = "a".repeat(100);
+
+        regex.set_step_limit(Some(10)).unwrap();
+        let err = regex.try_is_match(Input::new(&haystack)).unwrap_err();
+        assert!(err.is_budget_exceeded());
+
+        // The budget is refilled before every search
+        assert!(regex.try_is_match(Input::new("aaaaz")).unwrap());
+
+        regex.set_step_limit(None).unwrap();
+        assert!(!regex.try_is_match(Input::new(&haystack)).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn step_limit_requires_step_counter() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.set_step_limit(Some(10)).is_err());
+        assert!(regex.set_step_limit(None).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn haystack_too_large() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().max_haystack_len(10))
+            .build("z")
+            .unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+
+        assert!(regex.try_is_match(Input::new("aaaaaaaaaz")).unwrap());
+        let err = regex.try_is_match(Input::new("aaaaaaaaaaz")).unwrap_err();
+        assert!(err.is_haystack_too_large());
+    }
+
+    #[wasm_bindgen_test]
+    fn trace_steps() {
+        let (bytecode, context) = Builder::new()
+            .configure(Config::new().trace(true))
+            .thompson(RegexNFAConfig::new().which_captures(WhichCaptures::None))
+            .build("ab")
+            .unwrap();
+        let nfa = context.nfa.unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+
+        assert!(regex.is_match(Input::new("ab").anchored(regex_automata::Anchored::Yes)));
+        let states = |step: &TraceStep| {
+            step.states
+                .iter()
+                .map(|sid| nfa.state(*sid).clone())
+                .collect::<Vec<_>>()
+        };
+        {
+            let trace = regex.trace();
+            assert_eq!(
+                trace.iter().map(|step| step.at_offset).collect::<Vec<_>>(),
+                [0, 1, 2]
+            );
+            assert!(matches!(
+                states(&trace[0])[..],
+                [State::ByteRange { trans }] if trans.start == b'a'
+            ));
+            assert!(matches!(
+                states(&trace[1])[..],
+                [State::ByteRange { trans }] if trans.start == b'b'
+            ));
+            assert!(matches!(states(&trace[2])[..], [State::Match { .. }]));
+        }
+
+        // The recorder is shared with the new instance, so tracing keeps working
+        // after the haystack memory is released
+        let haystack = "a".repeat(2 * Config::DEFAULT_PAGE_SIZE);
+        assert!(!regex.is_match(Input::new(&haystack).anchored(regex_automata::Anchored::Yes)));
+        assert!(regex.shrink_haystack(Config::DEFAULT_PAGE_SIZE).unwrap());
+        assert!(!regex.is_match(Input::new("ba").anchored(regex_automata::Anchored::Yes)));
+        assert_eq!(
+            regex
+                .trace()
+                .iter()
+                .map(|step| step.at_offset)
+                .collect::<Vec<_>>(),
+            [0]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn trace_disabled() {
+        let (bytecode, _) = Builder::new().build("ab").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        assert!(regex.is_match(Input::new("xab")));
+        assert!(regex.trace().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn shrink_haystack() {
+        let (bytecode, _) = Builder::new().build("z").unwrap();
+        let mut regex = Regex::new(&bytecode).unwrap();
+        let page_size = Config::DEFAULT_PAGE_SIZE;
+        assert_eq!(regex.haystack_capacity(), page_size);
+
+        let haystack = "a".repeat(4 * page_size);
+        assert!(!regex.is_match(Input::new(&haystack)));
+        assert_eq!(regex.haystack_capacity(), 4 * page_size);
+
+        assert!(!regex.shrink_haystack(4 * page_size).unwrap());
+        assert_eq!(regex.haystack_capacity(), 4 * page_size);
+
+        assert!(regex.shrink_haystack(page_size).unwrap());
+        assert_eq!(regex.haystack_capacity(), page_size);
+
+        // The new instance is still usable
+        assert!(regex.is_match(Input::new("aaz")));
+    }
+}
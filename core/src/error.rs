@@ -2,6 +2,9 @@
 
 use std::{alloc::LayoutError, error::Error, fmt};
 
+#[cfg(feature = "web")]
+use js_sys::wasm_bindgen::{JsCast, JsValue};
+
 /// Represents an error that can occur during the regex compilation process.
 ///
 /// This error type encapsulates various kinds of issues, from NFA construction
@@ -11,6 +14,49 @@ pub struct BuildError {
     kind: Box<BuildErrorKind>,
 }
 
+impl BuildError {
+    /// Creates an error indicating that a module was compiled for a different
+    /// [ABI version][crate::abi::VERSION].
+    pub(crate) fn incompatible_abi(found: u32) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::IncompatibleAbi { found }),
+        }
+    }
+
+    /// Creates an error indicating that the `wahgex` custom section of a module
+    /// could not be decoded.
+    pub(crate) fn invalid_metadata(message: impl Into<String>) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::InvalidMetadata(message.into())),
+        }
+    }
+
+    /// Creates an error indicating that the configured maximum haystack length
+    /// doesn't fit in the address space of the haystack memory.
+    pub(crate) fn max_haystack_len_too_large(max_haystack_len: usize) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::MaxHaystackLenTooLarge { max_haystack_len }),
+        }
+    }
+
+    /// Creates an error indicating that a module imports a function that the
+    /// component adapter does not provide.
+    #[cfg_attr(not(feature = "component"), expect(dead_code))]
+    pub(crate) fn unsupported_import(module: &str, name: &str) -> Self {
+        Self {
+            kind: Box::new(BuildErrorKind::UnsupportedImport(format!(
+                "{module}.{name}"
+            ))),
+        }
+    }
+
+    /// Returns `true` if the module was compiled for an ABI version that is
+    /// not compatible with this version of `wahgex`.
+    pub fn is_incompatible_abi(&self) -> bool {
+        matches!(&*self.kind, BuildErrorKind::IncompatibleAbi { .. })
+    }
+}
+
 impl fmt::Display for BuildError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &*self.kind {
@@ -18,6 +64,22 @@ impl fmt::Display for BuildError {
             BuildErrorKind::NFABuild(err) => err.fmt(f),
             BuildErrorKind::LookaroundUnicode(err) => err.fmt(f),
             BuildErrorKind::WasmBytesValidationError(err) => err.fmt(f),
+            BuildErrorKind::IncompatibleAbi { found } => write!(
+                f,
+                "module was compiled for ABI version {found}, but version {} is required",
+                crate::abi::VERSION
+            ),
+            BuildErrorKind::InvalidMetadata(message) => {
+                write!(f, "invalid wahgex metadata: {message}")
+            },
+            BuildErrorKind::MaxHaystackLenTooLarge { max_haystack_len } => write!(
+                f,
+                "maximum haystack length {max_haystack_len} does not fit in the haystack memory"
+            ),
+            BuildErrorKind::UnsupportedImport(import) => write!(
+                f,
+                "module imports `{import}`, which can't be provided inside of a component"
+            ),
         }
     }
 }
@@ -29,6 +91,10 @@ impl Error for BuildError {
             BuildErrorKind::NFABuild(err) => Some(err),
             BuildErrorKind::LookaroundUnicode(err) => Some(err),
             BuildErrorKind::WasmBytesValidationError(err) => Some(err),
+            BuildErrorKind::IncompatibleAbi { .. }
+            | BuildErrorKind::InvalidMetadata(_)
+            | BuildErrorKind::MaxHaystackLenTooLarge { .. }
+            | BuildErrorKind::UnsupportedImport(_) => None,
         }
     }
 }
@@ -75,4 +141,126 @@ enum BuildErrorKind {
     NFABuild(regex_automata::nfa::thompson::BuildError),
     LookaroundUnicode(regex_automata::util::look::UnicodeWordBoundaryError),
     WasmBytesValidationError(wasmparser::BinaryReaderError),
+    IncompatibleAbi { found: u32 },
+    InvalidMetadata(String),
+    MaxHaystackLenTooLarge { max_haystack_len: usize },
+    UnsupportedImport(String),
+}
+
+/// Represents an error that can occur while running a search with a compiled
+/// regular expression.
+///
+/// The most common cause is a search exhausting the execution budget that was
+/// configured for it, see [`MatchError::is_budget_exceeded`].
+#[derive(Debug)]
+#[cfg(any(feature = "wasmi", feature = "web"))]
+pub struct MatchError {
+    kind: Box<MatchErrorKind>,
+}
+
+#[cfg(any(feature = "wasmi", feature = "web"))]
+impl MatchError {
+    /// Creates an error indicating that the search ran out of budget before
+    /// it could complete.
+    pub(crate) fn budget_exceeded() -> Self {
+        Self {
+            kind: Box::new(MatchErrorKind::BudgetExceeded),
+        }
+    }
+
+    /// Creates an error indicating that the haystack was larger than the
+    /// configured maximum haystack length.
+    pub(crate) fn haystack_too_large(len: usize) -> Self {
+        Self {
+            kind: Box::new(MatchErrorKind::HaystackTooLarge { len }),
+        }
+    }
+
+    /// Returns `true` if the search was stopped because it exhausted its
+    /// execution budget.
+    pub fn is_budget_exceeded(&self) -> bool {
+        matches!(&*self.kind, MatchErrorKind::BudgetExceeded)
+    }
+
+    /// Returns `true` if the search was rejected because the haystack was
+    /// longer than the configured
+    /// [`Config::max_haystack_len`][crate::Config::max_haystack_len].
+    pub fn is_haystack_too_large(&self) -> bool {
+        matches!(&*self.kind, MatchErrorKind::HaystackTooLarge { .. })
+    }
+}
+
+#[cfg(any(feature = "wasmi", feature = "web"))]
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.kind {
+            MatchErrorKind::BudgetExceeded => f.write_str("search exceeded its execution budget"),
+            MatchErrorKind::HaystackTooLarge { len } => write!(
+                f,
+                "haystack of length {len} exceeds the maximum haystack length"
+            ),
+            #[cfg(feature = "wasmi")]
+            MatchErrorKind::Wasmi(err) => err.fmt(f),
+            #[cfg(feature = "web")]
+            MatchErrorKind::Js(message) => f.write_str(message),
+        }
+    }
+}
+
+#[cfg(any(feature = "wasmi", feature = "web"))]
+impl Error for MatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &*self.kind {
+            MatchErrorKind::BudgetExceeded | MatchErrorKind::HaystackTooLarge { .. } => None,
+            #[cfg(feature = "wasmi")]
+            MatchErrorKind::Wasmi(err) => Some(err),
+            #[cfg(feature = "web")]
+            MatchErrorKind::Js(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "wasmi")]
+impl From<wasmi::Error> for MatchError {
+    fn from(value: wasmi::Error) -> Self {
+        // Running out of fuel is the engine-specific way of exceeding the budget,
+        // so we want it to be indistinguishable from the step counter
+        if let Some(wasmi::core::TrapCode::OutOfFuel) = value.as_trap_code() {
+            return Self::budget_exceeded();
+        }
+
+        Self {
+            kind: Box::new(MatchErrorKind::Wasmi(value)),
+        }
+    }
+}
+
+#[cfg(feature = "web")]
+impl From<JsValue> for MatchError {
+    fn from(value: JsValue) -> Self {
+        let message = match value.dyn_ref::<js_sys::Error>() {
+            Some(err) => String::from(err.to_string()),
+            None => format!("{value:?}"),
+        };
+
+        Self {
+            kind: Box::new(MatchErrorKind::Js(message)),
+        }
+    }
+}
+
+/// Represents the specific kind of a [`MatchError`].
+#[derive(Debug)]
+#[cfg(any(feature = "wasmi", feature = "web"))]
+enum MatchErrorKind {
+    BudgetExceeded,
+    HaystackTooLarge {
+        len: usize,
+    },
+    #[cfg(feature = "wasmi")]
+    Wasmi(wasmi::Error),
+    /// The message of an exception thrown by the JavaScript host, which is
+    /// kept as a string so that the error stays `Send` and `Sync`.
+    #[cfg(feature = "web")]
+    Js(String),
 }
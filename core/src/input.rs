@@ -1,20 +1,18 @@
-#[cfg(feature = "wasmi")]
+#[cfg(any(feature = "wasmi", feature = "web"))]
 use regex_automata::Anchored;
 
+#[cfg(any(feature = "wasmi", feature = "web"))]
+use crate::abi::AnchoredMode;
+
 /// This type is a mirror of [`regex_automata::Input`], with guaranteed
 /// alignment and no-substructs.
 #[derive(Debug)]
 #[repr(C)]
-#[cfg(feature = "wasmi")]
+#[cfg(any(feature = "wasmi", feature = "web"))]
 pub struct InputOpts {
     /// Whether to execute an "earliest" search or not.
     pub earliest: i32,
-    /// Sets the anchor mode of a search.
-    ///
-    /// The translation:
-    ///   - [`Anchored::No`] => `0`
-    ///   - [`Anchored::Yes`] => `1`
-    ///   - [`Anchored::Pattern`] => `2`
+    /// Sets the anchor mode of a search, see [`AnchoredMode`].
     pub anchored: i32,
     /// If `anchored` is equivalent to [`Anchored::Pattern`], then this is
     /// the
@@ -24,7 +22,7 @@ pub struct InputOpts {
     pub anchored_pattern: i32,
 }
 
-#[cfg(feature = "wasmi")]
+#[cfg(any(feature = "wasmi", feature = "web"))]
 impl InputOpts {
     /// Creates a new `InputOpts` from a [`regex_automata::Input`].
     ///
@@ -32,9 +30,12 @@ impl InputOpts {
     /// suitable for WASM.
     pub fn new(input: &regex_automata::Input<'_>) -> InputOpts {
         let (anchored, anchored_pattern) = match input.get_anchored() {
-            Anchored::No => (0, 0),
-            Anchored::Yes => (1, 0),
-            Anchored::Pattern(id) => (2, i32::from_ne_bytes(id.to_ne_bytes())),
+            Anchored::No => (AnchoredMode::No as i32, 0),
+            Anchored::Yes => (AnchoredMode::Yes as i32, 0),
+            Anchored::Pattern(id) => (
+                AnchoredMode::Pattern as i32,
+                i32::from_ne_bytes(id.to_ne_bytes()),
+            ),
         };
 
         InputOpts {
@@ -44,15 +45,3 @@ impl InputOpts {
         }
     }
 }
-
-/// This enum represents the results of the `prepare_input` function.
-#[derive(Debug)]
-#[cfg(feature = "compile")]
-pub enum PrepareInputResult {
-    /// Indicates that the input preparation was successful and no memory
-    /// growth was needed.
-    SuccessNoGrowth = 0,
-    /// Indicates that the input preparation was successful and memory was
-    /// grown to accommodate the haystack.
-    SuccessGrowth = 1,
-}
@@ -0,0 +1,473 @@
+//! This module describes how the states of an NFA are compiled into WASM
+//! functions and lookup tables, to help explain the size of a module.
+//!
+//! The [`Inspection`] returned by [`inspect`] is recorded while compiling the
+//! NFA. It lists, for every NFA state, whether it got an epsilon closure
+//! function or had it omitted, whether it got a transition function and which
+//! epsilon closure that function inserts inline, and which kind of lookup
+//! table holds its transitions. Function names are those of the final module,
+//! after identical functions were merged. [`Inspection::to_dot`] renders the
+//! same information as a Graphviz graph of the NFA.
+//!
+//! The [`SizeReport`] returned by [`size_report`] attributes the bytes of an
+//! already compiled module to its sections, functions, and data segments.
+
+use std::fmt::Write;
+
+use regex_automata::{
+    nfa::thompson::{NFA, State},
+    util::primitives::StateID,
+};
+
+#[cfg(feature = "compile")]
+use crate::{BuildError, Config};
+
+pub use self::size::{ItemSize, SizeReport, size_report};
+
+mod size;
+
+/// Describes the functions and lookup tables generated for every state of an
+/// NFA.
+#[derive(Debug, Clone)]
+pub struct Inspection {
+    /// The information for each state, indexed by the state ID.
+    pub states: Vec<StateInfo>,
+    /// The state that anchored searches start at.
+    pub start_anchored: StateID,
+    /// The state that unanchored searches start at.
+    pub start_unanchored: StateID,
+}
+
+/// Describes the functions and lookup tables generated for a single NFA state.
+#[derive(Debug, Clone)]
+pub struct StateInfo {
+    /// The ID of the state.
+    pub id: StateID,
+    /// The NFA state itself.
+    pub state: State,
+    /// How the epsilon closure of the state is computed.
+    pub epsilon_closure: EpsilonClosure,
+    /// The name of the function that follows the transitions out of this
+    /// state, if any.
+    ///
+    /// Only states that consume a byte or match have a transition function.
+    pub transition_fn: Option<String>,
+    /// The states of the epsilon closure that the transition function inserts
+    /// directly, instead of calling the epsilon closure function of the next
+    /// state.
+    ///
+    /// See [`Config::inline_epsilon_closure_threshold`].
+    pub inlined_closure: Option<Vec<StateID>>,
+    /// The lookup table that holds the transitions out of this state.
+    pub lookup_table: LookupTable,
+}
+
+/// Describes how the epsilon closure of a state is computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpsilonClosure {
+    /// The closure is computed by a dedicated function.
+    Function {
+        /// The name of the function.
+        name: String,
+        /// The number of states that are always in the closure, including the
+        /// state itself.
+        unconditional_len: usize,
+        /// The number of look-around assertions that must be checked to find
+        /// the rest of the closure.
+        lookaround_len: usize,
+        /// Whether the states that are always in the closure are inserted with
+        /// a single call, from a run of state IDs in a data segment.
+        bulk_insert: bool,
+    },
+    /// The function was omitted because the closure only contains the state
+    /// itself, which `branch_to_epsilon_closure` inserts by default.
+    Omitted,
+    /// The function was omitted because every transition that leads to the
+    /// state inserts the closure directly.
+    ///
+    /// See [`Config::inline_epsilon_closure_threshold`].
+    Inlined,
+}
+
+/// Describes the lookup table that holds the transitions out of a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupTable {
+    /// The state has no lookup table, either because it has no byte
+    /// transitions, or because its single byte range is embedded in its
+    /// transition function.
+    None,
+    /// The state has a table of byte ranges, and a table of the target state
+    /// for each range.
+    Sparse {
+        /// The number of byte ranges in the table.
+        len: usize,
+    },
+    /// The state has a table with the target state for each of the 256 bytes.
+    Dense,
+}
+
+/// Compiles the NFA with the given configuration, and describes the
+/// functions and lookup tables that were generated for each of its states.
+#[cfg(feature = "compile")]
+pub fn inspect(nfa: &NFA, config: Config) -> Result<Inspection, BuildError> {
+    let (_, states) = crate::compile::compile_and_inspect(nfa.clone(), None, config)?;
+    Ok(Inspection {
+        states,
+        start_anchored: nfa.start_anchored(),
+        start_unanchored: nfa.start_unanchored(),
+    })
+}
+
+impl Inspection {
+    /// Renders the NFA as a [Graphviz] graph in the DOT language, where each
+    /// node is labelled with its generated functions and lookup table.
+    ///
+    /// Nodes with an epsilon closure function are filled, and epsilon
+    /// transitions are dashed.
+    ///
+    /// [Graphviz]: https://graphviz.org/doc/info/lang.html
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_active(&[])
+    }
+
+    /// Renders the NFA like [`to_dot`][Self::to_dot], with a bold red outline
+    /// around the given states.
+    ///
+    /// This is intended to show the active states of a search, such as those
+    /// reported by [`Config::trace`][crate::Config::trace].
+    pub fn to_dot_with_active(&self, active: &[StateID]) -> String {
+        let mut out = String::new();
+        out.push_str("digraph nfa {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+        out.push_str("    edge [fontname=monospace];\n");
+        out.push_str("    start_anchored [shape=plaintext, label=\"anchored\"];\n");
+        out.push_str("    start_unanchored [shape=plaintext, label=\"unanchored\"];\n");
+        writeln!(
+            out,
+            "    start_anchored -> s{};",
+            self.start_anchored.as_usize()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    start_unanchored -> s{};",
+            self.start_unanchored.as_usize()
+        )
+        .unwrap();
+
+        for info in &self.states {
+            info.write_dot_node(&mut out, active.contains(&info.id));
+        }
+        for info in &self.states {
+            info.write_dot_edges(&mut out);
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl StateInfo {
+    fn write_dot_node(&self, out: &mut String, is_active: bool) {
+        let mut label = format!("s{}: {}", self.id.as_usize(), state_kind(&self.state));
+        match &self.epsilon_closure {
+            EpsilonClosure::Function {
+                name,
+                unconditional_len,
+                lookaround_len,
+                bulk_insert,
+            } => {
+                write!(
+                    label,
+                    "\n{name} ({unconditional_len} states, {lookaround_len} looks)"
+                )
+                .unwrap();
+                if *bulk_insert {
+                    label.push_str("\nbulk insert");
+                }
+            },
+            EpsilonClosure::Omitted => label.push_str("\nepsilon closure omitted"),
+            EpsilonClosure::Inlined => label.push_str("\nepsilon closure inlined"),
+        }
+        if let Some(transition_fn) = &self.transition_fn {
+            write!(label, "\n{transition_fn}").unwrap();
+        }
+        if let Some(inlined_closure) = &self.inlined_closure {
+            write!(
+                label,
+                "\ninlines closure ({} states)",
+                inlined_closure.len()
+            )
+            .unwrap();
+        }
+        match self.lookup_table {
+            LookupTable::None => {},
+            LookupTable::Sparse { len } => write!(label, "\nsparse table ({len} ranges)").unwrap(),
+            LookupTable::Dense => label.push_str("\ndense table"),
+        }
+
+        label.push('\n');
+        let mut attrs = format!("label={}", dot_string(&label));
+        if matches!(self.epsilon_closure, EpsilonClosure::Function { .. }) {
+            attrs.push_str(", style=filled, fillcolor=lightblue");
+        }
+        if matches!(self.state, State::Match { .. }) {
+            attrs.push_str(", peripheries=2");
+        }
+        if is_active {
+            attrs.push_str(", color=red, penwidth=3");
+        }
+        writeln!(out, "    s{} [{attrs}];", self.id.as_usize()).unwrap();
+    }
+
+    fn write_dot_edges(&self, out: &mut String) {
+        let from = self.id.as_usize();
+        let mut edge = |to: StateID, label: String, epsilon: bool| {
+            writeln!(
+                out,
+                "    s{from} -> s{} [label={}{}];",
+                to.as_usize(),
+                dot_string(&label),
+                if epsilon { ", style=dashed" } else { "" }
+            )
+            .unwrap();
+        };
+
+        match &self.state {
+            State::ByteRange { trans } => {
+                edge(trans.next, byte_range(trans.start, trans.end), false);
+            },
+            State::Sparse(sparse) => {
+                for trans in sparse.transitions.iter() {
+                    edge(trans.next, byte_range(trans.start, trans.end), false);
+                }
+            },
+            State::Dense(dense) => {
+                // Merge runs of bytes with the same target into ranges
+                let mut start = 0;
+                for end in 0..=255u8 {
+                    let next = dense.transitions[usize::from(end)];
+                    let is_run_end = end == 255 || dense.transitions[usize::from(end) + 1] != next;
+                    if is_run_end {
+                        if next != StateID::ZERO {
+                            edge(next, byte_range(start, end), false);
+                        }
+                        start = end.wrapping_add(1);
+                    }
+                }
+            },
+            State::Look { look, next } => edge(*next, format!("{look:?}"), true),
+            State::Union { alternates } => {
+                for (priority, next) in alternates.iter().enumerate() {
+                    edge(*next, priority.to_string(), true);
+                }
+            },
+            State::BinaryUnion { alt1, alt2 } => {
+                edge(*alt1, "0".into(), true);
+                edge(*alt2, "1".into(), true);
+            },
+            State::Capture {
+                next, group_index, ..
+            } => edge(*next, format!("group {}", group_index.as_usize()), true),
+            State::Fail | State::Match { .. } => {},
+        }
+    }
+}
+
+fn state_kind(state: &State) -> String {
+    match state {
+        State::ByteRange { .. } => "byte range".into(),
+        State::Sparse(_) => "sparse".into(),
+        State::Dense(_) => "dense".into(),
+        State::Look { .. } => "look".into(),
+        State::Union { .. } => "union".into(),
+        State::BinaryUnion { .. } => "binary union".into(),
+        State::Capture { pattern_id, .. } => format!("capture (pattern {})", pattern_id.as_usize()),
+        State::Fail => "fail".into(),
+        State::Match { pattern_id } => format!("match (pattern {})", pattern_id.as_usize()),
+    }
+}
+
+fn byte_range(start: u8, end: u8) -> String {
+    if start == end {
+        format!("{}", start.escape_ascii())
+    } else {
+        format!("{}-{}", start.escape_ascii(), end.escape_ascii())
+    }
+}
+
+/// Formats the value as a quoted DOT string.
+fn dot_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            // Ends a left-justified line
+            '\n' => out.push_str("\\l"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(all(test, feature = "compile"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inspect_states() {
+        let nfa = NFA::new(r"(?:a|b|d)+c").unwrap();
+        let inspection = inspect(&nfa, Config::new()).unwrap();
+
+        let sparse = inspection
+            .states
+            .iter()
+            .find(|info| matches!(info.state, State::Sparse(_)))
+            .unwrap();
+        assert_eq!(sparse.lookup_table, LookupTable::Sparse { len: 2 });
+        assert_eq!(
+            sparse.transition_fn,
+            Some(format!("transition_s{}", sparse.id.as_usize()))
+        );
+        assert_eq!(sparse.epsilon_closure, EpsilonClosure::Omitted);
+
+        let union = inspection
+            .states
+            .iter()
+            .find(|info| matches!(info.state, State::BinaryUnion { .. }))
+            .unwrap();
+        assert!(matches!(
+            union.epsilon_closure,
+            EpsilonClosure::Function { .. }
+        ));
+        assert_eq!(union.transition_fn, None);
+        assert_eq!(union.lookup_table, LookupTable::None);
+    }
+
+    #[test]
+    fn inspect_inlined_closures() {
+        let nfa = NFA::new(r"(?:ab|cd)+").unwrap();
+        let default_inspection = inspect(&nfa, Config::new()).unwrap();
+        assert!(
+            default_inspection
+                .states
+                .iter()
+                .all(|info| info.inlined_closure.is_none())
+        );
+
+        let config = Config::new().inline_epsilon_closure_threshold(4);
+        let inspection = inspect(&nfa, config).unwrap();
+        // Closures that only inlined transitions lead to don't get a function
+        let inlined_only = inspection
+            .states
+            .iter()
+            .filter(|info| info.epsilon_closure == EpsilonClosure::Inlined)
+            .map(|info| info.id.as_usize())
+            .collect::<Vec<_>>();
+        assert!(!inlined_only.is_empty());
+        for sid in inlined_only {
+            assert!(matches!(
+                default_inspection.states[sid].epsilon_closure,
+                EpsilonClosure::Function { .. }
+            ));
+        }
+        let mut inlined = inspection
+            .states
+            .iter()
+            .filter_map(|info| Some((&info.state, info.inlined_closure.as_ref()?)))
+            .peekable();
+        assert!(inlined.peek().is_some());
+        for (state, closure) in inlined {
+            let State::ByteRange { trans } = state else {
+                panic!("only byte range transitions inline closures: {state:?}");
+            };
+            assert!(closure.contains(&trans.next));
+            assert!(closure.len() <= 4);
+        }
+    }
+
+    #[test]
+    fn inspect_final_function_names() {
+        let nfa = NFA::new(r"(?:x?y?)*z").unwrap();
+        let config = Config::new().include_names(true);
+        let inspection = inspect(&nfa, config).unwrap();
+        let closures = inspection
+            .states
+            .iter()
+            .filter_map(|info| match &info.epsilon_closure {
+                EpsilonClosure::Function {
+                    name,
+                    unconditional_len,
+                    bulk_insert,
+                    ..
+                } => Some((name, *unconditional_len, *bulk_insert)),
+                EpsilonClosure::Omitted | EpsilonClosure::Inlined => None,
+            })
+            .collect::<Vec<_>>();
+        for (_, unconditional_len, bulk_insert) in &closures {
+            assert_eq!(*bulk_insert, *unconditional_len > 1);
+        }
+        // The identical closure functions were merged under a condensed name
+        assert!(
+            closures
+                .iter()
+                .filter(|(name, ..)| name.ends_with("_functions"))
+                .count()
+                > 1
+        );
+
+        let bytecode = crate::compile::compile_from_nfa(nfa, None, config).unwrap();
+        let report = size_report(&bytecode).unwrap();
+        let names = inspection
+            .states
+            .iter()
+            .flat_map(|info| {
+                let closure_fn = match &info.epsilon_closure {
+                    EpsilonClosure::Function { name, .. } => Some(name),
+                    EpsilonClosure::Omitted | EpsilonClosure::Inlined => None,
+                };
+                closure_fn.into_iter().chain(&info.transition_fn)
+            })
+            .collect::<Vec<_>>();
+        assert!(!names.is_empty());
+        for name in names {
+            assert!(
+                report
+                    .functions
+                    .iter()
+                    .any(|function| &function.name == name),
+                "{name} is not in the module"
+            );
+        }
+    }
+
+    #[test]
+    fn inspect_to_dot() {
+        let nfa = NFA::new(r"(?:a|\x22)+$").unwrap();
+        let dot = inspect(&nfa, Config::new()).unwrap().to_dot();
+        insta::assert_snapshot!(dot);
+    }
+
+    #[test]
+    fn inspect_to_dot_with_active() {
+        let nfa = NFA::new(r"ab").unwrap();
+        let inspection = inspect(&nfa, Config::new()).unwrap();
+        let active = nfa.start_anchored();
+        let dot = inspection.to_dot_with_active(&[active]);
+
+        let highlighted = dot
+            .lines()
+            .filter(|line| line.contains("color=red"))
+            .collect::<Vec<_>>();
+        assert_eq!(highlighted.len(), 1);
+        assert!(highlighted[0].starts_with(&format!("    s{} [", active.as_usize())));
+        assert_eq!(
+            dot.replace(", color=red, penwidth=3", ""),
+            inspection.to_dot()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! This module attributes the bytes of a compiled module to its sections,
+//! functions, and data segments.
+
+use std::{collections::HashMap, fmt};
+
+use wasmparser::{ExternalKind, Name, NameSectionReader, Parser, Payload};
+
+use crate::{BuildError, RegexBytecode};
+
+/// The size in bytes of each section, function, and data segment of a
+/// compiled module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The size of the whole module.
+    pub total: usize,
+    /// The size of each section, including its header, in module order.
+    pub sections: Vec<ItemSize>,
+    /// The size of each function body in the code section, largest first.
+    pub functions: Vec<ItemSize>,
+    /// The size of each data segment in the data section, largest first.
+    pub data_segments: Vec<ItemSize>,
+}
+
+/// The size in bytes of a single part of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemSize {
+    /// The name of the part.
+    ///
+    /// Functions and data segments are named by the name section if present,
+    /// then by their export name, and otherwise by their index.
+    pub name: String,
+    /// The size in bytes.
+    pub size: usize,
+}
+
+/// Attributes the bytes of the module to its sections, functions, and data
+/// segments.
+///
+/// The names of functions and data segments are only available if the module
+/// was compiled with [`Config::include_names`][crate::Config::include_names].
+/// Each lookup table only gets its own data segment if the module was compiled
+/// with [`Config::compact_data_section`][crate::Config::compact_data_section]
+/// disabled.
+pub fn size_report(bytecode: &RegexBytecode) -> Result<SizeReport, BuildError> {
+    let bytes = bytecode.as_ref();
+
+    let mut sections = Vec::new();
+    // (function index, size)
+    let mut functions = Vec::new();
+    // (data segment index, size)
+    let mut data_segments = Vec::new();
+    let mut function_names = HashMap::new();
+    let mut data_names = HashMap::new();
+    let mut num_imported_functions = 0;
+    // Each section is attributed all the bytes since the end of the previous one,
+    // so that the section headers are included
+    let mut previous_end = 0;
+
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload?;
+        match &payload {
+            Payload::Version { range, .. } => {
+                sections.push(ItemSize {
+                    name: "header".into(),
+                    size: range.end,
+                });
+                previous_end = range.end;
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader.clone() {
+                    if matches!(import?.ty, wasmparser::TypeRef::Func(_)) {
+                        num_imported_functions += 1;
+                    }
+                }
+            },
+            Payload::ExportSection(reader) => {
+                for export in reader.clone() {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        function_names.insert(export.index, export.name.to_owned());
+                    }
+                }
+            },
+            Payload::CodeSectionEntry(body) => {
+                let index = num_imported_functions + u32::try_from(functions.len()).unwrap();
+                functions.push((index, body.range().len()));
+            },
+            Payload::DataSection(reader) => {
+                for data in reader.clone() {
+                    let index = u32::try_from(data_segments.len()).unwrap();
+                    data_segments.push((index, data?.range.len()));
+                }
+            },
+            Payload::CustomSection(reader) if reader.name() == "name" => {
+                let names = NameSectionReader::new(wasmparser::BinaryReader::new(
+                    reader.data(),
+                    reader.data_offset(),
+                ));
+                for name in names {
+                    match name? {
+                        Name::Function(map) => {
+                            for naming in map {
+                                let naming = naming?;
+                                function_names.insert(naming.index, naming.name.to_owned());
+                            }
+                        },
+                        Name::Data(map) => {
+                            for naming in map {
+                                let naming = naming?;
+                                data_names.insert(naming.index, naming.name.to_owned());
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        if let Some((id, range)) = payload.as_section() {
+            let name = match &payload {
+                Payload::CustomSection(reader) => format!("custom {:?}", reader.name()),
+                _ => section_name(id).into(),
+            };
+            sections.push(ItemSize {
+                name,
+                size: range.end - previous_end,
+            });
+            previous_end = range.end;
+        }
+    }
+
+    let mut functions = functions
+        .into_iter()
+        .map(|(index, size)| ItemSize {
+            name: function_names
+                .remove(&index)
+                .unwrap_or_else(|| format!("function {index}")),
+            size,
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|item| std::cmp::Reverse(item.size));
+    let mut data_segments = data_segments
+        .into_iter()
+        .map(|(index, size)| ItemSize {
+            name: data_names
+                .remove(&index)
+                .unwrap_or_else(|| format!("data {index}")),
+            size,
+        })
+        .collect::<Vec<_>>();
+    data_segments.sort_by_key(|item| std::cmp::Reverse(item.size));
+
+    Ok(SizeReport {
+        total: bytes.len(),
+        sections,
+        functions,
+        data_segments,
+    })
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "data count",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total: {} bytes", self.total)?;
+        for (heading, items) in [
+            ("sections", &self.sections),
+            ("functions", &self.functions),
+            ("data segments", &self.data_segments),
+        ] {
+            writeln!(f, "\n{heading}:")?;
+            let name_width = items.iter().map(|item| item.name.len()).max().unwrap_or(0);
+            for item in items {
+                writeln!(
+                    f,
+                    "  {:<name_width$}  {:>6}  {:>5.1}%",
+                    item.name,
+                    item.size,
+                    100.0 * item.size as f64 / self.total as f64
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "compile"))]
+mod tests {
+    use crate::{Builder, Config};
+
+    use super::*;
+
+    #[test]
+    fn sections_add_up() {
+        let (bytecode, _) = Builder::new()
+            .configure(
+                Config::new()
+                    .include_names(true)
+                    .compact_data_section(false),
+            )
+            .build("a|b|d|e|g")
+            .unwrap();
+        let report = size_report(&bytecode).unwrap();
+
+        assert_eq!(
+            report.sections.iter().map(|item| item.size).sum::<usize>(),
+            report.total
+        );
+        let code = report
+            .sections
+            .iter()
+            .find(|item| item.name == "code")
+            .unwrap();
+        assert!(report.functions.iter().map(|item| item.size).sum::<usize>() < code.size);
+        assert!(
+            report
+                .functions
+                .iter()
+                .any(|item| item.name.starts_with("transition_s"))
+        );
+        assert!(
+            report
+                .data_segments
+                .iter()
+                .any(|item| item.name.starts_with("sparse_range_table_"))
+        );
+    }
+
+    #[test]
+    fn unnamed_items() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().include_names(false))
+            .build("a")
+            .unwrap();
+        let report = size_report(&bytecode).unwrap();
+
+        // Exported functions are still named
+        assert!(report.functions.iter().any(|item| item.name == "is_match"));
+        assert!(
+            report
+                .functions
+                .iter()
+                .any(|item| item.name.starts_with("function "))
+        );
+    }
+}
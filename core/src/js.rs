@@ -0,0 +1,345 @@
+//! This module generates JavaScript wrappers, with TypeScript declarations,
+//! for compiled regular expression modules.
+//!
+//! The wrapper implements the [memory-copy protocol][crate::abi] so that
+//! consumers can search JavaScript strings directly. String indices passed to
+//! the wrapper are in UTF-16 code units, like all JavaScript string indices,
+//! and are converted into UTF-8 byte offsets for the module.
+//!
+//! Only `isMatch` is generated, since that is the only search that the module
+//! implements. The capture group names of each pattern are exported as
+//! `groupNames`.
+
+use std::fmt::Write;
+
+use crate::{BuildError, RegexBytecode, RegexInfo, abi, metadata::Metadata};
+
+/// The generated wrapper for a compiled regular expression module.
+#[derive(Debug, Clone)]
+pub struct JsWrapper {
+    /// The JavaScript module, intended to be written to a `.mjs` file.
+    pub module: String,
+    /// The TypeScript declarations for the JavaScript module, intended to be
+    /// written to a `.d.mts` file next to it.
+    pub declarations: String,
+}
+
+/// Generates a wrapper for the given module.
+///
+/// The `wasm_path` is the URL of the `.wasm` file relative to the generated
+/// `.mjs` file, and is used when no other source is passed to `Regex.load`.
+///
+/// Returns an error if the module was compiled without metadata, see
+/// [`Config::include_metadata`][crate::Config::include_metadata].
+pub fn generate(bytecode: &RegexBytecode, wasm_path: &str) -> Result<JsWrapper, BuildError> {
+    let info = RegexInfo::from_bytecode(bytecode)?;
+    let metadata = bytecode
+        .metadata()?
+        .expect("metadata must be present if the info was restored");
+
+    Ok(JsWrapper {
+        module: generate_module(&info, &metadata, wasm_path),
+        declarations: generate_declarations(&metadata),
+    })
+}
+
+fn generate_module(info: &RegexInfo, metadata: &Metadata, wasm_path: &str) -> String {
+    let mut out = String::new();
+    out.push_str("// @generated by wahgex\n\n");
+
+    writeln!(out, "const ABI_VERSION = {};", abi::VERSION).unwrap();
+    writeln!(
+        out,
+        "const PREPARE_INPUT_HAYSTACK_TOO_LARGE = {};",
+        abi::PrepareInputResult::HaystackTooLarge as i32
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "const IS_MATCH_MATCH = {};",
+        abi::IsMatchResult::Match as i32
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "const IS_MATCH_BUDGET_EXCEEDED = {};",
+        abi::IsMatchResult::BudgetExceeded as i32
+    )
+    .unwrap();
+    writeln!(out, "const ANCHORED_NO = {};", abi::AnchoredMode::No as i32).unwrap();
+    writeln!(
+        out,
+        "const ANCHORED_YES = {};",
+        abi::AnchoredMode::Yes as i32
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "const ANCHORED_PATTERN = {};",
+        abi::AnchoredMode::Pattern as i32
+    )
+    .unwrap();
+    out.push('\n');
+
+    writeln!(
+        out,
+        "export const patterns = {};",
+        metadata
+            .patterns()
+            .map(|patterns| js_array(patterns.iter().map(|pattern| js_string(pattern))))
+            .unwrap_or_else(|| "null".into())
+    )
+    .unwrap();
+    writeln!(out, "export const patternLen = {};", info.pattern_len()).unwrap();
+    writeln!(
+        out,
+        "export const groupNames = {};",
+        js_array(metadata.group_names().iter().map(|names| {
+            js_array(
+                names
+                    .iter()
+                    .map(|name| name.as_deref().map_or_else(|| "null".into(), js_string)),
+            )
+        }))
+    )
+    .unwrap();
+    out.push('\n');
+
+    writeln!(
+        out,
+        "const defaultSource = new URL({}, import.meta.url);",
+        js_string(wasm_path)
+    )
+    .unwrap();
+    out.push_str(MODULE_BODY);
+
+    out
+}
+
+fn generate_declarations(metadata: &Metadata) -> String {
+    let mut out = String::new();
+    out.push_str("// @generated by wahgex\n\n");
+
+    if let Some(patterns) = metadata.patterns() {
+        out.push_str("/** The patterns the module was compiled from. */\n");
+        writeln!(
+            out,
+            "export declare const patterns: readonly [{}];",
+            patterns
+                .iter()
+                .map(|pattern| js_string(pattern))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+    } else {
+        out.push_str("export declare const patterns: null;\n");
+    }
+    out.push_str("/** The number of patterns in the module. */\n");
+    writeln!(
+        out,
+        "export declare const patternLen: {};",
+        metadata.pattern_len()
+    )
+    .unwrap();
+    out.push_str(
+        "/** The capture group names of each pattern, `null` for unnamed groups. */\nexport \
+         declare const groupNames: readonly (readonly (string | null)[])[];\n",
+    );
+    out.push_str(DECLARATIONS_BODY);
+
+    out
+}
+
+/// Formats the items as a JavaScript array literal.
+fn js_array(items: impl IntoIterator<Item = String>) -> String {
+    let items = items.into_iter().collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+/// Formats the value as a JavaScript string literal.
+fn js_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // Line terminators in JavaScript, even though they are valid in JSON
+            '\u{2028}' | '\u{2029}' => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c if c.is_control() => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The part of the JavaScript module that doesn't depend on the compiled
+/// module.
+const MODULE_BODY: &str = r#"
+/**
+ * Returns the number of UTF-8 bytes needed to encode the first `index` UTF-16
+ * code units of `haystack`.
+ */
+function utf8Offset(haystack, index) {
+    let offset = 0;
+    for (let i = 0; i < index; i++) {
+        const unit = haystack.charCodeAt(i);
+        if (unit < 0x80) {
+            offset += 1;
+        } else if (unit < 0x800) {
+            offset += 2;
+        } else if ((unit & 0xfc00) === 0xd800 && (haystack.charCodeAt(i + 1) & 0xfc00) === 0xdc00) {
+            // A surrogate pair is a single 4 byte code point
+            offset += 4;
+            i++;
+        } else {
+            // Lone surrogates are encoded as the 3 byte replacement character
+            offset += 3;
+        }
+    }
+    return offset;
+}
+
+export class Regex {
+    /**
+     * Compiles and instantiates the module from the given source, which is
+     * either the module bytes or a URL to fetch them from.
+     */
+    static async load(source = defaultSource) {
+        let result;
+        if (source instanceof ArrayBuffer || ArrayBuffer.isView(source)) {
+            result = await WebAssembly.instantiate(source);
+        } else {
+            const response = source instanceof Response ? source : fetch(source);
+            result = await WebAssembly.instantiateStreaming(response);
+        }
+        return new Regex(result.instance);
+    }
+
+    constructor(instance) {
+        const abiVersion = instance.exports.abi_version?.value;
+        if (abiVersion !== ABI_VERSION) {
+            throw new Error(
+                `Module implements ABI version [${abiVersion}], but version [${ABI_VERSION}] is required`,
+            );
+        }
+        this.exports = instance.exports;
+        this.encoder = new TextEncoder();
+    }
+
+    /**
+     * Returns true if the haystack contains a match between the `start` and
+     * `end` string indices.
+     */
+    isMatch(haystack, { start = 0, end = haystack.length, anchored = false } = {}) {
+        if (start < 0 || start > end || end > haystack.length) {
+            throw new RangeError(
+                `Invalid search span [${start}, ${end}) for haystack of length [${haystack.length}]`,
+            );
+        }
+
+        const bytes = this.encoder.encode(haystack);
+        const haystackLen = BigInt(bytes.length);
+        if (this.exports.prepare_input(haystackLen) === PREPARE_INPUT_HAYSTACK_TOO_LARGE) {
+            throw new RangeError(
+                `Haystack of length [${bytes.length}] exceeds the maximum haystack length`,
+            );
+        }
+        // The memory may have grown, so the buffer must be fetched again
+        new Uint8Array(this.exports.haystack.buffer).set(bytes);
+
+        let anchoredMode = ANCHORED_NO;
+        let anchoredPattern = 0;
+        if (anchored === true) {
+            anchoredMode = ANCHORED_YES;
+        } else if (typeof anchored === "number") {
+            anchoredMode = ANCHORED_PATTERN;
+            anchoredPattern = anchored;
+        }
+
+        const result = this.exports.is_match(
+            anchoredMode,
+            anchoredPattern,
+            BigInt(utf8Offset(haystack, start)),
+            BigInt(utf8Offset(haystack, end)),
+            haystackLen,
+        );
+        if (result === IS_MATCH_BUDGET_EXCEEDED) {
+            throw new Error("Search exceeded its execution budget");
+        }
+        return result === IS_MATCH_MATCH;
+    }
+}
+"#;
+
+/// The part of the TypeScript declarations that doesn't depend on the
+/// compiled module.
+const DECLARATIONS_BODY: &str = r#"
+export interface SearchOptions {
+    /** The string index to start searching at, defaults to `0`. */
+    start?: number;
+    /** The string index to stop searching at, defaults to the haystack length. */
+    end?: number;
+    /**
+     * Whether the match must start at `start`. If a number is given, then only
+     * the pattern with that ID is searched for, anchored.
+     */
+    anchored?: boolean | number;
+}
+
+export declare class Regex {
+    /**
+     * Compiles and instantiates the module from the given source, which is
+     * either the module bytes or a URL to fetch them from.
+     */
+    static load(source?: BufferSource | Response | URL | string): Promise<Regex>;
+
+    constructor(instance: WebAssembly.Instance);
+
+    /**
+     * Returns true if the haystack contains a match between the `start` and
+     * `end` string indices.
+     */
+    isMatch(haystack: string, options?: SearchOptions): boolean;
+}
+"#;
+
+#[cfg(all(test, feature = "compile"))]
+mod tests {
+    use crate::{Builder, Config};
+
+    use super::*;
+
+    #[test]
+    fn generate_wrapper() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().include_patterns(true))
+            .build_many(&[r"(?<year>\d{4})-(?<month>\d{2})", "\"quoted\"\n"])
+            .unwrap();
+
+        let wrapper = generate(&bytecode, "./dates.wasm").unwrap();
+        insta::assert_snapshot!("generate_wrapper_module", wrapper.module);
+        insta::assert_snapshot!("generate_wrapper_declarations", wrapper.declarations);
+    }
+
+    #[test]
+    fn generate_requires_metadata() {
+        let (bytecode, _) = Builder::new()
+            .configure(Config::new().include_metadata(false))
+            .build("a")
+            .unwrap();
+
+        assert!(generate(&bytecode, "./a.wasm").is_err());
+    }
+
+    #[test]
+    fn escape_js_string() {
+        assert_eq!(js_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(js_string("\n\u{0}\u{2028}"), r#""\n\u0000\u2028""#);
+    }
+}
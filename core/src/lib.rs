@@ -11,22 +11,34 @@ use compile::compile_from_nfa;
 use regex_automata::nfa::thompson::Compiler;
 use wasmparser::types::Types;
 
-pub use crate::error::BuildError;
+#[cfg(any(feature = "wasmi", feature = "web"))]
+pub use crate::error::MatchError;
+pub use crate::{error::BuildError, metadata::Metadata};
 pub use regex_automata::{
-    Input,
+    Input, MatchKind,
     nfa::thompson::{Config as RegexNFAConfig, NFA},
-    util::syntax::Config as RegexSyntaxConfig,
+    util::{
+        captures::GroupInfo,
+        look::{LookMatcher, LookSet},
+        syntax::Config as RegexSyntaxConfig,
+    },
 };
 
+pub mod abi;
 #[cfg(feature = "compile")]
 mod compile;
-#[cfg(feature = "wasmi")]
+#[cfg(feature = "component")]
+pub mod component;
+#[cfg(any(feature = "wasmi", feature = "web"))]
 pub mod engines;
 mod error;
 mod input;
+pub mod inspect;
+pub mod js;
+mod metadata;
 
 /// Configuration options for building a regular expression.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Config {
     #[cfg(test)]
     export_state: Option<bool>,
@@ -34,6 +46,14 @@ pub struct Config {
     export_all_functions: Option<bool>,
     include_names: Option<bool>,
     compact_data_section: Option<bool>,
+    step_counter: Option<bool>,
+    max_haystack_len: Option<usize>,
+    include_metadata: Option<bool>,
+    include_patterns: Option<bool>,
+    wasi_grep: Option<bool>,
+    trace: Option<bool>,
+    inline_epsilon_closure_threshold: Option<usize>,
+    branch_hints: Option<bool>,
 }
 
 impl Config {
@@ -116,6 +136,189 @@ impl Config {
         self.compact_data_section.unwrap_or(true)
     }
 
+    /// Configures whether the output WASM module will count the iterations of
+    /// its search loop against a budget provided by the host.
+    ///
+    /// When enabled, the module exports a mutable `step_budget` global which is
+    /// decremented once per haystack offset. Once the budget reaches zero,
+    /// the search stops and reports that the budget was exceeded. This is
+    /// an engine-independent alternative to fuel metering, for engines that
+    /// don't support it.
+    ///
+    /// This value defaults to `false`.
+    pub fn step_counter(mut self, step_counter: bool) -> Self {
+        self.step_counter = Some(step_counter);
+        self
+    }
+
+    /// Return `true` if the WASM module will count search loop iterations
+    /// against a step budget.
+    pub fn get_step_counter(&self) -> bool {
+        self.step_counter.unwrap_or(false)
+    }
+
+    /// Configures the maximum length of haystack, in bytes, that the output
+    /// WASM module will accept.
+    ///
+    /// The haystack memory is given a matching maximum size, and the
+    /// `prepare_input` function will refuse longer haystacks without growing
+    /// the memory. This bounds the memory that a single instance can hold on
+    /// to.
+    ///
+    /// By default, there is no limit.
+    pub fn max_haystack_len(mut self, max_haystack_len: usize) -> Self {
+        self.max_haystack_len = Some(max_haystack_len);
+        self
+    }
+
+    /// Return the maximum length of haystack that the WASM module will accept,
+    /// or `None` if there is no limit.
+    pub fn get_max_haystack_len(&self) -> Option<usize> {
+        self.max_haystack_len
+    }
+
+    /// Configures whether the output WASM module will contain a `wahgex`
+    /// custom section describing the patterns and compiler that produced it.
+    ///
+    /// This value defaults to `true`. The section can be read back using
+    /// [`RegexBytecode::metadata`], and allows modules built for an
+    /// incompatible [ABI version][abi::VERSION] to be rejected when they are
+    /// loaded. It is also required by [`RegexContext::from_bytecode`]. The
+    /// pattern strings themselves are only included if
+    /// [`Config::include_patterns`] is enabled.
+    pub fn include_metadata(mut self, include_metadata: bool) -> Self {
+        self.include_metadata = Some(include_metadata);
+        self
+    }
+
+    /// Return `true` if the `wahgex` metadata section will be included in the
+    /// output WASM module.
+    pub fn get_include_metadata(&self) -> bool {
+        self.include_metadata.unwrap_or(true)
+    }
+
+    /// Configures whether the `wahgex` metadata section will contain the
+    /// pattern strings that the module was compiled from.
+    ///
+    /// The patterns are not needed to run the module, and may be large or
+    /// not meant to be shipped with it. Without them,
+    /// [`Metadata::patterns`] returns `None`. This has no effect if
+    /// [`Config::include_metadata`] is disabled.
+    ///
+    /// This value defaults to `false`.
+    pub fn include_patterns(mut self, include_patterns: bool) -> Self {
+        self.include_patterns = Some(include_patterns);
+        self
+    }
+
+    /// Return `true` if the `wahgex` metadata section will contain the pattern
+    /// strings.
+    pub fn get_include_patterns(&self) -> bool {
+        self.include_patterns.unwrap_or(false)
+    }
+
+    /// Configures whether the output WASM module will be a standalone WASI
+    /// command that filters lines of text, like `grep`.
+    ///
+    /// When enabled, the module imports `fd_read`, `fd_write`, `args_get`,
+    /// `args_sizes_get`, and `proc_exit` from `wasi_snapshot_preview1`, and
+    /// exports a `_start` function along with a 32-bit `memory` for the
+    /// buffers passed to the host. The `_start` function reads stdin line by
+    /// line, and writes every line that matches any of the patterns to stdout.
+    /// The exit code is `0` if any line was selected, `1` if none were, and `2`
+    /// if an error occurred. These flags are accepted as arguments:
+    ///
+    ///  - `-v` selects the lines that don't match instead.
+    ///  - `-c` only writes the number of selected lines.
+    ///  - `-n` prefixes each line with its 1-based line number.
+    ///
+    /// The `prepare_input` and `is_match` functions are still exported, but the
+    /// module can only be instantiated by a host that provides the WASI
+    /// imports. This value defaults to `false`.
+    pub fn wasi_grep(mut self, wasi_grep: bool) -> Self {
+        self.wasi_grep = Some(wasi_grep);
+        self
+    }
+
+    /// Return `true` if the WASM module will be a standalone WASI command.
+    pub fn get_wasi_grep(&self) -> bool {
+        self.wasi_grep.unwrap_or(false)
+    }
+
+    /// Configures whether the output WASM module will report the active NFA
+    /// states at every haystack offset to the host.
+    ///
+    /// When enabled, the module imports a `trace` function from the `wahgex`
+    /// module, which `is_match` calls after computing the transitions at each
+    /// offset. The module also exports its `state` memory and a `state_id_size`
+    /// global, so that the host can decode the set of states, see
+    /// [`abi`] for the details. This is intended for debugging, for example by
+    /// comparing the sets against another regex engine.
+    ///
+    /// The module can only be instantiated by a host that provides the import.
+    /// This value defaults to `false`.
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Return `true` if the WASM module will report the active NFA states to
+    /// the host.
+    pub fn get_trace(&self) -> bool {
+        self.trace.unwrap_or(false)
+    }
+
+    /// Configures the largest epsilon closure, in number of states, that will
+    /// be inlined into the transition functions which lead to it.
+    ///
+    /// Every state with a non-trivial epsilon closure gets its own function,
+    /// which a transition normally reaches through a dispatch on the next
+    /// state ID. When the next state of a byte range transition is known at
+    /// compile time and its closure has no more states than this threshold
+    /// (and no lookaround assertions), the states are instead inserted
+    /// directly by the transition function. The closure function is left out
+    /// if nothing else leads to the state, so depending on how many
+    /// transitions share a closure the module may end up smaller or larger.
+    ///
+    /// This value defaults to `0`, which disables inlining. No speedup from
+    /// inlining has been shown: it saves calls rather than instructions,
+    /// searches run by `wasmi` were no faster with any threshold, and the
+    /// instruction count benchmarks haven't been run with it. Only enable it
+    /// after measuring with the engine that runs the module.
+    pub fn inline_epsilon_closure_threshold(mut self, threshold: usize) -> Self {
+        self.inline_epsilon_closure_threshold = Some(threshold);
+        self
+    }
+
+    /// Return the largest epsilon closure, in number of states, that will be
+    /// inlined into the transition functions.
+    pub fn get_inline_epsilon_closure_threshold(&self) -> usize {
+        self.inline_epsilon_closure_threshold.unwrap_or(0)
+    }
+
+    /// Configures whether the output WASM module will include hints for the
+    /// branches of the search loop that are rarely taken.
+    ///
+    /// The hints are written to a `metadata.code.branch_hint` custom section,
+    /// as described by the [branch hinting proposal]. They mark the exits of
+    /// the search loop, the misses of the sparse transition tables, and the
+    /// UTF-8 boundary checks as unlikely, so that engines which honor the
+    /// hints can lay out the hot path of the loop contiguously. Other engines
+    /// ignore the section.
+    ///
+    /// This value defaults to `false`.
+    ///
+    /// [branch hinting proposal]: https://github.com/WebAssembly/branch-hinting
+    pub fn branch_hints(mut self, branch_hints: bool) -> Self {
+        self.branch_hints = Some(branch_hints);
+        self
+    }
+
+    /// Return `true` if the WASM module will include branch hints.
+    pub fn get_branch_hints(&self) -> bool {
+        self.branch_hints.unwrap_or(false)
+    }
+
     /// Overwrites the current configuration with options from another config.
     ///
     /// Options set in `other` take precedence over options in `self`.
@@ -127,6 +330,16 @@ impl Config {
             export_all_functions: other.export_all_functions.or(self.export_all_functions),
             include_names: other.include_names.or(self.include_names),
             compact_data_section: other.compact_data_section.or(self.compact_data_section),
+            step_counter: other.step_counter.or(self.step_counter),
+            max_haystack_len: other.max_haystack_len.or(self.max_haystack_len),
+            include_metadata: other.include_metadata.or(self.include_metadata),
+            include_patterns: other.include_patterns.or(self.include_patterns),
+            wasi_grep: other.wasi_grep.or(self.wasi_grep),
+            trace: other.trace.or(self.trace),
+            inline_epsilon_closure_threshold: other
+                .inline_epsilon_closure_threshold
+                .or(self.inline_epsilon_closure_threshold),
+            branch_hints: other.branch_hints.or(self.branch_hints),
         }
     }
 }
@@ -172,20 +385,32 @@ impl Builder {
         patterns: &[P],
     ) -> Result<(RegexBytecode, RegexContext), BuildError> {
         let nfa = self.thompson.build_many(patterns)?;
-        self.build_from_nfa(nfa)
+        let patterns = patterns.iter().map(|p| p.as_ref().to_owned()).collect();
+        self.build_inner(nfa, Some(patterns))
     }
 
     /// Compiles a Thompson NFA into a [`RegexBytecode`]
     /// and [`RegexContext`].
     #[cfg(feature = "compile")]
     pub fn build_from_nfa(&self, nfa: NFA) -> Result<(RegexBytecode, RegexContext), BuildError> {
+        self.build_inner(nfa, None)
+    }
+
+    #[cfg(feature = "compile")]
+    fn build_inner(
+        &self,
+        nfa: NFA,
+        patterns: Option<Vec<String>>,
+    ) -> Result<(RegexBytecode, RegexContext), BuildError> {
         nfa.look_set_any().available()?;
-        let compiled = compile_from_nfa(nfa.clone(), self.config)?;
+        let compiled = compile_from_nfa(nfa.clone(), patterns, self.config)?;
         Ok((
             compiled,
             RegexContext {
                 config: self.config,
-                nfa,
+                group_info: nfa.group_info().clone(),
+                look_set_any: nfa.look_set_any(),
+                nfa: Some(nfa),
             },
         ))
     }
@@ -222,10 +447,52 @@ pub struct RegexContext {
     pub config: Config,
     /// The non-deterministic finite automaton (NFA) used to build the regular
     /// expression.
-    pub nfa: NFA,
+    ///
+    /// This is `None` if the context was restored with
+    /// [`RegexContext::from_bytecode`], since the module doesn't contain the
+    /// NFA.
+    pub nfa: Option<NFA>,
+    group_info: GroupInfo,
+    look_set_any: LookSet,
 }
 
 impl RegexContext {
+    /// Restores the context of a compiled regular expression from the
+    /// [`Metadata`] embedded in its bytecode, without an NFA.
+    ///
+    /// Returns an error if the module was compiled without metadata, see
+    /// [`Config::include_metadata`].
+    pub fn from_bytecode(bytecode: &RegexBytecode) -> Result<Self, BuildError> {
+        RegexInfo::from_bytecode(bytecode).map(Self::from)
+    }
+
+    /// Returns the number of patterns in the regular expression.
+    pub fn pattern_len(&self) -> usize {
+        self.group_info.pattern_len()
+    }
+
+    /// Returns the capture group information for every pattern in the
+    /// regular expression.
+    pub fn group_info(&self) -> &GroupInfo {
+        &self.group_info
+    }
+
+    /// Returns the union of all look-around assertions used by the regular
+    /// expression.
+    pub fn look_set_any(&self) -> LookSet {
+        self.look_set_any
+    }
+
+    /// Returns the parts of the context that are needed to run the compiled
+    /// module, which are also available from [`RegexInfo::from_bytecode`].
+    pub fn info(&self) -> RegexInfo {
+        RegexInfo {
+            config: self.config,
+            group_info: self.group_info.clone(),
+            look_set_any: self.look_set_any,
+        }
+    }
+
     /// Returns a new default [`Config`] for configuring a [`Builder`].
     pub fn config() -> Config {
         Config::new()
@@ -237,6 +504,65 @@ impl RegexContext {
     }
 }
 
+impl From<RegexInfo> for RegexContext {
+    fn from(info: RegexInfo) -> Self {
+        Self {
+            config: info.config,
+            nfa: None,
+            group_info: info.group_info,
+            look_set_any: info.look_set_any,
+        }
+    }
+}
+
+/// The parts of a [`RegexContext`] that are needed to run a compiled regular
+/// expression, without the NFA it was compiled from.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RegexInfo {
+    /// The configuration used to build the regular expression.
+    pub config: Config,
+    group_info: GroupInfo,
+    look_set_any: LookSet,
+}
+
+impl RegexInfo {
+    /// Restores the information about a compiled regular expression from the
+    /// [`Metadata`] embedded in its bytecode.
+    ///
+    /// This allows modules to be compiled ahead of time, and then loaded
+    /// without access to the original patterns. Returns an error if the module
+    /// was compiled without metadata, see [`Config::include_metadata`].
+    pub fn from_bytecode(bytecode: &RegexBytecode) -> Result<Self, BuildError> {
+        let metadata = bytecode
+            .metadata()?
+            .ok_or_else(|| BuildError::invalid_metadata("module has no wahgex metadata section"))?;
+
+        Ok(Self {
+            config: *metadata.config(),
+            group_info: metadata.group_info()?,
+            look_set_any: metadata.look_set_any(),
+        })
+    }
+
+    /// Returns the number of patterns in the regular expression.
+    pub fn pattern_len(&self) -> usize {
+        self.group_info.pattern_len()
+    }
+
+    /// Returns the capture group information for every pattern in the
+    /// regular expression.
+    pub fn group_info(&self) -> &GroupInfo {
+        &self.group_info
+    }
+
+    /// Returns the union of all look-around assertions used by the regular
+    /// expression.
+    pub fn look_set_any(&self) -> LookSet {
+        self.look_set_any
+    }
+}
+
 /// Represents a regular expression that has been compiled into WebAssembly
 /// bytes.
 #[derive(Debug)]
@@ -273,11 +599,14 @@ impl RegexBytecode {
     /// that it is a valid WebAssembly module with the expected shape.
     ///
     /// This is the recommended way to create a `RegexBytecode` instance from a
-    /// dynamic byte slice.
+    /// dynamic byte slice. If the module contains a `wahgex` metadata section,
+    /// this will return an error if it was compiled for a different
+    /// [ABI version][abi::VERSION].
     pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, BuildError> {
         let bytes = bytes.into();
         let types = wasmparser::validate(&bytes)?;
         Self::validate_module_shape(types)?;
+        Metadata::from_module_bytes(&bytes)?;
 
         Ok(Self::from_bytes_unchecked(bytes))
     }
@@ -287,10 +616,13 @@ impl RegexBytecode {
     /// shape.
     ///
     /// This is the recommended way to create a `RegexBytecode` instance from a
-    /// static byte slice.
+    /// static byte slice. If the module contains a `wahgex` metadata section,
+    /// this will return an error if it was compiled for a different
+    /// [ABI version][abi::VERSION].
     pub fn from_static_bytes(bytes: &'static [u8]) -> Result<Self, BuildError> {
         let types = wasmparser::validate(bytes)?;
         Self::validate_module_shape(types)?;
+        Metadata::from_module_bytes(bytes)?;
 
         Ok(Self::from_static_bytes_unchecked(bytes))
     }
@@ -303,6 +635,14 @@ impl RegexBytecode {
         }
     }
 
+    /// Reads the [`Metadata`] from the `wahgex` custom section of the module.
+    ///
+    /// Returns `None` if the module was compiled without metadata, see
+    /// [`Config::include_metadata`].
+    pub fn metadata(&self) -> Result<Option<Metadata>, BuildError> {
+        Metadata::from_module_bytes(self.as_ref())
+    }
+
     fn validate_module_shape(_types: Types) -> Result<(), BuildError> {
         // TODO: Implement this so that we validate the expected shape of the
         // bytes
@@ -326,7 +666,7 @@ impl AsRef<[u8]> for RegexBytecode {
 ///     [`input.end`][Input::end].
 ///  3. The [`input.end()`][Input::end] must be less than or equal to the length
 ///     of the haystack.
-#[cfg(feature = "wasmi")]
+#[cfg(any(feature = "wasmi", feature = "web"))]
 fn common_input_validation(input: &Input<'_>) {
     assert!(
         input.haystack().len() < usize::MAX,
@@ -0,0 +1,421 @@
+//! This module contains types and functions related to the `wahgex` custom
+//! section, which describes how a WASM module was built.
+
+use regex_automata::{
+    MatchKind,
+    util::{captures::GroupInfo, look::LookSet},
+};
+#[cfg(feature = "compile")]
+use regex_automata::{PatternID, nfa::thompson::NFA};
+use wasmparser::{BinaryReader, Parser, Payload};
+
+use crate::{BuildError, Config, abi};
+
+/// The name of the custom section that contains the [`Metadata`].
+pub(crate) const METADATA_SECTION_NAME: &str = "wahgex";
+
+/// The version of the layout of the custom section, which follows the ABI
+/// version.
+///
+/// This must be incremented whenever a field is added, removed, or reordered,
+/// so that sections written by another version are rejected rather than
+/// decoded into the wrong fields.
+const FORMAT_VERSION: u8 = 2;
+
+/// Describes the regular expression a WASM module was compiled from, and the
+/// compiler that produced it.
+///
+/// This is stored in a `wahgex` custom section of the module, see
+/// [`Config::include_metadata`][crate::Config::include_metadata], and can be
+/// read back using [`RegexBytecode::metadata`][crate::RegexBytecode::metadata].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    abi_version: u32,
+    wahgex_version: String,
+    pattern_len: usize,
+    patterns: Option<Vec<String>>,
+    group_names: Vec<Vec<Option<String>>>,
+    match_kind: MatchKind,
+    is_utf8: bool,
+    look_set_any: LookSet,
+    config: Config,
+}
+
+impl Metadata {
+    /// Creates the metadata describing a module compiled from the given NFA.
+    ///
+    /// The `patterns` should be `None` if the NFA was not built from pattern
+    /// strings.
+    #[cfg(feature = "compile")]
+    pub(crate) fn new(nfa: &NFA, patterns: Option<Vec<String>>, config: Config) -> Self {
+        let group_info = nfa.group_info();
+        let group_names = (0..nfa.pattern_len())
+            .map(|pid| {
+                group_info
+                    .pattern_names(PatternID::new_unchecked(pid))
+                    .map(|name| name.map(str::to_owned))
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            abi_version: abi::VERSION,
+            wahgex_version: env!("CARGO_PKG_VERSION").to_owned(),
+            pattern_len: nfa.pattern_len(),
+            patterns,
+            group_names,
+            // The compiled module only implements leftmost-first semantics
+            match_kind: MatchKind::LeftmostFirst,
+            is_utf8: nfa.is_utf8(),
+            look_set_any: nfa.look_set_any(),
+            config,
+        }
+    }
+
+    /// Returns the ABI version of the module.
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    /// Returns the version of `wahgex` that compiled the module.
+    pub fn wahgex_version(&self) -> &str {
+        &self.wahgex_version
+    }
+
+    /// Returns the number of patterns in the module.
+    pub fn pattern_len(&self) -> usize {
+        self.pattern_len
+    }
+
+    /// Returns the pattern strings the module was compiled from.
+    ///
+    /// This is `None` unless the module was compiled with
+    /// [`Config::include_patterns`][crate::Config::include_patterns], or if
+    /// it was compiled directly from an NFA, see
+    /// [`Builder::build_from_nfa`][crate::Builder::build_from_nfa].
+    pub fn patterns(&self) -> Option<&[String]> {
+        self.patterns.as_deref()
+    }
+
+    /// Returns the capture group names of each pattern, indexed by pattern and
+    /// then by group.
+    ///
+    /// Unnamed groups (including the implicit group 0) are `None`.
+    pub fn group_names(&self) -> &[Vec<Option<String>>] {
+        &self.group_names
+    }
+
+    /// Returns the match semantics that the module implements.
+    pub fn match_kind(&self) -> MatchKind {
+        self.match_kind
+    }
+
+    /// Returns `true` if the module only reports matches that split the
+    /// haystack on UTF-8 boundaries.
+    pub fn is_utf8(&self) -> bool {
+        self.is_utf8
+    }
+
+    /// Returns the union of all look-around assertions used by the patterns.
+    pub fn look_set_any(&self) -> LookSet {
+        self.look_set_any
+    }
+
+    /// Returns the [`Config`] the module was compiled with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Builds the [`GroupInfo`] describing the capture groups of every pattern.
+    pub fn group_info(&self) -> Result<GroupInfo, BuildError> {
+        GroupInfo::new(
+            self.group_names
+                .iter()
+                .map(|names| names.iter().map(Option::as_deref)),
+        )
+        .map_err(|err| BuildError::invalid_metadata(err.to_string()))
+    }
+
+    /// Encodes the metadata into the contents of the custom section.
+    #[cfg(feature = "compile")]
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        use wasm_encoder::Encode;
+
+        fn encode_len(len: usize, sink: &mut Vec<u8>) {
+            u32::try_from(len).unwrap().encode(sink);
+        }
+
+        fn encode_bool(value: bool, sink: &mut Vec<u8>) {
+            sink.push(u8::from(value));
+        }
+
+        let mut sink = Vec::new();
+        self.abi_version.encode(&mut sink);
+        sink.push(FORMAT_VERSION);
+        self.wahgex_version.encode(&mut sink);
+        encode_len(self.pattern_len, &mut sink);
+
+        encode_bool(self.patterns.is_some(), &mut sink);
+        if let Some(patterns) = &self.patterns {
+            encode_len(patterns.len(), &mut sink);
+            for pattern in patterns {
+                pattern.encode(&mut sink);
+            }
+        }
+
+        encode_len(self.group_names.len(), &mut sink);
+        for names in &self.group_names {
+            encode_len(names.len(), &mut sink);
+            for name in names {
+                encode_bool(name.is_some(), &mut sink);
+                if let Some(name) = name {
+                    name.encode(&mut sink);
+                }
+            }
+        }
+
+        sink.push(match self.match_kind {
+            MatchKind::All => 0,
+            MatchKind::LeftmostFirst => 1,
+            _ => unreachable!("match kind is always one we created"),
+        });
+        encode_bool(self.is_utf8, &mut sink);
+        self.look_set_any.bits.encode(&mut sink);
+
+        // Options which were not set are encoded as such, so that the decoded
+        // config can still be overwritten by another
+        let config = &self.config;
+        for option in [
+            config.include_names,
+            config.compact_data_section,
+            config.step_counter,
+            config.include_metadata,
+            config.include_patterns,
+            config.wasi_grep,
+            config.trace,
+            config.branch_hints,
+        ] {
+            sink.push(match option {
+                None => 0,
+                Some(false) => 1,
+                Some(true) => 2,
+            });
+        }
+        encode_bool(config.max_haystack_len.is_some(), &mut sink);
+        if let Some(max_haystack_len) = config.max_haystack_len {
+            u64::try_from(max_haystack_len).unwrap().encode(&mut sink);
+        }
+        encode_bool(config.inline_epsilon_closure_threshold.is_some(), &mut sink);
+        if let Some(threshold) = config.inline_epsilon_closure_threshold {
+            u64::try_from(threshold).unwrap().encode(&mut sink);
+        }
+
+        sink
+    }
+
+    /// Decodes the metadata from the contents of the custom section.
+    ///
+    /// Returns an error if the ABI version of the module does not match
+    /// [`abi::VERSION`], without attempting to read the remainder of the
+    /// section. Sections with a different layout version, or with bytes left
+    /// over after the last field, are rejected as invalid.
+    fn decode(data: &[u8], offset: usize) -> Result<Self, BuildError> {
+        fn read_len(reader: &mut BinaryReader<'_>) -> Result<usize, BuildError> {
+            Ok(usize::try_from(reader.read_var_u32()?).unwrap())
+        }
+
+        let mut reader = BinaryReader::new(data, offset);
+
+        let abi_version = reader.read_var_u32()?;
+        if abi_version != abi::VERSION {
+            return Err(BuildError::incompatible_abi(abi_version));
+        }
+        let format_version = reader.read_u8()?;
+        if format_version != FORMAT_VERSION {
+            return Err(BuildError::invalid_metadata(format!(
+                "unsupported format version {format_version}"
+            )));
+        }
+
+        let wahgex_version = reader.read_string()?.to_owned();
+        let pattern_len = read_len(&mut reader)?;
+
+        let patterns = if reader.read_u8()? != 0 {
+            let len = read_len(&mut reader)?;
+            let patterns = (0..len)
+                .map(|_| reader.read_string().map(str::to_owned))
+                .collect::<Result<_, _>>()?;
+            Some(patterns)
+        } else {
+            None
+        };
+
+        let group_names_len = read_len(&mut reader)?;
+        let mut group_names = Vec::new();
+        for _ in 0..group_names_len {
+            let len = read_len(&mut reader)?;
+            let mut names = Vec::new();
+            for _ in 0..len {
+                let name = if reader.read_u8()? != 0 {
+                    Some(reader.read_string()?.to_owned())
+                } else {
+                    None
+                };
+                names.push(name);
+            }
+            group_names.push(names);
+        }
+
+        let match_kind = match reader.read_u8()? {
+            0 => MatchKind::All,
+            1 => MatchKind::LeftmostFirst,
+            other => {
+                return Err(BuildError::invalid_metadata(format!(
+                    "unknown match kind {other}"
+                )));
+            },
+        };
+        let is_utf8 = reader.read_u8()? != 0;
+        let look_set_any = LookSet {
+            bits: reader.read_var_u32()?,
+        };
+
+        let mut read_option_bool = || -> Result<Option<bool>, BuildError> {
+            match reader.read_u8()? {
+                0 => Ok(None),
+                1 => Ok(Some(false)),
+                2 => Ok(Some(true)),
+                other => Err(BuildError::invalid_metadata(format!(
+                    "unknown config option value {other}"
+                ))),
+            }
+        };
+        let mut config = Config::new();
+        config.include_names = read_option_bool()?;
+        config.compact_data_section = read_option_bool()?;
+        config.step_counter = read_option_bool()?;
+        config.include_metadata = read_option_bool()?;
+        config.include_patterns = read_option_bool()?;
+        config.wasi_grep = read_option_bool()?;
+        config.trace = read_option_bool()?;
+        config.branch_hints = read_option_bool()?;
+        if reader.read_u8()? != 0 {
+            let max_haystack_len = reader.read_var_u64()?;
+            config.max_haystack_len = Some(usize::try_from(max_haystack_len).map_err(|_| {
+                BuildError::invalid_metadata("maximum haystack length is too large")
+            })?);
+        }
+        if reader.read_u8()? != 0 {
+            let threshold = reader.read_var_u64()?;
+            config.inline_epsilon_closure_threshold =
+                Some(usize::try_from(threshold).map_err(|_| {
+                    BuildError::invalid_metadata("epsilon closure inline threshold is too large")
+                })?);
+        }
+        if !reader.eof() {
+            return Err(BuildError::invalid_metadata(format!(
+                "{} unexpected trailing bytes",
+                reader.bytes_remaining()
+            )));
+        }
+
+        Ok(Self {
+            abi_version,
+            wahgex_version,
+            pattern_len,
+            patterns,
+            group_names,
+            match_kind,
+            is_utf8,
+            look_set_any,
+            config,
+        })
+    }
+
+    /// Searches the given WASM module for a `wahgex` custom section and
+    /// decodes it.
+    ///
+    /// Returns `None` if the module does not have the section.
+    pub(crate) fn from_module_bytes(bytes: &[u8]) -> Result<Option<Self>, BuildError> {
+        for payload in Parser::new(0).parse_all(bytes) {
+            if let Payload::CustomSection(reader) = payload? {
+                if reader.name() == METADATA_SECTION_NAME {
+                    return Self::decode(reader.data(), reader.data_offset()).map(Some);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, feature = "compile"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let patterns = [r"(?<year>\d{4})-(?<month>\d{2})", "a(b)c"];
+        let nfa = NFA::new_many(&patterns).unwrap();
+        let config = Config::new()
+            .step_counter(true)
+            .max_haystack_len(1024)
+            .inline_epsilon_closure_threshold(4)
+            .branch_hints(true);
+        let metadata = Metadata::new(
+            &nfa,
+            Some(patterns.iter().map(|p| p.to_string()).collect()),
+            config,
+        );
+
+        let bytes = metadata.encode();
+        let decoded = Metadata::decode(&bytes, 0).unwrap();
+        assert_eq!(decoded, metadata);
+
+        assert_eq!(decoded.abi_version(), abi::VERSION);
+        assert_eq!(decoded.pattern_len(), 2);
+        assert_eq!(decoded.patterns().unwrap(), &patterns);
+        assert_eq!(
+            decoded.group_names(),
+            &[
+                vec![None, Some("year".into()), Some("month".into())],
+                vec![None, None],
+            ]
+        );
+        assert_eq!(decoded.match_kind(), MatchKind::LeftmostFirst);
+        assert!(decoded.is_utf8());
+        assert_eq!(decoded.config(), &config);
+        assert_eq!(decoded.group_info().unwrap().pattern_len(), 2);
+    }
+
+    #[test]
+    fn incompatible_abi() {
+        let nfa = NFA::new("a").unwrap();
+        let mut metadata = Metadata::new(&nfa, None, Config::new());
+        metadata.abi_version = abi::VERSION + 1;
+
+        let err = Metadata::decode(&metadata.encode(), 0).unwrap_err();
+        assert!(err.is_incompatible_abi());
+    }
+
+    #[test]
+    fn unsupported_format_version() {
+        let nfa = NFA::new("a").unwrap();
+        let mut bytes = Metadata::new(&nfa, None, Config::new()).encode();
+        // The ABI version is a single byte, and the format version follows it
+        bytes[1] = FORMAT_VERSION + 1;
+
+        let err = Metadata::decode(&bytes, 0).unwrap_err();
+        assert!(err.to_string().contains("unsupported format version"));
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let nfa = NFA::new("a").unwrap();
+        let mut bytes = Metadata::new(&nfa, None, Config::new()).encode();
+        bytes.push(0);
+
+        let err = Metadata::decode(&bytes, 0).unwrap_err();
+        assert!(err.to_string().contains("trailing bytes"));
+    }
+}
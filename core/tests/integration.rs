@@ -4,7 +4,7 @@ use regex_test::{
     CompiledRegex, RegexTest, TestResult, TestRunner,
     anyhow::{self, Context},
 };
-use wahgex::{Builder, RegexBytecode, RegexContext, engines::wasmi::Regex};
+use wahgex::{Builder, Config, RegexBytecode, RegexContext, engines::wasmi::Regex};
 
 mod common;
 
@@ -27,6 +27,48 @@ fn default() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Tests the configuration which inlines small epsilon closures into the
+/// transition functions.
+#[test]
+fn inline_epsilon_closures() -> anyhow::Result<()> {
+    const TEST_DENYLIST: &[&str] = &[];
+
+    let mut builder = RegexContext::builder();
+    builder.configure(Config::new().inline_epsilon_closure_threshold(8));
+    let mut runner = TestRunner::new()?;
+    runner.expand(&["is_match"], |test| test.compiles());
+    runner
+        .test_iter(
+            common::suite()?
+                .iter()
+                .filter(|test| !TEST_DENYLIST.contains(&test.name())),
+            compiler(builder),
+        )
+        .assert();
+    Ok(())
+}
+
+/// Tests the configuration which emits branch hints, which must not change the
+/// results of the search.
+#[test]
+fn branch_hints() -> anyhow::Result<()> {
+    const TEST_DENYLIST: &[&str] = &[];
+
+    let mut builder = RegexContext::builder();
+    builder.configure(Config::new().branch_hints(true));
+    let mut runner = TestRunner::new()?;
+    runner.expand(&["is_match"], |test| test.compiles());
+    runner
+        .test_iter(
+            common::suite()?
+                .iter()
+                .filter(|test| !TEST_DENYLIST.contains(&test.name())),
+            compiler(builder),
+        )
+        .assert();
+    Ok(())
+}
+
 /// Configure a regex_automata::Input with the given test configuration.
 fn create_input(test: &regex_test::RegexTest) -> regex_automata::Input<'_> {
     use regex_automata::Anchored;
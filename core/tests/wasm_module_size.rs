@@ -110,7 +110,7 @@ fn compile(
     }
 
     let (with_names, _) = match builder
-        .configure(builder.get_config().clone().include_names(true))
+        .configure(builder.get_config().include_names(true))
         .build_many(regexes)
     {
         Ok(re) => re,
@@ -120,7 +120,7 @@ fn compile(
     };
 
     let (without_names, _) = match builder
-        .configure(builder.get_config().clone().include_names(false))
+        .configure(builder.get_config().include_names(false))
         .build_many(regexes)
     {
         Ok(re) => re,
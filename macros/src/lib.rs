@@ -0,0 +1,164 @@
+//! `wahgex-macros` provides the [`regex!`] macro, which compiles regular
+//! expressions into WebAssembly modules while building the crate that uses
+//! them.
+//!
+//! The expansion refers to the `wahgex` crate, so it must also be a dependency
+//! of the crate using the macro.
+//!
+//! The macro is only available from this crate, and isn't re-exported by
+//! `wahgex`: it uses `wahgex` to compile the patterns, so `wahgex` can't depend
+//! on it in turn.
+
+#![deny(missing_docs, missing_debug_implementations)]
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    Ident, LitBool, LitByteStr, LitStr, Token,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+};
+use wahgex::{Builder, Config};
+
+/// Compiles one or more regular expression patterns at build time, and expands
+/// to a `wahgex::RegexBytecode` containing the compiled module.
+///
+/// The patterns are given as string literals, and may be followed by options
+/// for the [`wahgex::Config`]:
+///
+/// ```
+/// use wahgex::RegexBytecode;
+/// use wahgex_macros::regex;
+///
+/// static DATE: RegexBytecode = regex!(r"\d{4}-\d{2}-\d{2}");
+/// static KEYWORDS: RegexBytecode = regex!("fn", "let", include_names = true);
+/// ```
+///
+/// The supported options are `include_names`, `compact_data_section`,
+/// `step_counter`, `include_metadata`, `include_patterns` (all taking a
+/// `bool`), and `max_haystack_len` (taking an integer).
+///
+/// A pattern with invalid syntax is reported as a compile error pointing at
+/// the pattern literal:
+///
+/// ```compile_fail
+/// static UNCLOSED: wahgex::RegexBytecode = wahgex_macros::regex!("(abc");
+/// ```
+#[proc_macro]
+pub fn regex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as RegexInput);
+
+    match input.expand() {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The parsed arguments of the [`regex!`] macro.
+struct RegexInput {
+    patterns: Vec<LitStr>,
+    config: Config,
+}
+
+impl RegexInput {
+    fn expand(&self) -> syn::Result<proc_macro2::TokenStream> {
+        let mut builder = Builder::new();
+        builder.configure(self.config);
+
+        let patterns = self.patterns.iter().map(LitStr::value).collect::<Vec<_>>();
+        let (bytecode, _) = builder.build_many(&patterns).map_err(|err| {
+            // Compile each pattern on its own, so that a syntax error can be
+            // attributed to the literal that caused it
+            self.patterns
+                .iter()
+                .find_map(|pattern| {
+                    let err = builder.build(&pattern.value()).err()?;
+                    Some(syn::Error::new(pattern.span(), err))
+                })
+                .unwrap_or_else(|| syn::Error::new(Span::call_site(), err))
+        })?;
+        let bytes = LitByteStr::new(bytecode.as_ref(), Span::call_site());
+
+        Ok(quote! {
+            ::wahgex::RegexBytecode::from_static_bytes_unchecked(#bytes)
+        })
+    }
+}
+
+impl Parse for RegexInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut patterns = Vec::new();
+        while input.peek(LitStr) {
+            patterns.push(input.parse()?);
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        if patterns.is_empty() {
+            return Err(input.error("expected at least one pattern string literal"));
+        }
+
+        let mut config = Config::new();
+        let options = Punctuated::<ConfigOption, Token![,]>::parse_terminated(input)?;
+        for option in options {
+            config = option.apply(config)?;
+        }
+
+        Ok(Self { patterns, config })
+    }
+}
+
+/// A single `name = value` option of the [`regex!`] macro.
+struct ConfigOption {
+    name: Ident,
+    value: syn::Lit,
+}
+
+impl ConfigOption {
+    fn apply(&self, config: Config) -> syn::Result<Config> {
+        let config = match self.name.to_string().as_str() {
+            "include_names" => config.include_names(self.bool_value()?),
+            "compact_data_section" => config.compact_data_section(self.bool_value()?),
+            "step_counter" => config.step_counter(self.bool_value()?),
+            "include_metadata" => config.include_metadata(self.bool_value()?),
+            "include_patterns" => config.include_patterns(self.bool_value()?),
+            "max_haystack_len" => config.max_haystack_len(self.int_value()?),
+            name => {
+                return Err(syn::Error::new(
+                    self.name.span(),
+                    format!("unknown option `{name}`"),
+                ));
+            },
+        };
+
+        Ok(config)
+    }
+
+    fn bool_value(&self) -> syn::Result<bool> {
+        match &self.value {
+            syn::Lit::Bool(LitBool { value, .. }) => Ok(*value),
+            other => Err(syn::Error::new(other.span(), "expected a boolean literal")),
+        }
+    }
+
+    fn int_value(&self) -> syn::Result<usize> {
+        match &self.value {
+            syn::Lit::Int(int) => int.base10_parse(),
+            other => Err(syn::Error::new(other.span(), "expected an integer literal")),
+        }
+    }
+}
+
+impl Parse for ConfigOption {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+
+        Ok(Self { name, value })
+    }
+}
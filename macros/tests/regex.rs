@@ -0,0 +1,33 @@
+use wahgex::{RegexBytecode, RegexContext, engines::wasmi::Regex};
+use wahgex_macros::regex;
+
+static DATE: RegexBytecode = regex!(r"\d{4}-\d{2}-\d{2}");
+
+#[test]
+fn static_regex() {
+    let mut regex = Regex::new(&DATE).unwrap();
+
+    assert!(regex.is_match("released on 2024-03-01".into()));
+    assert!(!regex.is_match("released on 2024-3-1".into()));
+}
+
+#[test]
+fn many_patterns_with_options() {
+    let bytecode = regex!("fn", "let", include_names = true, max_haystack_len = 1024);
+    let mut regex = Regex::new(&bytecode).unwrap();
+
+    assert!(regex.is_match("let x = 1;".into()));
+    assert!(!regex.is_match("const X: u8 = 1;".into()));
+
+    let context = RegexContext::from_bytecode(&bytecode).unwrap();
+    assert_eq!(context.pattern_len(), 2);
+    assert!(context.config.get_include_names());
+    assert_eq!(context.config.get_max_haystack_len(), Some(1024));
+}
+
+#[test]
+fn matches_builder_output() {
+    let (expected, _) = wahgex::Builder::new().build("a+b").unwrap();
+
+    assert_eq!(regex!("a+b").as_ref(), expected.as_ref());
+}
@@ -17,7 +17,12 @@ cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-feat
 cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features default
 cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features wasmi
 cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features compile,wasmi
+cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features component,wasmi
+cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features web
+cargo "${TOOLCHAIN_ARG}" build --manifest-path core/Cargo.toml --no-default-features --features compile,web
+cargo "${TOOLCHAIN_ARG}" build --manifest-path build/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" build --manifest-path cli/Cargo.toml
+cargo "${TOOLCHAIN_ARG}" build --manifest-path macros/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" build --manifest-path web/playground/Cargo.toml
 
 # --all-targets does not include the doctests
@@ -28,9 +33,19 @@ cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-featu
 cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features default
 cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features wasmi
 cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features compile,wasmi
+cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features component,wasmi
+cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features web
+cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --no-default-features --features compile,web
+cargo "${TOOLCHAIN_ARG}" test --manifest-path build/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" test --manifest-path cli/Cargo.toml
+cargo "${TOOLCHAIN_ARG}" test --manifest-path macros/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" test --manifest-path web/playground/Cargo.toml
 
+# The web engine only runs inside of a JS host, run its tests with
+# `wasm-bindgen-test-runner` under node (24+ for multi-memory and memory64)
+CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner \
+    cargo "${TOOLCHAIN_ARG}" test --manifest-path core/Cargo.toml --target wasm32-unknown-unknown --no-default-features --features compile,web --lib
+
 cargo "${TOOLCHAIN_ARG}" test --doc
 cargo "${TOOLCHAIN_ARG}" test --benches --release
 
@@ -41,7 +56,13 @@ cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-fea
 cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features default
 cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features wasmi
 cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features compile,wasmi
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features component,wasmi
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features web
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --no-default-features --features compile,web
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path core/Cargo.toml --target wasm32-unknown-unknown --no-default-features --features compile,web --lib --tests
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path build/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" clippy --manifest-path cli/Cargo.toml
+cargo "${TOOLCHAIN_ARG}" clippy --manifest-path macros/Cargo.toml
 cargo "${TOOLCHAIN_ARG}" clippy --manifest-path web/playground/Cargo.toml
 
 cargo "${TOOLCHAIN_ARG}" doc    --no-deps --document-private-items
@@ -5,11 +5,14 @@ set -o nounset # make script exit when using undeclared variables
 set -o pipefail # make script exit when command fails in a pipe
 set -o xtrace # print a trace of all commands executed by script
 
-version=$(cargo metadata --format-version=1 |\
-    jq '.packages[] | select(.name == "gungraun").version' |\
-    tr -d '"'
-)
+package_version() {
+    cargo metadata --format-version=1 |\
+        jq ".packages[] | select(.name == \"$1\").version" |\
+        tr -d '"'
+}
 
-cargo install gungraun-runner --version $version
+cargo install gungraun-runner --version "$(package_version gungraun)"
+# The test runner must match the `wasm-bindgen` version exactly
+cargo install wasm-bindgen-cli --version "$(package_version wasm-bindgen)"
 
 sudo apt install -y valgrind
\ No newline at end of file
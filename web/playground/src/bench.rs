@@ -0,0 +1,145 @@
+use js_sys::{Array, Function, JsString, Reflect, RegExp};
+use wasm_bindgen::{JsCast, prelude::*};
+
+use crate::CompileOptions;
+
+/// The time taken by a single engine to search the haystack repeatedly.
+#[wasm_bindgen]
+pub struct BenchResult {
+    engine: String,
+    iterations: u32,
+    total_ms: f64,
+    is_match: Option<bool>,
+    error: Option<String>,
+}
+
+impl BenchResult {
+    /// Runs the search the given number of times, stopping at the first
+    /// error.
+    pub(crate) fn run(
+        engine: &str,
+        iterations: u32,
+        mut search: impl FnMut() -> Result<bool, String>,
+    ) -> Self {
+        let mut is_match = None;
+        let mut error = None;
+        let start = now();
+        for _ in 0..iterations {
+            match search() {
+                Ok(result) => is_match = Some(result),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                },
+            }
+        }
+        let total_ms = now() - start;
+
+        Self {
+            engine: engine.into(),
+            iterations,
+            total_ms,
+            is_match,
+            error,
+        }
+    }
+
+    /// Records an engine which could not be set up.
+    pub(crate) fn failed(engine: &str, error: String) -> Self {
+        Self {
+            engine: engine.into(),
+            iterations: 0,
+            total_ms: 0.0,
+            is_match: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl BenchResult {
+    /// The name of the engine.
+    #[wasm_bindgen(getter)]
+    pub fn engine(&self) -> String {
+        self.engine.clone()
+    }
+
+    /// The number of searches that were run.
+    #[wasm_bindgen(getter)]
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// The time taken by all the searches, in milliseconds.
+    #[wasm_bindgen(getter)]
+    pub fn total_ms(&self) -> f64 {
+        self.total_ms
+    }
+
+    /// The mean time taken by a single search, in milliseconds.
+    #[wasm_bindgen(getter)]
+    pub fn mean_ms(&self) -> f64 {
+        if self.iterations == 0 {
+            0.0
+        } else {
+            self.total_ms / f64::from(self.iterations)
+        }
+    }
+
+    /// The result of the searches, or `undefined` if none completed.
+    #[wasm_bindgen(getter)]
+    pub fn is_match(&self) -> Option<bool> {
+        self.is_match
+    }
+
+    /// The error that stopped the searches, if any.
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
+
+/// Creates a JavaScript `RegExp` which matches any of the patterns, with the
+/// flags that correspond to the options.
+///
+/// The patterns are passed through as is, so they must also be valid
+/// JavaScript syntax.
+pub(crate) fn js_regexp(patterns: &[String], options: &CompileOptions) -> Result<RegExp, String> {
+    let source = patterns
+        .iter()
+        .map(|pattern| format!("(?:{pattern})"))
+        .collect::<Vec<_>>()
+        .join("|");
+    let flags = [
+        (options.case_insensitive, 'i'),
+        (options.multi_line, 'm'),
+        (options.dot_matches_new_line, 's'),
+        (options.unicode, 'u'),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, flag)| enabled.then_some(flag))
+    .collect::<String>();
+
+    // The `RegExp` constructor throws on invalid syntax, which the bindings
+    // in `js_sys` don't catch
+    let constructor = Reflect::get(&js_sys::global(), &JsValue::from_str("RegExp"))
+        .and_then(JsCast::dyn_into::<Function>)
+        .map_err(|err| format!("{err:?}"))?;
+    let regexp = Reflect::construct(
+        &constructor,
+        &Array::of2(&JsString::from(source), &JsString::from(flags)),
+    )
+    .map_err(|err| {
+        err.dyn_into::<js_sys::Error>()
+            .map_or_else(|err| format!("{err:?}"), |err| String::from(err.message()))
+    })?;
+    Ok(regexp.unchecked_into())
+}
+
+/// Returns the current time in milliseconds, preferring the high resolution
+/// `performance.now()` when it is available.
+pub(crate) fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
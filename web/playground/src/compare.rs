@@ -0,0 +1,182 @@
+use regex_automata::{
+    Input, MatchKind, PatternSet,
+    nfa::thompson::{NFA, pikevm::PikeVM},
+};
+use wasm_bindgen::prelude::*;
+
+/// Runs the PikeVM of `regex-automata` on the same NFA that the module was
+/// compiled from, to check the results of the module.
+pub(crate) struct Reference {
+    /// Finds the leftmost-first matches, which are used for highlighting.
+    leftmost_first: PikeVM,
+    /// Finds every pattern that matches, since a leftmost-first search stops
+    /// at the first pattern.
+    all: PikeVM,
+}
+
+impl Reference {
+    pub(crate) fn new(nfa: &NFA) -> Result<Self, String> {
+        let build = |match_kind| {
+            PikeVM::builder()
+                .configure(PikeVM::config().match_kind(match_kind))
+                .build_from_nfa(nfa.clone())
+                .map_err(|err| err.to_string())
+        };
+        Ok(Self {
+            leftmost_first: build(MatchKind::LeftmostFirst)?,
+            all: build(MatchKind::All)?,
+        })
+    }
+
+    /// Returns the IDs of the patterns that match anywhere in the haystack.
+    fn matching_patterns(&self, haystack: &str) -> Vec<u32> {
+        let mut patterns = PatternSet::new(self.all.pattern_len());
+        self.all.which_overlapping_matches(
+            &mut self.all.create_cache(),
+            &Input::new(haystack),
+            &mut patterns,
+        );
+        patterns.iter().map(|pid| pid.as_u32()).collect()
+    }
+
+    /// Returns the successive leftmost-first matches in the haystack, with
+    /// offsets into the `whole` string that the haystack starts at `start` of.
+    fn matches(&self, whole: &str, start: usize, haystack: &str) -> Vec<MatchSpan> {
+        self.leftmost_first
+            .find_iter(&mut self.leftmost_first.create_cache(), haystack)
+            .map(|m| MatchSpan {
+                pattern: m.pattern().as_u32(),
+                start: utf16_offset(whole, start + m.start()),
+                end: utf16_offset(whole, start + m.end()),
+            })
+            .collect()
+    }
+}
+
+/// A match found by `regex-automata`, in UTF-16 code units like all
+/// JavaScript string indices.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct MatchSpan {
+    pub pattern: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The results of searching a haystack, or a single line of it, with both the
+/// module and `regex-automata`.
+#[wasm_bindgen]
+pub struct SearchResult {
+    start: usize,
+    end: usize,
+    pattern_ids: Vec<u32>,
+    expected_pattern_ids: Vec<u32>,
+    matches: Vec<MatchSpan>,
+}
+
+impl SearchResult {
+    /// Checks the results of the module for the part of the `whole` string
+    /// that starts at the byte offset `start`.
+    pub(crate) fn new(
+        reference: &Reference,
+        whole: &str,
+        start: usize,
+        haystack: &str,
+        pattern_ids: Vec<u32>,
+    ) -> Self {
+        Self {
+            start: utf16_offset(whole, start),
+            end: utf16_offset(whole, start + haystack.len()),
+            pattern_ids,
+            expected_pattern_ids: reference.matching_patterns(haystack),
+            matches: reference.matches(whole, start, haystack),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SearchResult {
+    /// The start of the searched text, in UTF-16 code units.
+    #[wasm_bindgen(getter)]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The end of the searched text, in UTF-16 code units.
+    #[wasm_bindgen(getter)]
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The IDs of the patterns that the module found to match.
+    #[wasm_bindgen(getter)]
+    pub fn pattern_ids(&self) -> Vec<u32> {
+        self.pattern_ids.clone()
+    }
+
+    /// The IDs of the patterns that `regex-automata` found to match.
+    #[wasm_bindgen(getter)]
+    pub fn expected_pattern_ids(&self) -> Vec<u32> {
+        self.expected_pattern_ids.clone()
+    }
+
+    /// The leftmost-first matches found by `regex-automata`, since the module
+    /// can't report where it matched.
+    #[wasm_bindgen(getter)]
+    pub fn matches(&self) -> Vec<MatchSpan> {
+        self.matches.clone()
+    }
+
+    /// Returns `true` if the module and `regex-automata` found the same
+    /// patterns to match.
+    pub fn agrees(&self) -> bool {
+        self.pattern_ids == self.expected_pattern_ids
+    }
+}
+
+/// Splits the haystack into lines, without their terminators, along with the
+/// byte offset that each line starts at.
+pub(crate) fn lines(haystack: &str) -> impl Iterator<Item = (usize, &str)> {
+    haystack.split_inclusive('\n').scan(0, |start, line| {
+        let line_start = *start;
+        *start += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        Some((line_start, line.strip_suffix('\r').unwrap_or(line)))
+    })
+}
+
+/// Converts a byte offset into the string to UTF-16 code units, rounding up
+/// offsets that fall inside of a character.
+fn utf16_offset(haystack: &str, offset: usize) -> usize {
+    haystack
+        .char_indices()
+        .take_while(|(index, _)| *index < offset)
+        .map(|(_, c)| c.len_utf16())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lines() {
+        assert_eq!(
+            lines("a\r\nbc\n\nd").collect::<Vec<_>>(),
+            [(0, "a"), (3, "bc"), (6, ""), (7, "d")]
+        );
+        assert_eq!(lines("").count(), 0);
+    }
+
+    #[test]
+    fn utf16_offsets() {
+        let haystack = "aé😀b";
+        assert_eq!(utf16_offset(haystack, 0), 0);
+        assert_eq!(utf16_offset(haystack, 1), 1);
+        assert_eq!(utf16_offset(haystack, 3), 2);
+        assert_eq!(utf16_offset(haystack, 7), 4);
+        assert_eq!(utf16_offset(haystack, haystack.len()), 5);
+        // Offsets inside of a character are rounded up
+        assert_eq!(utf16_offset(haystack, 2), 2);
+    }
+}
@@ -0,0 +1,79 @@
+use wahgex::{LookMatcher, LookSet, engines::TraceStep, inspect::Inspection};
+use wasm_bindgen::prelude::*;
+
+/// The state of the NFA simulation at a single offset of the haystack.
+#[wasm_bindgen]
+pub struct DebugStep {
+    at_offset: usize,
+    byte: Option<u8>,
+    states: Vec<u32>,
+    looks_passed: Vec<String>,
+    looks_failed: Vec<String>,
+    nfa_dot: String,
+}
+
+impl DebugStep {
+    /// Describes the traced step, evaluating every assertion used by the NFA
+    /// at the offset of the step.
+    pub(crate) fn new(
+        step: &TraceStep,
+        haystack: &[u8],
+        inspection: &Inspection,
+        look_matcher: &LookMatcher,
+        look_set_any: LookSet,
+    ) -> Self {
+        let (looks_passed, looks_failed) = look_set_any
+            .iter()
+            .partition::<Vec<_>, _>(|look| look_matcher.matches(*look, haystack, step.at_offset));
+        let names = |looks: Vec<_>| looks.into_iter().map(|look| format!("{look:?}")).collect();
+
+        Self {
+            at_offset: step.at_offset,
+            byte: haystack.get(step.at_offset).copied(),
+            states: step.states.iter().map(|sid| sid.as_u32()).collect(),
+            looks_passed: names(looks_passed),
+            looks_failed: names(looks_failed),
+            nfa_dot: inspection.to_dot_with_active(&step.states),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DebugStep {
+    /// The offset of the step in the haystack, in bytes.
+    #[wasm_bindgen(getter)]
+    pub fn at_offset(&self) -> usize {
+        self.at_offset
+    }
+
+    /// The byte at the offset, or `undefined` at the end of the haystack.
+    #[wasm_bindgen(getter)]
+    pub fn byte(&self) -> Option<u8> {
+        self.byte
+    }
+
+    /// The IDs of the NFA states that are active at the offset, in the order
+    /// they were added.
+    #[wasm_bindgen(getter)]
+    pub fn states(&self) -> Vec<u32> {
+        self.states.clone()
+    }
+
+    /// The assertions of the NFA that hold at the offset.
+    #[wasm_bindgen(getter)]
+    pub fn looks_passed(&self) -> Vec<String> {
+        self.looks_passed.clone()
+    }
+
+    /// The assertions of the NFA that don't hold at the offset.
+    #[wasm_bindgen(getter)]
+    pub fn looks_failed(&self) -> Vec<String> {
+        self.looks_failed.clone()
+    }
+
+    /// The NFA as a Graphviz DOT graph, with the active states highlighted.
+    #[wasm_bindgen(getter)]
+    pub fn nfa_dot(&self) -> String {
+        self.nfa_dot.clone()
+    }
+}
@@ -1,11 +1,50 @@
-use wahgex::{Builder, Config};
+use wahgex::{
+    Config, Input, LookMatcher, LookSet, RegexBytecode,
+    engines::web::Regex,
+    inspect::{self, Inspection},
+};
 use wasm_bindgen::prelude::*;
 use wasmprinter::print_bytes;
 
+use crate::compare::Reference;
+pub use crate::{
+    bench::BenchResult,
+    compare::{MatchSpan, SearchResult},
+    debug::DebugStep,
+    options::CompileOptions,
+};
+
+mod bench;
+mod compare;
+mod debug;
+mod options;
+
 #[wasm_bindgen]
 pub struct CompileResult {
+    patterns: Vec<String>,
+    options: CompileOptions,
+    bytecode: RegexBytecode,
+    /// The module that is searched, only present if it differs from
+    /// `bytecode` because the grep command was left out.
+    search_bytecode: Option<RegexBytecode>,
+    /// The modules compiled from each pattern on its own, only present if
+    /// there are multiple patterns.
+    pattern_bytecodes: Vec<RegexBytecode>,
+    /// The instantiated modules, created on the first search.
+    regexes: Option<(Regex, Vec<Regex>)>,
+    /// The module compiled with tracing enabled, created on the first debug
+    /// run.
+    tracer: Option<Regex>,
+    inspection: Inspection,
+    look_matcher: LookMatcher,
+    look_set_any: LookSet,
+    /// The PikeVM of `regex-automata`, run on the same NFA to check the
+    /// results of the module.
+    reference: Reference,
     wasm_bytes: Box<[u8]>,
     module_size: usize,
+    compile_ms: f64,
+    table_size: usize,
     states: usize,
     pattern_len: usize,
     has_capture: bool,
@@ -15,27 +54,68 @@ pub struct CompileResult {
     lookset_any: String,
     lookset_prefix_any: String,
     wat_string: String,
+    effective_config: String,
+    nfa_dot: String,
+    size_report: String,
 }
 
+/// Compiles the patterns, one per line, into a single module.
+///
+/// Blank lines are skipped, so pattern IDs count only the non-blank lines.
 #[wasm_bindgen]
-pub fn compile(regex: String) -> Result<CompileResult, String> {
-    let (bytecode, context) = Builder::new()
-        .configure(
-            Config::new()
-                .include_names(true)
-                .compact_data_section(false),
-        )
-        .build(&regex)
+pub fn compile(patterns: String, options: &CompileOptions) -> Result<CompileResult, String> {
+    let patterns = patterns
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let builder = options.builder();
+
+    let start = bench::now();
+    let (bytecode, context) = builder
+        .build_many(&patterns)
         .map_err(|err| err.to_string())?;
+    let compile_ms = bench::now() - start;
+    let search_builder = options.search_builder();
+    let search_bytecode = if options.wasi_grep {
+        let (bytecode, _) = search_builder
+            .build_many(&patterns)
+            .map_err(|err| err.to_string())?;
+        Some(bytecode)
+    } else {
+        None
+    };
+    // The module only reports whether any pattern matched, so each pattern is
+    // also compiled on its own to find which ones did
+    let pattern_bytecodes = if patterns.len() > 1 {
+        patterns
+            .iter()
+            .map(|pattern| Ok(search_builder.build(pattern)?.0))
+            .collect::<Result<Vec<_>, wahgex::BuildError>>()
+            .map_err(|err| err.to_string())?
+    } else {
+        Vec::new()
+    };
 
+    let size_report = inspect::size_report(&bytecode).map_err(|err| err.to_string())?;
+    let table_size = size_report
+        .data_segments
+        .iter()
+        .map(|segment| segment.size)
+        .sum();
     let wasm_bytes = bytecode.as_ref();
     let wat_string = print_bytes(wasm_bytes).map_err(|err| err.to_string())?;
 
-    let nfa = context.nfa;
+    let nfa = context
+        .nfa
+        .expect("contexts built from patterns have an NFA");
+    let inspection = inspect::inspect(&nfa, context.config).map_err(|err| err.to_string())?;
 
     let result = CompileResult {
         wasm_bytes: wasm_bytes.into(),
         module_size: wasm_bytes.len(),
+        compile_ms,
+        table_size,
         states: nfa.states().len(),
         pattern_len: nfa.pattern_len(),
         has_capture: nfa.has_capture(),
@@ -45,6 +125,20 @@ pub fn compile(regex: String) -> Result<CompileResult, String> {
         lookset_any: format!("{:?}", nfa.look_set_any()),
         lookset_prefix_any: format!("{:?}", nfa.look_set_prefix_any()),
         wat_string,
+        effective_config: options.effective_config(),
+        nfa_dot: inspection.to_dot(),
+        size_report: size_report.to_string(),
+        patterns,
+        options: *options,
+        bytecode,
+        search_bytecode,
+        pattern_bytecodes,
+        regexes: None,
+        tracer: None,
+        inspection,
+        look_matcher: nfa.look_matcher().clone(),
+        look_set_any: nfa.look_set_any(),
+        reference: Reference::new(&nfa)?,
     };
 
     Ok(result)
@@ -52,6 +146,141 @@ pub fn compile(regex: String) -> Result<CompileResult, String> {
 
 #[wasm_bindgen]
 impl CompileResult {
+    /// Returns the IDs of the patterns that match the haystack.
+    pub fn matching_patterns(&mut self, haystack: &str) -> Result<Vec<u32>, String> {
+        let (regex, pattern_regexes) = self.regexes()?;
+
+        let is_match = regex
+            .try_is_match(Input::new(haystack))
+            .map_err(|err| err.to_string())?;
+        let pattern_ids = if pattern_regexes.is_empty() {
+            if is_match { vec![0] } else { vec![] }
+        } else {
+            let mut pattern_ids = Vec::new();
+            for (pattern_id, regex) in (0..).zip(pattern_regexes.iter_mut()) {
+                if regex
+                    .try_is_match(Input::new(haystack))
+                    .map_err(|err| err.to_string())?
+                {
+                    pattern_ids.push(pattern_id);
+                }
+            }
+            pattern_ids
+        };
+        if pattern_ids.is_empty() == is_match {
+            return Err(format!(
+                "the module reported {}, but the patterns {pattern_ids:?} matched on their own",
+                if is_match { "a match" } else { "no match" }
+            ));
+        }
+        Ok(pattern_ids)
+    }
+
+    /// Searches the haystack the given number of times with the module, a
+    /// JavaScript `RegExp`, and `regex-automata`, and times each of them.
+    ///
+    /// Only `is_match` is timed, since that is the only search that the module
+    /// implements. The `RegExp` is given the patterns as is, so it fails for
+    /// patterns that are not also valid JavaScript syntax.
+    pub fn benchmark(
+        &mut self,
+        haystack: &str,
+        iterations: u32,
+    ) -> Result<Vec<BenchResult>, String> {
+        let mut results = Vec::new();
+
+        let (regex, _) = self.regexes()?;
+        results.push(BenchResult::run("wahgex", iterations, || {
+            regex
+                .try_is_match(Input::new(haystack))
+                .map_err(|err| err.to_string())
+        }));
+
+        results.push(match bench::js_regexp(&self.patterns, &self.options) {
+            Ok(regexp) => BenchResult::run("RegExp", iterations, || Ok(regexp.test(haystack))),
+            Err(err) => BenchResult::failed("RegExp", err),
+        });
+
+        let meta = regex_automata::meta::Regex::builder()
+            .configure(self.options.meta_config())
+            .syntax(self.options.syntax_config())
+            .build_many(&self.patterns);
+        results.push(match meta {
+            Ok(meta) => {
+                BenchResult::run("regex-automata", iterations, || Ok(meta.is_match(haystack)))
+            },
+            Err(err) => BenchResult::failed("regex-automata", err.to_string()),
+        });
+
+        Ok(results)
+    }
+
+    /// Searches the whole haystack, or each of its lines on their own, and
+    /// compares the patterns that matched against `regex-automata`.
+    ///
+    /// Lines are split on `\n`, and a trailing `\r` is not searched.
+    pub fn compare(&mut self, haystack: &str, per_line: bool) -> Result<Vec<SearchResult>, String> {
+        let parts = if per_line {
+            compare::lines(haystack).collect()
+        } else {
+            vec![(0, haystack)]
+        };
+        parts
+            .into_iter()
+            .map(|(start, part)| {
+                let pattern_ids = self.matching_patterns(part)?;
+                Ok(SearchResult::new(
+                    &self.reference,
+                    haystack,
+                    start,
+                    part,
+                    pattern_ids,
+                ))
+            })
+            .collect()
+    }
+
+    /// Searches the haystack one offset at a time, and returns the active NFA
+    /// states and the outcome of every assertion at each offset that the
+    /// search visited.
+    ///
+    /// The search stops at the first offset where a match is found.
+    pub fn debug(&mut self, haystack: &str) -> Result<Vec<DebugStep>, String> {
+        if self.tracer.is_none() {
+            let (bytecode, _) = self
+                .options
+                .search_builder()
+                .configure(Config::new().trace(true))
+                .build_many(&self.patterns)
+                .map_err(|err| err.to_string())?;
+            self.tracer = Some(Regex::new(&bytecode).map_err(|err| format!("{err:?}"))?);
+        }
+        let tracer = self.tracer.as_mut().unwrap();
+
+        tracer
+            .try_is_match(Input::new(haystack))
+            .map_err(|err| err.to_string())?;
+        let steps = tracer
+            .trace()
+            .iter()
+            .map(|step| {
+                DebugStep::new(
+                    step,
+                    haystack.as_bytes(),
+                    &self.inspection,
+                    &self.look_matcher,
+                    self.look_set_any,
+                )
+            })
+            .collect();
+        Ok(steps)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn patterns(&self) -> Vec<String> {
+        self.patterns.clone()
+    }
+
     #[wasm_bindgen(getter)]
     pub fn wasm_bytes(&self) -> Box<[u8]> {
         self.wasm_bytes.clone()
@@ -62,6 +291,19 @@ impl CompileResult {
         self.module_size
     }
 
+    /// The time taken to compile the module, in milliseconds.
+    #[wasm_bindgen(getter)]
+    pub fn compile_ms(&self) -> f64 {
+        self.compile_ms
+    }
+
+    /// The total size of the data segments, which hold the lookup tables of
+    /// the states.
+    #[wasm_bindgen(getter)]
+    pub fn table_size(&self) -> usize {
+        self.table_size
+    }
+
     #[wasm_bindgen(getter)]
     pub fn states(&self) -> usize {
         self.states
@@ -106,4 +348,40 @@ impl CompileResult {
     pub fn wat_string(&self) -> String {
         self.wat_string.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn effective_config(&self) -> String {
+        self.effective_config.clone()
+    }
+
+    /// The NFA as a Graphviz DOT graph, annotated with the functions and
+    /// lookup tables generated for each state.
+    #[wasm_bindgen(getter)]
+    pub fn nfa_dot(&self) -> String {
+        self.nfa_dot.clone()
+    }
+
+    /// The size of each section, function, and data segment of the module.
+    #[wasm_bindgen(getter)]
+    pub fn size_report(&self) -> String {
+        self.size_report.clone()
+    }
+}
+
+impl CompileResult {
+    /// Returns the instantiated modules, creating them on the first call.
+    fn regexes(&mut self) -> Result<&mut (Regex, Vec<Regex>), String> {
+        if self.regexes.is_none() {
+            let bytecode = self.search_bytecode.as_ref().unwrap_or(&self.bytecode);
+            let regex = Regex::new(bytecode).map_err(|err| format!("{err:?}"))?;
+            let pattern_regexes = self
+                .pattern_bytecodes
+                .iter()
+                .map(Regex::new)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("{err:?}"))?;
+            self.regexes = Some((regex, pattern_regexes));
+        }
+        Ok(self.regexes.as_mut().unwrap())
+    }
 }
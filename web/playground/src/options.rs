@@ -0,0 +1,221 @@
+use wahgex::{Builder, Config, LookMatcher, RegexNFAConfig, RegexSyntaxConfig};
+use wasm_bindgen::prelude::*;
+
+/// The toggles of the playground, which are mapped onto the syntax, NFA, and
+/// module configuration of the [`Builder`].
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct CompileOptions {
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    pub crlf: bool,
+    pub line_terminator: u8,
+    pub swap_greed: bool,
+    pub ignore_whitespace: bool,
+    pub unicode: bool,
+    pub utf8_syntax: bool,
+    pub nest_limit: u32,
+    pub octal: bool,
+    pub utf8_nfa: bool,
+    pub look_line_terminator: u8,
+    pub shrink: bool,
+    pub include_names: bool,
+    pub compact_data_section: bool,
+    pub step_counter: bool,
+    pub max_haystack_len: Option<usize>,
+    pub include_metadata: bool,
+    pub include_patterns: bool,
+    pub wasi_grep: bool,
+    pub trace: bool,
+    pub inline_epsilon_closure_threshold: usize,
+    pub branch_hints: bool,
+}
+
+#[wasm_bindgen]
+impl CompileOptions {
+    /// Creates the default options, which include a name section so that the
+    /// text format is readable.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let syntax = RegexSyntaxConfig::new();
+        let nfa = RegexNFAConfig::new();
+        let config = Config::new();
+        Self {
+            case_insensitive: syntax.get_case_insensitive(),
+            multi_line: syntax.get_multi_line(),
+            dot_matches_new_line: syntax.get_dot_matches_new_line(),
+            crlf: syntax.get_crlf(),
+            line_terminator: syntax.get_line_terminator(),
+            swap_greed: syntax.get_swap_greed(),
+            ignore_whitespace: syntax.get_ignore_whitespace(),
+            unicode: syntax.get_unicode(),
+            utf8_syntax: syntax.get_utf8(),
+            nest_limit: syntax.get_nest_limit(),
+            octal: syntax.get_octal(),
+            utf8_nfa: nfa.get_utf8(),
+            look_line_terminator: nfa.get_look_matcher().get_line_terminator(),
+            shrink: false,
+            include_names: true,
+            compact_data_section: false,
+            step_counter: config.get_step_counter(),
+            max_haystack_len: config.get_max_haystack_len(),
+            include_metadata: config.get_include_metadata(),
+            include_patterns: config.get_include_patterns(),
+            wasi_grep: config.get_wasi_grep(),
+            trace: config.get_trace(),
+            inline_epsilon_closure_threshold: config.get_inline_epsilon_closure_threshold(),
+            branch_hints: config.get_branch_hints(),
+        }
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompileOptions {
+    pub(crate) fn syntax_config(&self) -> RegexSyntaxConfig {
+        RegexSyntaxConfig::new()
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .crlf(self.crlf)
+            .line_terminator(self.line_terminator)
+            .swap_greed(self.swap_greed)
+            .ignore_whitespace(self.ignore_whitespace)
+            .unicode(self.unicode)
+            .utf8(self.utf8_syntax)
+            .nest_limit(self.nest_limit)
+            .octal(self.octal)
+    }
+
+    pub(crate) fn nfa_config(&self) -> RegexNFAConfig {
+        let mut look_matcher = LookMatcher::new();
+        look_matcher.set_line_terminator(self.look_line_terminator);
+        RegexNFAConfig::new()
+            .utf8(self.utf8_nfa)
+            .look_matcher(look_matcher)
+            .shrink(self.shrink)
+    }
+
+    /// Returns the options that `regex-automata`'s meta regex engine accepts,
+    /// which include those of the NFA.
+    pub(crate) fn meta_config(&self) -> regex_automata::meta::Config {
+        regex_automata::meta::Config::new()
+            .utf8_empty(self.utf8_nfa)
+            .line_terminator(self.look_line_terminator)
+    }
+
+    fn config(&self) -> Config {
+        let config = Config::new()
+            .include_names(self.include_names)
+            .compact_data_section(self.compact_data_section)
+            .step_counter(self.step_counter)
+            .include_metadata(self.include_metadata)
+            .include_patterns(self.include_patterns)
+            .wasi_grep(self.wasi_grep)
+            .trace(self.trace)
+            .inline_epsilon_closure_threshold(self.inline_epsilon_closure_threshold)
+            .branch_hints(self.branch_hints);
+        match self.max_haystack_len {
+            Some(max_haystack_len) => config.max_haystack_len(max_haystack_len),
+            None => config,
+        }
+    }
+
+    pub(crate) fn builder(&self) -> Builder {
+        let mut builder = Builder::new();
+        builder
+            .configure(self.config())
+            .syntax(self.syntax_config())
+            .thompson(self.nfa_config());
+        builder
+    }
+
+    /// Returns a builder for the modules that the playground searches, which
+    /// leave out the grep command since the playground can't provide its WASI
+    /// imports.
+    pub(crate) fn search_builder(&self) -> Builder {
+        let mut builder = self.builder();
+        builder.configure(Config::new().wasi_grep(false));
+        builder
+    }
+
+    /// Returns a line for every option, with its value after applying the
+    /// defaults.
+    pub(crate) fn effective_config(&self) -> String {
+        let syntax = self.syntax_config();
+        let nfa = self.nfa_config();
+        let config = self.config();
+        [
+            (
+                "syntax.case_insensitive",
+                syntax.get_case_insensitive().to_string(),
+            ),
+            ("syntax.multi_line", syntax.get_multi_line().to_string()),
+            (
+                "syntax.dot_matches_new_line",
+                syntax.get_dot_matches_new_line().to_string(),
+            ),
+            ("syntax.crlf", syntax.get_crlf().to_string()),
+            (
+                "syntax.line_terminator",
+                format!("{:?}", char::from(syntax.get_line_terminator())),
+            ),
+            ("syntax.swap_greed", syntax.get_swap_greed().to_string()),
+            (
+                "syntax.ignore_whitespace",
+                syntax.get_ignore_whitespace().to_string(),
+            ),
+            ("syntax.unicode", syntax.get_unicode().to_string()),
+            ("syntax.utf8", syntax.get_utf8().to_string()),
+            ("syntax.nest_limit", syntax.get_nest_limit().to_string()),
+            ("syntax.octal", syntax.get_octal().to_string()),
+            ("nfa.utf8", nfa.get_utf8().to_string()),
+            (
+                "nfa.look_line_terminator",
+                format!(
+                    "{:?}",
+                    char::from(nfa.get_look_matcher().get_line_terminator())
+                ),
+            ),
+            ("nfa.shrink", nfa.get_shrink().to_string()),
+            (
+                "config.include_names",
+                config.get_include_names().to_string(),
+            ),
+            (
+                "config.compact_data_section",
+                config.get_compact_data_section().to_string(),
+            ),
+            ("config.step_counter", config.get_step_counter().to_string()),
+            (
+                "config.max_haystack_len",
+                config
+                    .get_max_haystack_len()
+                    .map_or_else(|| "none".into(), |len| len.to_string()),
+            ),
+            (
+                "config.include_metadata",
+                config.get_include_metadata().to_string(),
+            ),
+            (
+                "config.include_patterns",
+                config.get_include_patterns().to_string(),
+            ),
+            ("config.wasi_grep", config.get_wasi_grep().to_string()),
+            ("config.trace", config.get_trace().to_string()),
+            (
+                "config.inline_epsilon_closure_threshold",
+                config.get_inline_epsilon_closure_threshold().to_string(),
+            ),
+            ("config.branch_hints", config.get_branch_hints().to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| format!("{name}: {value}\n"))
+        .collect()
+    }
+}
@@ -1,7 +1,63 @@
-import init, { compile } from "./playground/wahgex_web_playground.js";
+import init, {
+    compile,
+    CompileOptions,
+} from "./playground/wahgex_web_playground.js";
 
 await init();
 
+// Must match `wahgex::abi::VERSION`, the ABI implemented by compiled modules
+const ABI_VERSION = 2;
+
+// The fields of `CompileOptions`, grouped by the configuration they belong to,
+// along with their label and the kind of input used to edit them
+const OPTION_GROUPS = {
+    syntax: [
+        ["case_insensitive", "Case insensitive"],
+        ["multi_line", "Multi-line"],
+        ["dot_matches_new_line", "Dot matches new line"],
+        ["crlf", "CRLF"],
+        ["line_terminator", "Line terminator (byte)", "byte"],
+        ["swap_greed", "Swap greed"],
+        ["ignore_whitespace", "Ignore whitespace"],
+        ["unicode", "Unicode"],
+        ["utf8_syntax", "UTF-8"],
+        ["nest_limit", "Nest limit", "number"],
+        ["octal", "Octal"],
+    ],
+    nfa: [
+        ["utf8_nfa", "UTF-8"],
+        ["look_line_terminator", "Look line terminator (byte)", "byte"],
+        ["shrink", "Shrink"],
+    ],
+    module: [
+        ["include_names", "Include names"],
+        ["compact_data_section", "Compact data section"],
+        ["step_counter", "Step counter"],
+        ["max_haystack_len", "Max haystack length", "optionalNumber"],
+        ["include_metadata", "Include metadata"],
+        ["include_patterns", "Include patterns"],
+        ["wasi_grep", "WASI grep command"],
+        ["trace", "Trace"],
+        [
+            "inline_epsilon_closure_threshold",
+            "Inline epsilon closure threshold",
+            "number",
+        ],
+        ["branch_hints", "Branch hints"],
+    ],
+};
+const OPTIONS = Object.entries(OPTION_GROUPS).flatMap(([group, options]) =>
+    options.map(([name, label, kind = "bool"]) => ({
+        name,
+        group,
+        label,
+        kind,
+    })),
+);
+const DEFAULT_OPTIONS = new CompileOptions();
+// Graphviz compiled to WASM, which is only loaded once a graph is shown
+const VIZ_URL = "https://cdn.jsdelivr.net/npm/@viz-js/viz@3/+esm";
+
 // Copied from https://codepen.io/wonism/pen/dXgrxE
 function debounce(cb, interval, immediate) {
     var timeout;
@@ -23,16 +79,41 @@ function debounce(cb, interval, immediate) {
     };
 }
 
+// Returns an import object that satisfies every import of the module.
+//
+// The module is only instantiated to check its ABI version, so the `trace`
+// import is ignored, and the WASI imports of the grep command throw.
+function stubImports(module) {
+    const imports = {};
+    for (const entry of WebAssembly.Module.imports(module)) {
+        const { module: moduleName, name, kind } = entry;
+        if (kind !== "function") {
+            throw new Error(
+                `Unexpected ${kind} import [${moduleName}.${name}]`,
+            );
+        }
+        imports[moduleName] ??= {};
+        imports[moduleName][name] =
+            moduleName === "wahgex" && name === "trace"
+                ? () => {}
+                : () => {
+                      throw new Error(
+                          `[${moduleName}.${name}] is not available in the playground`,
+                      );
+                  };
+    }
+    return imports;
+}
+
 class RegexModule {
-    static async create(pattern) {
+    static async create(pattern, options) {
         if (pattern == null || !(typeof pattern === "string")) {
             return null;
         }
 
         let compileResult;
         try {
-            // The compile function now returns the CompileResult object
-            compileResult = compile(pattern);
+            compileResult = compile(pattern, options);
         } catch (err) {
             if (typeof err === "string") {
                 throw Error(err);
@@ -41,9 +122,17 @@ class RegexModule {
             }
         }
 
-        // Extract the wasm_bytes from the CompileResult
-        const moduleBytes = compileResult.wasm_bytes;
-        const { module, instance } = await WebAssembly.instantiate(moduleBytes);
+        const module = await WebAssembly.compile(compileResult.wasm_bytes);
+        const instance = await WebAssembly.instantiate(
+            module,
+            stubImports(module),
+        );
+        const abiVersion = instance.exports.abi_version?.value;
+        if (abiVersion !== ABI_VERSION) {
+            throw new Error(
+                `Module implements ABI version [${abiVersion}], but version [${ABI_VERSION}] is required`,
+            );
+        }
 
         // Pass the compileResult object to the constructor
         return new RegexModule(pattern, compileResult, module, instance);
@@ -54,32 +143,6 @@ class RegexModule {
         this.compileResult = compileResult;
         this.module = module;
         this.instance = instance;
-        this.encoder = new TextEncoder("utf-8");
-    }
-
-    isMatch(haystack) {
-        if (haystack === null || !(typeof haystack === "string")) {
-            return false;
-        }
-
-        this.instance.exports.prepare_input(BigInt(haystack.length));
-        const view = new Uint8Array(this.instance.exports.haystack.buffer);
-        this.encoder.encodeInto(haystack, view);
-
-        // anchored, anchored_pattern, span_start, span_end, haystack_len
-        const anchored = 0;
-        const anchoredPattern = 0;
-        const spanStart = BigInt(0);
-        const spanEnd = BigInt(haystack.length);
-        const haystackLen = BigInt(haystack.length);
-        const result = this.instance.exports.is_match(
-            anchored,
-            anchoredPattern,
-            spanStart,
-            spanEnd,
-            haystackLen,
-        );
-        return Boolean(result);
     }
 }
 
@@ -99,21 +162,141 @@ const shareRegexButton = getElementById("shareRegexButton");
 const regexInput = getElementById("regexInput");
 const haystackText = getElementById("haystackText");
 const regexErrorOutput = getElementById("regexError");
+const perLineInput = getElementById("perLineInput");
 const searchResultOutput = getElementById("searchResult");
+const searchDetails = getElementById("searchDetails");
+const highlightedHaystack = getElementById("highlightedHaystack");
+const searchResultsBody = getElementById("searchResultsBody");
 const statsOutput = getElementById("statsOutput");
 const statsOutputList = getElementById("statsOutputList");
 const watOutput = getElementById("watOutput");
 const watOutputPre = getElementById("watOutputPre");
+const nfaOutput = getElementById("nfaOutput");
+const nfaOutputDetails = getElementById("nfaOutputDetails");
+const nfaGraph = getElementById("nfaGraph");
+const nfaDotPre = getElementById("nfaDotPre");
+const debugOutput = getElementById("debugOutput");
+const debugOutputDetails = getElementById("debugOutputDetails");
+const debugPreviousButton = getElementById("debugPreviousButton");
+const debugStepInput = getElementById("debugStepInput");
+const debugNextButton = getElementById("debugNextButton");
+const debugPosition = getElementById("debugPosition");
+const debugError = getElementById("debugError");
+const debugStepList = getElementById("debugStepList");
+const debugGraph = getElementById("debugGraph");
+const debugDotPre = getElementById("debugDotPre");
+const benchOutput = getElementById("benchOutput");
+const benchHaystackText = getElementById("benchHaystackText");
+const benchFileInput = getElementById("benchFileInput");
+const benchIterationsInput = getElementById("benchIterationsInput");
+const benchRepeatInput = getElementById("benchRepeatInput");
+const benchRunButton = getElementById("benchRunButton");
+const benchStatus = getElementById("benchStatus");
+const benchResults = getElementById("benchResults");
+const benchResultsBody = getElementById("benchResultsBody");
+const benchChart = getElementById("benchChart");
 const shareRegexOutput = getElementById("shareRegex");
+const optionsPanel = getElementById("optionsPanel");
+const resetOptionsButton = getElementById("resetOptionsButton");
+const effectiveConfigPre = getElementById("effectiveConfigPre");
+
+function optionInputId(option) {
+    return `option-${option.name}`;
+}
+
+// Fills the options panel with an input for each option, set to its default
+function renderOptions() {
+    for (const option of OPTIONS) {
+        const fieldset = getElementById(`${option.group}Options`);
+        const label = document.createElement("label");
+        const input = document.createElement("input");
+        input.id = optionInputId(option);
+        input.name = option.name;
+        if (option.kind === "bool") {
+            input.type = "checkbox";
+        } else {
+            input.type = "number";
+            input.min = "0";
+            if (option.kind === "byte") {
+                input.max = "255";
+            }
+        }
+        label.append(input, ` ${option.label}`);
+        fieldset.appendChild(label);
+    }
+    setOptions(new URLSearchParams());
+}
+
+// Sets each input to the value in the URL parameters, or to its default
+function setOptions(params) {
+    for (const option of OPTIONS) {
+        const input = getElementById(optionInputId(option));
+        const value = params.has(option.name)
+            ? params.get(option.name)
+            : String(DEFAULT_OPTIONS[option.name] ?? "");
+        if (option.kind === "bool") {
+            input.checked = value === "true";
+        } else {
+            input.value = value;
+        }
+    }
+}
+
+// Returns the value of the option's input, or `undefined` if it is empty
+function optionValue(option) {
+    const input = getElementById(optionInputId(option));
+    if (option.kind === "bool") {
+        return input.checked;
+    }
+    if (input.value === "") {
+        return option.kind === "optionalNumber"
+            ? undefined
+            : DEFAULT_OPTIONS[option.name];
+    }
+    const number = Math.trunc(Number(input.value));
+    if (!Number.isFinite(number)) {
+        return DEFAULT_OPTIONS[option.name];
+    }
+    const value = Math.max(0, number);
+    return option.kind === "byte" ? Math.min(value, 255) : value;
+}
+
+function readOptions() {
+    const options = new CompileOptions();
+    for (const option of OPTIONS) {
+        options[option.name] = optionValue(option);
+    }
+    return options;
+}
+
+// Returns the URL parameters for the options that differ from their defaults
+function optionsParams() {
+    const params = new URLSearchParams();
+    for (const option of OPTIONS) {
+        const value = optionValue(option);
+        if (value !== DEFAULT_OPTIONS[option.name]) {
+            params.set(option.name, String(value ?? ""));
+        }
+    }
+    return params;
+}
 
 function clearMessages() {
     regexErrorOutput.textContent = "";
-    searchResultOutput.textContent = "";
+    clearSearchResults();
     statsOutput.style.display = "none";
     statsOutputList.innerHTML = "";
     watOutput.style.display = "none";
     watOutputPre.textContent = "";
+    nfaOutput.style.display = "none";
+    nfaGraph.replaceChildren();
+    nfaDotPre.textContent = "";
+    debugOutput.style.display = "none";
+    clearDebugger();
+    benchOutput.style.display = "none";
+    clearBenchmark();
     shareRegexOutput.textContent = "";
+    effectiveConfigPre.textContent = "";
 }
 
 // Helper function to toggle button states and clear module data
@@ -126,25 +309,156 @@ function resetModuleState() {
     statsOutputList.innerHTML = "";
     watOutput.style.display = "none";
     watOutputPre.textContent = "";
+    nfaOutput.style.display = "none";
+    nfaGraph.replaceChildren();
+    nfaDotPre.textContent = "";
+    debugOutput.style.display = "none";
+    clearDebugger();
+    benchOutput.style.display = "none";
+    clearBenchmark();
     shareRegexOutput.textContent = "";
 }
 
+function clearSearchResults() {
+    searchResultOutput.replaceChildren();
+    searchDetails.style.display = "none";
+    highlightedHaystack.replaceChildren();
+    searchResultsBody.replaceChildren();
+}
+
+// Searches the haystack, or each of its lines, and copies the results into
+// plain objects, since the ones returned by the playground own its memory
+function compareSearch(compileResult, haystack, perLine) {
+    return compileResult.compare(haystack, perLine).map((result) => {
+        const matches = result.matches.map((span) => {
+            const match = {
+                pattern: span.pattern,
+                start: span.start,
+                end: span.end,
+            };
+            span.free();
+            return match;
+        });
+        const copy = {
+            start: result.start,
+            end: result.end,
+            patternIds: Array.from(result.pattern_ids),
+            expectedPatternIds: Array.from(result.expected_pattern_ids),
+            matches,
+            agrees: result.agrees(),
+        };
+        result.free();
+        return copy;
+    });
+}
+
+// Describes the patterns that matched, which are only listed if there is more
+// than one pattern
+function describePatterns(patternIds, patternLen) {
+    if (patternIds.length === 0) {
+        return "no match";
+    }
+    return patternLen > 1 ? `patterns ${patternIds.join(", ")}` : "match";
+}
+
+// Shows the haystack with every match of `regex-automata` highlighted, and
+// outlines the parts where the module found different patterns to match
+function displayHighlights(haystack, results) {
+    const fragments = [];
+    let offset = 0;
+    for (const result of results) {
+        fragments.push(haystack.slice(offset, result.start));
+        const part = [];
+        offset = result.start;
+        for (const match of result.matches) {
+            part.push(haystack.slice(offset, match.start));
+            const mark = document.createElement("mark");
+            mark.title = `Pattern ${match.pattern}`;
+            mark.textContent = haystack.slice(match.start, match.end);
+            part.push(mark);
+            offset = match.end;
+        }
+        part.push(haystack.slice(offset, result.end));
+        offset = result.end;
+
+        if (result.agrees) {
+            fragments.push(...part);
+        } else {
+            const span = document.createElement("span");
+            span.className = "disagrees";
+            span.title = "The module disagrees with regex-automata";
+            span.append(...part);
+            fragments.push(span);
+        }
+    }
+    fragments.push(haystack.slice(offset));
+    highlightedHaystack.replaceChildren(...fragments);
+}
+
+function displayResultsTable(results, perLine, patternLen) {
+    const rows = results.map((result, index) => {
+        const row = document.createElement("tr");
+        if (!result.agrees) {
+            row.className = "disagrees";
+        }
+        for (const text of [
+            perLine ? `Line ${index + 1}` : "Haystack",
+            describePatterns(result.patternIds, patternLen),
+            describePatterns(result.expectedPatternIds, patternLen),
+            result.agrees ? "yes" : "no",
+        ]) {
+            const cell = document.createElement("td");
+            cell.textContent = text;
+            row.appendChild(cell);
+        }
+        return row;
+    });
+    searchResultsBody.replaceChildren(...rows);
+}
+
 function performSearch() {
-    searchResultOutput.textContent = "";
+    clearSearchResults();
     if (currentModule === null) {
         return;
     }
 
     const haystack = haystackText.value;
     if (haystack === null || !(typeof haystack === "string")) {
-        searchResultOutput.textContent = "";
         return;
     }
 
-    const result = currentModule.isMatch(haystack);
-    searchResultOutput.textContent = result
-        ? "Match found!"
-        : "No match found.";
+    const compileResult = currentModule.compileResult;
+    const perLine = perLineInput.checked;
+    let results;
+    try {
+        results = compareSearch(compileResult, haystack, perLine);
+    } catch (err) {
+        searchResultOutput.textContent = `Search error: ${err.message ?? err}`;
+        return;
+    }
+
+    const matched = results.filter((result) => result.patternIds.length > 0);
+    const summary = perLine
+        ? `Matched ${matched.length} of ${results.length} lines.`
+        : matched.length > 0
+          ? "Match found!"
+          : "No match found.";
+    const disagreements = results.filter((result) => !result.agrees).length;
+    if (disagreements > 0) {
+        const warning = document.createElement("span");
+        warning.className = "disagrees";
+        warning.textContent = perLine
+            ? ` The module disagrees with regex-automata on ${disagreements} of ${results.length} lines.`
+            : " The module disagrees with regex-automata.";
+        searchResultOutput.replaceChildren(summary, warning);
+    } else {
+        searchResultOutput.replaceChildren(summary);
+    }
+
+    // Undo the `display: none;` style override
+    searchDetails.style.display = "";
+    displayHighlights(haystack, results);
+    displayResultsTable(results, perLine, compileResult.pattern_len);
 }
 
 function displayStats(compileResult) {
@@ -193,6 +507,26 @@ function displayStats(compileResult) {
             compileResult.lookset_prefix_any,
         ),
     );
+    statsOutputList.appendChild(
+        createStatItem(
+            "Compile time",
+            `${compileResult.compile_ms.toFixed(2)} ms`,
+        ),
+    );
+    statsOutputList.appendChild(
+        createStatItem("Table size", `${compileResult.table_size} bytes`),
+    );
+
+    // The size of each section, function, and data segment
+    const sizeReport = document.createElement("li");
+    const sizeReportDetails = document.createElement("details");
+    const sizeReportSummary = document.createElement("summary");
+    sizeReportSummary.textContent = "Size report";
+    const sizeReportPre = document.createElement("pre");
+    sizeReportPre.textContent = compileResult.size_report;
+    sizeReportDetails.append(sizeReportSummary, sizeReportPre);
+    sizeReport.appendChild(sizeReportDetails);
+    statsOutputList.appendChild(sizeReport);
 }
 
 function displayWat(compileResult) {
@@ -209,6 +543,236 @@ function displayWat(compileResult) {
     watOutputPre.textContent = compileResult.wat_string;
 }
 
+let vizInstance = null;
+
+// Renders the DOT graph as an SVG inside of the container, leaving only a
+// message if Graphviz can't be loaded
+async function renderDot(container, dot) {
+    try {
+        vizInstance ??= import(VIZ_URL).then((viz) => viz.instance());
+        const viz = await vizInstance;
+        container.replaceChildren(viz.renderSVGElement(dot));
+    } catch (err) {
+        vizInstance = null;
+        container.replaceChildren(
+            `Unable to render the graph, see the DOT source instead: ${err.message}`,
+        );
+    }
+}
+
+function displayNfa(compileResult) {
+    nfaGraph.replaceChildren();
+    nfaDotPre.textContent = "";
+    nfaOutput.style.display = "none";
+
+    if (!compileResult) {
+        return;
+    }
+
+    // Undo the `display: none;` style override
+    nfaOutput.style.display = "";
+    nfaDotPre.textContent = compileResult.nfa_dot;
+    // Large graphs are slow to lay out, so only render them when visible
+    if (nfaOutputDetails.open) {
+        renderDot(nfaGraph, compileResult.nfa_dot);
+    }
+}
+
+// The steps of the last debugger run, which own memory of the playground
+let debugSteps = [];
+
+function clearDebugger() {
+    for (const step of debugSteps) {
+        step.free();
+    }
+    debugSteps = [];
+    debugPreviousButton.disabled = true;
+    debugStepInput.disabled = true;
+    debugNextButton.disabled = true;
+    debugError.textContent = "";
+    debugPosition.textContent = "";
+    debugStepList.replaceChildren();
+    debugGraph.replaceChildren();
+    debugDotPre.textContent = "";
+}
+
+// Describes the byte at the offset of a step, which is `undefined` at the end
+// of the haystack
+function describeByte(byte) {
+    if (byte === undefined) {
+        return "end of haystack";
+    }
+    const hex = `0x${byte.toString(16).padStart(2, "0")}`;
+    return byte >= 0x20 && byte < 0x7f
+        ? `'${String.fromCharCode(byte)}' (${hex})`
+        : hex;
+}
+
+function showDebugStep(index) {
+    debugPreviousButton.disabled = index <= 0;
+    debugNextButton.disabled = index >= debugSteps.length - 1;
+    debugStepInput.disabled = debugSteps.length === 0;
+    if (debugSteps.length === 0) {
+        debugPosition.textContent = "The search visited no offsets.";
+        return;
+    }
+
+    const step = debugSteps[index];
+    debugStepInput.value = String(index);
+    debugPosition.textContent = `Step ${index + 1} of ${debugSteps.length}`;
+
+    const createStepItem = (label, values) => {
+        const li = document.createElement("li");
+        const strong = document.createElement("strong");
+        strong.textContent = `${label}:`;
+        li.append(strong, ` ${values.length > 0 ? values.join(", ") : "none"}`);
+        return li;
+    };
+    debugStepList.replaceChildren(
+        createStepItem("Offset", [
+            `${step.at_offset} (${describeByte(step.byte)})`,
+        ]),
+        createStepItem(
+            "Active states",
+            Array.from(step.states, (state) => `s${state}`),
+        ),
+        createStepItem("Assertions passed", step.looks_passed),
+        createStepItem("Assertions failed", step.looks_failed),
+    );
+    debugDotPre.textContent = step.nfa_dot;
+    renderDot(debugGraph, step.nfa_dot);
+}
+
+// Runs the traced search over the haystack, and shows its first step
+function runDebugger() {
+    clearDebugger();
+    if (currentModule === null || !debugOutputDetails.open) {
+        return;
+    }
+
+    try {
+        debugSteps = currentModule.compileResult.debug(haystackText.value);
+    } catch (err) {
+        debugError.textContent = `Debugger error: ${err.message ?? err}`;
+        return;
+    }
+    debugStepInput.max = String(Math.max(debugSteps.length - 1, 0));
+    showDebugStep(0);
+}
+
+function displayDebugger(compileResult) {
+    clearDebugger();
+    debugOutput.style.display = "none";
+
+    if (!compileResult) {
+        return;
+    }
+
+    // Undo the `display: none;` style override
+    debugOutput.style.display = "";
+    // Each step renders a graph, so only run the debugger when visible
+    runDebugger();
+}
+
+function clearBenchmark() {
+    benchStatus.textContent = "";
+    benchResults.style.display = "none";
+    benchResultsBody.replaceChildren();
+    benchChart.replaceChildren();
+}
+
+// Returns the value of a number input, or 1 if it isn't a positive number
+function positiveInteger(input) {
+    const number = Math.trunc(Number(input.value));
+    return Number.isFinite(number) ? Math.max(1, number) : 1;
+}
+
+// Copies the benchmark results into plain objects, since the ones returned by
+// the playground own its memory
+function runBenchmark(compileResult, haystack, iterations) {
+    return compileResult.benchmark(haystack, iterations).map((result) => {
+        const copy = {
+            engine: result.engine,
+            iterations: result.iterations,
+            totalMs: result.total_ms,
+            meanMs: result.mean_ms,
+            isMatch: result.is_match,
+            error: result.error,
+        };
+        result.free();
+        return copy;
+    });
+}
+
+function displayBenchmark(results) {
+    const formatMs = (ms) => `${ms.toFixed(4)} ms`;
+    const rows = results.map((result) => {
+        const row = document.createElement("tr");
+        const outcome =
+            result.error ??
+            (result.isMatch === undefined
+                ? "not run"
+                : result.isMatch
+                  ? "match"
+                  : "no match");
+        for (const text of [
+            result.engine,
+            result.error ? "" : formatMs(result.meanMs),
+            result.error ? "" : formatMs(result.totalMs),
+            outcome,
+        ]) {
+            const cell = document.createElement("td");
+            cell.textContent = text;
+            row.appendChild(cell);
+        }
+        return row;
+    });
+    benchResultsBody.replaceChildren(...rows);
+
+    // Engines that failed have no time to show
+    const timed = results.filter((result) => !result.error);
+    const slowest = Math.max(...timed.map((result) => result.meanMs));
+    const bars = timed.map((result) => {
+        const bar = document.createElement("div");
+        bar.className = "bench-bar";
+        const width = slowest > 0 ? (result.meanMs / slowest) * 100 : 0;
+        bar.style.width = `${Math.max(width, 1)}%`;
+        bar.textContent = `${result.engine}: ${formatMs(result.meanMs)}`;
+        return bar;
+    });
+    benchChart.replaceChildren(...bars);
+
+    // Undo the `display: none;` style override
+    benchResults.style.display = "";
+}
+
+function performBenchmark() {
+    clearBenchmark();
+    if (currentModule === null) {
+        return;
+    }
+
+    const compileResult = currentModule.compileResult;
+    const haystack = (benchHaystackText.value || haystackText.value).repeat(
+        positiveInteger(benchRepeatInput),
+    );
+    const iterations = positiveInteger(benchIterationsInput);
+    benchStatus.textContent = "Running...";
+    benchRunButton.disabled = true;
+    // The searches block the page, so let the status show first
+    setTimeout(() => {
+        try {
+            const results = runBenchmark(compileResult, haystack, iterations);
+            benchStatus.textContent = `Searched ${haystack.length} characters ${iterations} times.`;
+            displayBenchmark(results);
+        } catch (err) {
+            benchStatus.textContent = `Benchmark error: ${err.message ?? err}`;
+        } finally {
+            benchRunButton.disabled = false;
+        }
+    });
+}
+
 function processRegexInput(value) {
     clearMessages();
     if (value === null || !(typeof value === "string") || value.length === 0) {
@@ -217,7 +781,8 @@ function processRegexInput(value) {
         return;
     }
 
-    RegexModule.create(value)
+    const options = readOptions();
+    RegexModule.create(value, options)
         .then((module) => {
             currentModule = module;
             searchButton.disabled = false;
@@ -225,6 +790,12 @@ function processRegexInput(value) {
             shareRegexButton.disabled = false;
             displayStats(currentModule.compileResult); // Display stats after successful compilation
             displayWat(currentModule.compileResult); // Display WAT (placeholder) after successful compilation
+            displayNfa(currentModule.compileResult);
+            displayDebugger(currentModule.compileResult);
+            // Undo the `display: none;` style override
+            benchOutput.style.display = "";
+            effectiveConfigPre.textContent =
+                currentModule.compileResult.effective_config;
 
             // Automatically search if haystack is not empty after successful compilation
             if (haystackText.value.length > 0) {
@@ -235,7 +806,8 @@ function processRegexInput(value) {
             resetModuleState();
             clearMessages();
             regexErrorOutput.textContent = `Compilation error: ${err.message}`;
-        });
+        })
+        .finally(() => options.free());
 }
 
 // Event listener for the regex input field
@@ -246,13 +818,73 @@ regexInput.addEventListener(
     }, 500),
 );
 
+nfaOutputDetails.addEventListener("toggle", function () {
+    if (nfaOutputDetails.open && currentModule !== null) {
+        renderDot(nfaGraph, currentModule.compileResult.nfa_dot);
+    }
+});
+
+debugOutputDetails.addEventListener("toggle", function () {
+    if (debugOutputDetails.open) {
+        runDebugger();
+    } else {
+        clearDebugger();
+    }
+});
+
+debugStepInput.addEventListener("input", function () {
+    showDebugStep(Number(debugStepInput.value));
+});
+
+debugPreviousButton.addEventListener("click", function () {
+    showDebugStep(Number(debugStepInput.value) - 1);
+});
+
+debugNextButton.addEventListener("click", function () {
+    showDebugStep(Number(debugStepInput.value) + 1);
+});
+
+benchFileInput.addEventListener("change", async function () {
+    const file = benchFileInput.files[0];
+    if (file === undefined) {
+        return;
+    }
+    try {
+        benchHaystackText.value = await file.text();
+        benchStatus.textContent = `Loaded ${file.name}.`;
+    } catch (err) {
+        benchStatus.textContent = `Failed to read ${file.name}: ${err.message}`;
+    }
+});
+
+benchRunButton.addEventListener("click", performBenchmark);
+
+// Recompile with the new options whenever any of them changes
+optionsPanel.addEventListener(
+    "input",
+    debounce(function (ev) {
+        processRegexInput(regexInput.value);
+    }, 500),
+);
+
+resetOptionsButton.addEventListener("click", function () {
+    setOptions(new URLSearchParams());
+    processRegexInput(regexInput.value);
+});
+
 // Event listener for the share button
 shareRegexButton.addEventListener("click", async function () {
     if (currentModule && currentModule.pattern) {
         const pattern = currentModule.pattern;
         const encodedPattern = encodeURIComponent(pattern);
-        const urlParams = new URLSearchParams(window.location.search);
+        const urlParams = optionsParams();
         urlParams.set("regex", encodedPattern);
+        if (haystackText.value.length > 0) {
+            urlParams.set("haystack", haystackText.value);
+        }
+        if (perLineInput.checked) {
+            urlParams.set("per_line", "true");
+        }
         const shareUrl = `${window.location.origin}${window.location.pathname}?${urlParams.toString()}`;
 
         try {
@@ -298,15 +930,24 @@ haystackText.addEventListener("input", function (ev) {
     if (currentModule !== null) {
         performSearch();
     } else {
-        searchResultOutput.textContent = "";
+        clearSearchResults();
     }
 });
 
+perLineInput.addEventListener("change", performSearch);
+
+haystackText.addEventListener("input", debounce(runDebugger, 500));
+
 searchButton.addEventListener("click", performSearch);
 
 const urlParams = new URLSearchParams(window.location.search);
 const regexFromUrl = urlParams.get("regex");
 
+renderOptions();
+setOptions(urlParams);
+haystackText.value = urlParams.get("haystack") ?? "";
+perLineInput.checked = urlParams.get("per_line") === "true";
+
 if (regexFromUrl) {
     try {
         const decodedRegex = decodeURIComponent(regexFromUrl);